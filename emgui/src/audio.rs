@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use cpal::{SampleFormat, Stream, StreamConfig};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use emulateme::apu::Apu;
+
+// Samples queued ahead of the device, about 100ms at 44.1 kHz.
+const QUEUE_CAPACITY: usize = 4410;
+
+// Plays APU samples on the default output device.
+// The emulation thread pushes samples, the device callback drains them.
+pub struct AudioOutput {
+    sample_rate: u32,
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    _stream: Stream
+}
+
+impl AudioOutput {
+    // None when there is no usable output device, emulation continues silently.
+    pub fn new() -> Option<AudioOutput> {
+        let device = cpal::default_host().default_output_device()?;
+        let supported = device.default_output_config().ok()?;
+
+        if supported.sample_format() != SampleFormat::F32 {
+            println!("Audio disabled, output device does not take f32 samples");
+
+            return None
+        }
+
+        let config: StreamConfig = supported.into();
+        let channels = config.channels as usize;
+
+        let samples = Arc::new(Mutex::new(VecDeque::with_capacity(QUEUE_CAPACITY)));
+        let queue = samples.clone();
+
+        let stream = device.build_output_stream(&config, move |data: &mut [f32], _| {
+            let mut queue = queue.lock().unwrap();
+
+            // Same sample on every channel, silence on underrun.
+            for frame in data.chunks_mut(channels) {
+                frame.fill(queue.pop_front().unwrap_or(0.0));
+            }
+        }, |err| println!("Audio stream error ({err})"), None).ok()?;
+
+        stream.play().ok()?;
+
+        Some(AudioOutput {
+            sample_rate: config.sample_rate.0,
+            samples,
+            _stream: stream
+        })
+    }
+
+    // Call again whenever the APU is replaced, e.g. after restoring a state.
+    pub fn attach(&self, apu: &mut Apu) {
+        apu.collect_samples = true;
+        apu.sample_rate = self.sample_rate;
+    }
+
+    // Waits while the queue is full, which paces emulation to the device.
+    pub fn push(&self, data: &[f32]) {
+        while self.samples.lock().unwrap().len() >= QUEUE_CAPACITY {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        self.samples.lock().unwrap().extend(data);
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use emulateme::renderer::RenderedFrame;
+
+// Bounded queue of frames from the emulation thread to the render thread.
+// When the emulator outpaces presentation, the oldest queued frame is dropped.
+pub struct FrameHandoff {
+    capacity: usize,
+    frames: Mutex<VecDeque<Box<RenderedFrame>>>
+}
+
+impl FrameHandoff {
+    // Returns the number of frames dropped to make room (0 or 1).
+    pub fn push(&self, frame: Box<RenderedFrame>) -> usize {
+        let mut frames = self.frames.lock().unwrap();
+
+        let dropped = if frames.len() >= self.capacity {
+            frames.pop_front();
+
+            1
+        } else {
+            0
+        };
+
+        frames.push_back(frame);
+
+        dropped
+    }
+
+    // Oldest queued frame and whether more are waiting behind it.
+    pub fn pop(&self) -> (Option<Box<RenderedFrame>>, bool) {
+        let mut frames = self.frames.lock().unwrap();
+
+        let frame = frames.pop_front();
+
+        (frame, !frames.is_empty())
+    }
+
+    pub fn new(capacity: usize) -> FrameHandoff {
+        FrameHandoff {
+            capacity: capacity.max(1),
+            frames: Mutex::new(VecDeque::with_capacity(capacity))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u8) -> Box<RenderedFrame> {
+        let mut frame = Box::<RenderedFrame>::default();
+
+        frame.frame[0] = id;
+
+        frame
+    }
+
+    #[test]
+    fn drops_oldest_when_full() {
+        let handoff = FrameHandoff::new(2);
+
+        // The producer runs five frames ahead of the consumer.
+        let dropped: Vec<usize> = (0 .. 5).map(|id| handoff.push(frame(id))).collect();
+
+        assert_eq!(dropped, vec![0, 0, 1, 1, 1]);
+
+        let (first, more) = handoff.pop();
+
+        assert_eq!((first.unwrap().frame[0], more), (3, true));
+
+        let (second, more) = handoff.pop();
+
+        assert_eq!((second.unwrap().frame[0], more), (4, false));
+        assert!(handoff.pop().0.is_none());
+    }
+}
@@ -1,40 +1,85 @@
 use std::{env, fs, thread};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use winit::event::ElementState;
 use winit::keyboard::{KeyCode, PhysicalKey};
 use emulateme::controller::{Controller, ControllerFlags, GenericController, NoController};
 use emulateme::cpu::Cpu;
-use emulateme::renderer::{NES_HEIGHT, NES_WIDTH, RenderAction, RenderedFrame, Renderer};
-use emulateme::rom::parse_rom;
+use emulateme::recorder::Recorder;
+use emulateme::renderer::{NES_HEIGHT, NES_WIDTH, RenderAction, Renderer};
+use emulateme::rom::Rom;
 use emulateme::software::SoftwareRenderer;
-use emulateme::state::CpuState;
+use emulateme::state::{CpuState, StateError};
+use crate::handoff::FrameHandoff;
 use crate::streamer::Streamer;
 use crate::window::WindowDetails;
 
 mod window;
 mod streamer;
+mod handoff;
+#[cfg(feature = "audio")]
+mod audio;
 
 const STATE_FILE: &str = "state.dat";
+const DEFAULT_FRAME_BUFFER: usize = 2;
+// Raw 256x240 RGBA frames, e.g. ffmpeg -f rawvideo -pix_fmt rgba -s 256x240 -r 60 -i recording.rgba
+const RECORD_VIDEO_FILE: &str = "recording.rgba";
+const RECORD_INPUT_FILE: &str = "recording.txt";
 
 #[derive(Clone, Default)]
+struct GuiInput {
+    pending: Arc<Mutex<ControllerFlags>>
+}
+
 struct GuiController {
-    inner: Arc<Mutex<GenericController>>
+    pending: Arc<Mutex<ControllerFlags>>,
+    active: GenericController,
+    // When set, key presses only reach the game once the current frame completes.
+    frame_latched: bool
 }
 
-impl GuiController {
+impl GuiInput {
     fn set(&self, flag: ControllerFlags, value: bool) {
-        let mut state = self.inner.lock().unwrap();
+        let mut state = self.pending.lock().unwrap();
 
         state.set(flag, value)
     }
 }
 
+impl GuiController {
+    fn new(input: &GuiInput, frame_latched: bool) -> GuiController {
+        GuiController {
+            pending: input.pending.clone(),
+            active: GenericController::default(),
+            frame_latched
+        }
+    }
+
+    fn latch(&mut self) {
+        let state = self.pending.lock().unwrap();
+
+        self.active.press(*state)
+    }
+}
+
 impl Controller for GuiController {
     fn read(&mut self, clock: u64) -> u8 {
-        let mut state = self.inner.lock().unwrap();
+        if !self.frame_latched {
+            self.latch()
+        }
 
-        state.read(clock)
+        self.active.read(clock)
+    }
+
+    fn write(&mut self, value: u8) {
+        if !self.frame_latched {
+            self.latch()
+        }
+
+        self.active.write(value)
     }
 }
 
@@ -42,92 +87,186 @@ fn main() {
     let arguments = env::args().collect::<Vec<String>>();
 
     let Some(path) = arguments.get(1) else {
-        panic!("Usage: emgui /path/to/rom.nes")
+        panic!("Usage: emgui /path/to/rom.nes [--immediate-input] [--frame-buffer N]")
     };
 
-    let rom_bytes = fs::read(path).unwrap();
-    let (_, rom) = parse_rom(&rom_bytes).unwrap();
+    let frame_latched = !arguments.iter().any(|x| x == "--immediate-input");
 
-    if rom.chr_rom.is_empty() {
-        panic!("ROM has no CHR/Graphics data, it will probably crash the renderer, aborting.")
-    }
+    let frame_buffer = arguments.iter()
+        .position(|x| x == "--frame-buffer")
+        .and_then(|index| arguments.get(index + 1))
+        .map(|count| count.parse::<usize>().expect("--frame-buffer expects a frame count"))
+        .unwrap_or(DEFAULT_FRAME_BUFFER);
+
+    let file = File::open(path)
+        .unwrap_or_else(|_| panic!("Cannot find ROM at path {path}"));
+
+    let rom = Rom::from_reader(BufReader::new(file))
+        .unwrap_or_else(|err| panic!("Failed to parse ROM contents at path {path} ({err})"));
 
     let (window, event_loop) = WindowDetails::make("EmulateMe Gui").unwrap();
 
     let streamer = Streamer::new(&window.details, NES_WIDTH, NES_HEIGHT);
 
-    let frame_data = Arc::new(Mutex::new(Some(RenderedFrame::default())));
+    let frame_data = Arc::new(FrameHandoff::new(frame_buffer));
 
     let window_arc = window.window.clone();
     let frame_arc = frame_data.clone();
 
-    let controller = GuiController::default();
-    let controller_copy = controller.clone();
+    let controller = GuiInput::default();
+    let controller_copy = GuiController::new(&controller, frame_latched);
 
     let reload = Arc::new(AtomicBool::new(false));
     let store = Arc::new(AtomicBool::new(false));
+    let record = Arc::new(AtomicBool::new(false));
+    let quit = Arc::new(AtomicBool::new(false));
 
     let reload_clone = reload.clone();
     let store_clone = store.clone();
+    let record_clone = record.clone();
+    let quit_clone = quit.clone();
 
-    thread::spawn(move || {
+    // Battery backed PRG RAM, kept next to the ROM.
+    let save_path = Path::new(path).with_extension("sav");
+
+    let emulator = thread::spawn(move || {
         let mut cpu = Cpu::new(&rom, None, (controller_copy, NoController));
 
+        #[cfg(feature = "audio")]
+        let audio = audio::AudioOutput::new();
+
+        #[cfg(feature = "audio")]
+        if let Some(audio) = &audio {
+            audio.attach(&mut cpu.memory.apu);
+        }
+
+        let battery = rom.flags.battery_ram;
+
+        let write_save = |cpu: &Cpu<GuiController, NoController>| {
+            if battery {
+                fs::write(&save_path, cpu.memory.save_ram()).unwrap();
+
+                println!("Wrote battery RAM to {}", save_path.display());
+            }
+        };
+
+        if battery {
+            if let Ok(data) = fs::read(&save_path) {
+                if cpu.memory.load_ram(&data) {
+                    println!("Read battery RAM from {}", save_path.display());
+                } else {
+                    println!("Ignoring {}, it is not {} bytes", save_path.display(), cpu.memory.save_ram().len());
+                }
+            }
+        }
+
         let mut renderer = SoftwareRenderer::new();
+        let mut recorder: Option<Recorder<BufWriter<File>, BufWriter<File>>> = None;
 
         loop {
             if store.swap(false, Ordering::Relaxed) {
-                let state = CpuState::from(&cpu);
+                let state = CpuState::from(&cpu).with_renderer(&renderer);
 
-                let data = postcard::to_allocvec(&state).unwrap();
-
-                fs::write(STATE_FILE, data).unwrap();
+                state.write_to(BufWriter::new(File::create(STATE_FILE).unwrap())).unwrap();
 
                 println!("Wrote CPU state to {}", STATE_FILE);
+
+                write_save(&cpu);
+            }
+
+            if quit_clone.load(Ordering::Relaxed) {
+                write_save(&cpu);
+
+                return
             }
 
             if reload.swap(false, Ordering::Relaxed) {
-                let data = fs::read(STATE_FILE).unwrap();
+                // Checked up front, restore takes the controllers even when it fails.
+                let state = File::open(STATE_FILE)
+                    .map_err(StateError::Io)
+                    .and_then(|file| CpuState::read_from(BufReader::new(file)))
+                    .and_then(|state| state.header.check(&rom).map(|_| state));
+
+                let state = match state {
+                    Ok(state) => state,
+                    Err(err) => {
+                        println!("Ignoring {STATE_FILE} ({err})");
+
+                        continue
+                    }
+                };
 
-                let state: CpuState = postcard::from_bytes(&data).unwrap();
+                let scan = state.scan;
 
                 cpu = state.restore(&rom, cpu.memory.controllers).unwrap();
-                renderer = SoftwareRenderer::new();
+                renderer.resume(scan);
+
+                #[cfg(feature = "audio")]
+                if let Some(audio) = &audio {
+                    audio.attach(&mut cpu.memory.apu);
+                }
 
                 println!("Read and restored CPU state from {}", STATE_FILE);
             }
 
+            if record.swap(false, Ordering::Relaxed) {
+                if let Some(recorder) = recorder.take() {
+                    let frames = recorder.finish().unwrap();
+
+                    println!("Wrote {frames} frames to {RECORD_VIDEO_FILE} and {RECORD_INPUT_FILE}");
+                } else {
+                    let video = BufWriter::new(File::create(RECORD_VIDEO_FILE).unwrap());
+                    let inputs = BufWriter::new(File::create(RECORD_INPUT_FILE).unwrap());
+
+                    recorder = Some(Recorder::new(video, inputs));
+
+                    println!("Recording to {RECORD_VIDEO_FILE} and {RECORD_INPUT_FILE}");
+                }
+            }
+
             for _ in 0 .. 400 {
                 cpu.step().unwrap();
 
-                match renderer.render(&mut cpu.memory.ppu, cpu.memory.cycles) {
-                    RenderAction::None => {},
-                    RenderAction::SendFrame(frame) => {
-                        let mut frame_data = frame_arc.lock().unwrap();
+                let (frame, nmi) = match renderer.render(&mut cpu.memory.ppu, cpu.memory.cycles) {
+                    RenderAction::None => continue,
+                    RenderAction::SendFrame(frame) => (frame, true),
+                    RenderAction::SuppressedFrame(frame) => (frame, false),
+                };
 
-                        *frame_data = Some(*frame);
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&frame, cpu.memory.controllers.0.active.flags()).unwrap();
+                }
 
-                        window_arc.request_redraw();
+                frame_arc.push(frame);
 
-                        cpu.interrupt(cpu.vectors.nmi).unwrap()
-                    }
+                window_arc.request_redraw();
+
+                cpu.memory.controllers.0.latch();
+
+                if nmi {
+                    cpu.interrupt(cpu.vectors.nmi).unwrap()
                 }
             }
+
+            #[cfg(feature = "audio")]
+            if let Some(audio) = &audio {
+                audio.push(&cpu.memory.apu.drain_samples());
+            }
         }
     });
 
     window.run(event_loop, || {
-        let Ok(mut frame) = frame_data.try_lock() else {
-            window.window.request_redraw();
+        let (frame, has_more) = frame_data.pop();
 
-            return;
-        };
-
-        if let Some(frame) = frame.take() {
+        if let Some(frame) = frame {
             streamer.render_frame(&frame.frame, window.size.get()).unwrap();
         } else {
             streamer.redraw_frame(window.size.get()).unwrap();
         }
+
+        if has_more {
+            window.window.request_redraw();
+        }
     }, |event| {
         let value = event.state == ElementState::Pressed;
 
@@ -142,8 +281,51 @@ fn main() {
             PhysicalKey::Code(KeyCode::KeyL) => controller.set(ControllerFlags::START, value),
             PhysicalKey::Code(KeyCode::KeyP) if value => store_clone.store(true, Ordering::Relaxed),
             PhysicalKey::Code(KeyCode::KeyO) if value => reload_clone.store(true, Ordering::Relaxed),
+            PhysicalKey::Code(KeyCode::KeyR) if value => record_clone.store(true, Ordering::Relaxed),
 
             _ => { }
         }
     }).unwrap();
+
+    quit.store(true, Ordering::Relaxed);
+
+    emulator.join().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_latched_input_waits_for_frame() {
+        let input = GuiInput::default();
+        let mut controller = GuiController::new(&input, true);
+
+        input.set(ControllerFlags::A, true);
+
+        assert_eq!(controller.read(0), 0);
+
+        controller.latch();
+
+        assert_eq!(controller.read(0), 1);
+
+        // Releases also wait for the next frame.
+        input.set(ControllerFlags::A, false);
+
+        assert_eq!(controller.read(0), 1);
+
+        controller.latch();
+
+        assert_eq!(controller.read(0), 0);
+    }
+
+    #[test]
+    fn immediate_input_applies_on_read() {
+        let input = GuiInput::default();
+        let mut controller = GuiController::new(&input, false);
+
+        input.set(ControllerFlags::B, true);
+
+        assert_eq!(controller.read(1), 1);
+    }
 }
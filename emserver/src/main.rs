@@ -1,10 +1,13 @@
 mod server;
 mod messages;
 mod delimiter;
+mod streams;
 
-use std::{env, fs};
-use emulateme::rom::{parse_rom};
-use crate::server::run_server;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use emulateme::rom::Rom;
+use crate::server::{run_server, ServerOptions};
 
 #[tokio::main]
 async fn main() {
@@ -12,11 +15,19 @@ async fn main() {
     let path = arguments.get(1)
         .expect("Requires one argument, a path to a valid NES ROM.");
 
-    let bytes = fs::read(path)
+    let file = File::open(path)
         .unwrap_or_else(|_| panic!("Cannot find ROM at path {path}"));
 
-    let (_, rom) = parse_rom(&bytes)
-        .unwrap_or_else(|_| panic!("Failed to parse ROM contents at path {path}"));
+    let rom = Rom::from_reader(BufReader::new(file))
+        .unwrap_or_else(|err| panic!("Failed to parse ROM contents at path {path} ({err})"));
 
-    run_server(&rom, "127.0.0.1:9013").await.unwrap()
+    let mut options = ServerOptions::default();
+
+    if let Some(index) = arguments.iter().position(|x| x == "--max-frames") {
+        options.max_frames_per_action = arguments.get(index + 1)
+            .and_then(|count| count.parse().ok())
+            .expect("--max-frames expects a frame count");
+    }
+
+    run_server(&rom, "127.0.0.1:9013", options).await.unwrap()
 }
@@ -14,6 +14,45 @@ pub struct Pong {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetServerInfo {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RomFlags {
+    #[prost(uint32, tag = "1")]
+    pub mapper: u32,
+    #[prost(enumeration = "Mirroring", tag = "2")]
+    pub mirroring: i32,
+    #[prost(bool, tag = "3")]
+    pub battery_ram: bool,
+    #[prost(bool, tag = "4")]
+    pub four_screen: bool,
+    /// Always zero unless the ROM has a NES 2.0 header.
+    #[prost(uint32, tag = "5")]
+    pub submapper: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServerInfo {
+    #[prost(string, tag = "1")]
+    pub server: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub flags: ::core::option::Option<RomFlags>,
+}
+/// Region of the frame to return, clipped to 256x240.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CropRect {
+    #[prost(uint32, tag = "1")]
+    pub x: u32,
+    #[prost(uint32, tag = "2")]
+    pub y: u32,
+    #[prost(uint32, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, tag = "4")]
+    pub height: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetFrame {
     /// Maps some key of your choice (ex. MARIO_X) to a memory address to be fetched.
     /// Key will be repeated in FrameDetails.
@@ -22,6 +61,42 @@ pub struct GetFrame {
         ::prost::alloc::string::String,
         u32,
     >,
+    #[prost(bool, tag = "3")]
+    pub include_name_table: bool,
+    /// If set, the frame is only returned when its sequence is greater than this.
+    #[prost(uint64, optional, tag = "4")]
+    pub newer_than: ::core::option::Option<u64>,
+    #[prost(bool, tag = "5")]
+    pub include_sprites: bool,
+    /// Missing returns the whole frame.
+    #[prost(message, optional, tag = "6")]
+    pub crop: ::core::option::Option<CropRect>,
+    /// Fills FrameContents.motion.
+    #[prost(bool, tag = "7")]
+    pub include_motion: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NameTableObservation {
+    /// 32x30 tile indices of the visible screen (row major), after scrolling and mirroring.
+    #[prost(bytes = "vec", tag = "1")]
+    pub tiles: ::prost::alloc::vec::Vec<u8>,
+    /// Background palette (0-3) selected by the attribute table for each tile above.
+    #[prost(bytes = "vec", tag = "2")]
+    pub palettes: ::prost::alloc::vec::Vec<u8>,
+}
+/// One OAM entry, as the game wrote it.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SpriteObservation {
+    #[prost(uint32, tag = "1")]
+    pub x: u32,
+    #[prost(uint32, tag = "2")]
+    pub y: u32,
+    #[prost(uint32, tag = "3")]
+    pub tile: u32,
+    #[prost(uint32, tag = "4")]
+    pub attributes: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -30,12 +105,27 @@ pub struct FrameContents {
     pub frame: ::prost::alloc::vec::Vec<u8>,
     /// Maps some key of your choice (ex. MARIO_X) to the associated byte.
     /// Missing key in the map means the fetch failed.
+    /// Values are read at the instant frame was completed (start of v-blank, before the NMI handler runs).
     #[prost(map = "string, uint32", tag = "2")]
     pub memory_values: ::std::collections::HashMap<::prost::alloc::string::String, u32>,
+    /// Only present if requested with include_name_table.
+    #[prost(message, optional, tag = "3")]
+    pub name_table: ::core::option::Option<NameTableObservation>,
+    /// Counts frames completed by this emulator instance.
+    #[prost(uint64, tag = "4")]
+    pub sequence: u64,
+    /// All 64 sprites if requested with include_sprites, otherwise empty.
+    #[prost(message, repeated, tag = "5")]
+    pub sprites: ::prost::alloc::vec::Vec<SpriteObservation>,
+    /// If requested with include_motion, the absolute grayscale difference between this frame
+    /// and the one before it, one byte per pixel, cropped like frame. Otherwise empty.
+    #[prost(bytes = "vec", tag = "6")]
+    pub motion: ::prost::alloc::vec::Vec<u8>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FrameDetails {
+    /// Missing when newer_than was set and no newer frame is available.
     #[prost(message, optional, tag = "2")]
     pub frame: ::core::option::Option<FrameContents>,
 }
@@ -62,7 +152,9 @@ pub struct ControllerInput {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TakeAction {
-    /// Should be at least 1. # of frames to hold this input for before returning.
+    /// # of frames to hold this input for before returning.
+    /// 0 only updates the held input and returns the current frame without advancing,
+    /// nothing is pushed to the stream in that case.
     #[prost(uint64, tag = "2")]
     pub skip_frames: u64,
     #[prost(message, optional, tag = "3")]
@@ -74,6 +166,22 @@ pub struct TakeAction {
     >,
     #[prost(uint32, optional, tag = "5")]
     pub stream_id: ::core::option::Option<u32>,
+    #[prost(bool, tag = "6")]
+    pub include_name_table: bool,
+    #[prost(bool, tag = "7")]
+    pub include_sprites: bool,
+    #[prost(message, optional, tag = "8")]
+    pub crop: ::core::option::Option<CropRect>,
+    /// CPU cycles the action may run for. When used up, the latest completed frame
+    /// is returned early with budget_exceeded set.
+    #[prost(uint64, optional, tag = "9")]
+    pub cycle_budget: ::core::option::Option<u64>,
+    /// Fills FrameContents.motion.
+    #[prost(bool, tag = "10")]
+    pub include_motion: bool,
+    /// Layers drawn into the frames rendered by this action.
+    #[prost(enumeration = "Layers", tag = "11")]
+    pub layers: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -88,6 +196,8 @@ pub struct ActionResult {
     pub frame: ::core::option::Option<FrameContents>,
     #[prost(message, optional, tag = "3")]
     pub error: ::core::option::Option<ActionError>,
+    #[prost(bool, tag = "4")]
+    pub budget_exceeded: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -112,6 +222,80 @@ pub struct SetStateResult {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetPpuRegisters {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PpuRegisterDetails {
+    /// Internal scroll/address registers, see RenderRegister.
+    #[prost(uint32, tag = "1")]
+    pub t: u32,
+    #[prost(uint32, tag = "2")]
+    pub v: u32,
+    #[prost(uint32, tag = "3")]
+    pub x: u32,
+    #[prost(bool, tag = "4")]
+    pub w: bool,
+    /// Raw PPUCTRL ($2000) and PPUMASK ($2001) bits.
+    /// Name table select bits of PPUCTRL are held in t.
+    #[prost(uint32, tag = "5")]
+    pub control: u32,
+    #[prost(uint32, tag = "6")]
+    pub mask: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetPpuRegisters {
+    /// Missing fields are left unchanged.
+    #[prost(uint32, optional, tag = "1")]
+    pub t: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub v: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub x: ::core::option::Option<u32>,
+    #[prost(bool, optional, tag = "4")]
+    pub w: ::core::option::Option<bool>,
+    #[prost(uint32, optional, tag = "5")]
+    pub control: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "6")]
+    pub mask: ::core::option::Option<u32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlayScript {
+    /// State to restore before playing, current state is kept if missing.
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub initial_state: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    /// One input per frame, played in order.
+    #[prost(message, repeated, tag = "2")]
+    pub inputs: ::prost::alloc::vec::Vec<ControllerInput>,
+    /// Frame bytes are left empty in the result when false.
+    #[prost(bool, tag = "3")]
+    pub render_final: bool,
+    #[prost(map = "string, uint32", tag = "4")]
+    pub memory_requests: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        u32,
+    >,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MemoryWrite {
+    #[prost(uint32, tag = "1")]
+    pub address: u32,
+    #[prost(uint32, tag = "2")]
+    pub value: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrepareAction {
+    /// Applied in order through the CPU bus, right before the action runs.
+    #[prost(message, repeated, tag = "1")]
+    pub writes: ::prost::alloc::vec::Vec<MemoryWrite>,
+    #[prost(message, optional, tag = "2")]
+    pub action: ::core::option::Option<TakeAction>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetStream {
     #[prost(uint32, tag = "1")]
     pub stream_id: u32,
@@ -125,12 +309,24 @@ pub struct StreamDetails {
     pub input: ::core::option::Option<ControllerInput>,
     #[prost(map = "string, uint32", tag = "3")]
     pub memory_values: ::std::collections::HashMap<::prost::alloc::string::String, u32>,
+    /// Increases by one per frame pushed to the stream. Each subscriber receives
+    /// buffered frames in order, repeating the latest frame once caught up.
+    /// Starts at 1, 0 means the stream has no frame yet and the other fields are empty.
+    #[prost(uint64, tag = "4")]
+    pub sequence: u64,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct InitializeRequest {
-    #[prost(oneof = "initialize_request::Contents", tags = "1, 2")]
+    #[prost(oneof = "initialize_request::Contents", tags = "1, 2, 3")]
     pub contents: ::core::option::Option<initialize_request::Contents>,
+    /// Used by CREATE_EMULATOR in place of the built-in colors, 64 RGB triplets like a .pal file.
+    #[prost(bytes = "vec", tag = "4")]
+    pub palette: ::prost::alloc::vec::Vec<u8>,
+    /// Used by CREATE_EMULATOR, names (ex. MARIO_X) for memory addresses that are read into
+    /// every FrameContents.memory_values, as if added to each request's memory_requests.
+    #[prost(map = "string, uint32", tag = "5")]
+    pub symbols: ::std::collections::HashMap<::prost::alloc::string::String, u32>,
 }
 /// Nested message and enum types in `InitializeRequest`.
 pub mod initialize_request {
@@ -141,6 +337,8 @@ pub mod initialize_request {
         Ping(super::Ping),
         #[prost(enumeration = "super::InitializeType", tag = "2")]
         Initialize(i32),
+        #[prost(message, tag = "3")]
+        GetServerInfo(super::GetServerInfo),
     }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -163,7 +361,7 @@ pub mod stream_request {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct EmulatorRequest {
-    #[prost(oneof = "emulator_request::Contents", tags = "1, 3, 4, 5, 6")]
+    #[prost(oneof = "emulator_request::Contents", tags = "1, 3, 4, 5, 6, 7, 8, 9, 10")]
     pub contents: ::core::option::Option<emulator_request::Contents>,
 }
 /// Nested message and enum types in `EmulatorRequest`.
@@ -181,6 +379,46 @@ pub mod emulator_request {
         GetState(super::GetState),
         #[prost(message, tag = "6")]
         SetState(super::SetState),
+        #[prost(message, tag = "7")]
+        GetPpuRegisters(super::GetPpuRegisters),
+        #[prost(message, tag = "8")]
+        SetPpuRegisters(super::SetPpuRegisters),
+        #[prost(message, tag = "9")]
+        PlayScript(super::PlayScript),
+        #[prost(message, tag = "10")]
+        PrepareAction(super::PrepareAction),
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Mirroring {
+    Horizontal = 0,
+    Vertical = 1,
+    SingleLower = 2,
+    SingleUpper = 3,
+}
+impl Mirroring {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Mirroring::Horizontal => "MIRRORING_HORIZONTAL",
+            Mirroring::Vertical => "MIRRORING_VERTICAL",
+            Mirroring::SingleLower => "MIRRORING_SINGLE_LOWER",
+            Mirroring::SingleUpper => "MIRRORING_SINGLE_UPPER",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "MIRRORING_HORIZONTAL" => Some(Self::Horizontal),
+            "MIRRORING_VERTICAL" => Some(Self::Vertical),
+            "MIRRORING_SINGLE_LOWER" => Some(Self::SingleLower),
+            "MIRRORING_SINGLE_UPPER" => Some(Self::SingleUpper),
+            _ => None,
+        }
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
@@ -209,6 +447,37 @@ impl Renderer {
         }
     }
 }
+/// Debug override for which layers are drawn, on top of what the game enables in PPUMASK.
+/// Hidden sprites still register sprite 0 hits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Layers {
+    All = 0,
+    Background = 1,
+    Sprites = 2,
+}
+impl Layers {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Layers::All => "LAYERS_ALL",
+            Layers::Background => "LAYERS_BACKGROUND",
+            Layers::Sprites => "LAYERS_SPRITES",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "LAYERS_ALL" => Some(Self::All),
+            "LAYERS_BACKGROUND" => Some(Self::Background),
+            "LAYERS_SPRITES" => Some(Self::Sprites),
+            _ => None,
+        }
+    }
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum InitializeType {
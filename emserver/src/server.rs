@@ -5,63 +5,143 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use anyhow::{anyhow, Result};
 use prost::Message;
-use emulateme::controller::{ControllerFlags, GenericController, NoController};
+use emulateme::controller::{ButtonState, ControllerFlags, GenericController, NoController};
 use emulateme::cpu::Cpu;
 use emulateme::interpreter::CpuError;
-use emulateme::renderer::{RenderAction, RenderedFrame, Renderer};
-use emulateme::rom::Rom;
-use emulateme::software::SoftwareRenderer;
+use emulateme::memory::MemoryError;
+use emulateme::palette::{Color, parse_palette};
+use emulateme::ppu::{ControlRegister, MaskRegister};
+use emulateme::renderer::{crop_pixels, NES_HEIGHT, NES_WIDTH, RenderAction, RenderedFrame, Renderer};
+use emulateme::rom::{Flags, Mirroring as RomMirroring, Rom};
+use emulateme::software::{background_palette_at, Layers as RenderLayers, SoftwareRenderer};
 use emulateme::state::CpuState;
 use crate::delimiter::Delimiter;
-use crate::messages::{ActionError, ActionResult, ControllerInput, StreamDetails, EmulatorRequest, FrameContents, FrameDetails, InitializeRequest, InitializeType, Ping, Pong, SetStateResult, StateDetails, StreamRequest};
+use crate::streams::StreamBuffer;
+use crate::messages::{ActionError, ActionResult, ControllerInput, CropRect, GetFrame, StreamDetails, EmulatorRequest, FrameContents, FrameDetails, InitializeRequest, InitializeType, Layers, Mirroring, MemoryWrite, NameTableObservation, Ping, PlayScript, Pong, PrepareAction, PpuRegisterDetails, SetPpuRegisters, RomFlags, SpriteObservation, TakeAction, ServerInfo, SetStateResult, StateDetails, StreamRequest};
 use crate::messages::stream_request::Contents as StreamContents;
 use crate::messages::initialize_request::Contents as InitializeContents;
 use crate::messages::emulator_request::Contents as EmulatorContents;
 
-impl From<&ControllerInput> for ControllerFlags {
+impl From<&ControllerInput> for ButtonState {
     fn from(value: &ControllerInput) -> Self {
-        let mut flags = ControllerFlags::empty();
-
-        if value.a {
-            flags |= ControllerFlags::A;
+        ButtonState {
+            a: value.a,
+            b: value.b,
+            select: value.select,
+            start: value.start,
+            up: value.up,
+            down: value.down,
+            left: value.left,
+            right: value.right,
         }
+    }
+}
 
-        if value.b {
-            flags |= ControllerFlags::B;
+impl From<ButtonState> for ControllerInput {
+    fn from(value: ButtonState) -> Self {
+        ControllerInput {
+            a: value.a,
+            b: value.b,
+            select: value.select,
+            start: value.start,
+            up: value.up,
+            down: value.down,
+            left: value.left,
+            right: value.right,
         }
+    }
+}
 
-        if value.select {
-            flags |= ControllerFlags::SELECT;
-        }
+impl From<&ControllerInput> for ControllerFlags {
+    fn from(value: &ControllerInput) -> Self {
+        ButtonState::from(value).into()
+    }
+}
 
-        if value.start {
-            flags |= ControllerFlags::START;
+impl From<&Flags> for RomFlags {
+    fn from(value: &Flags) -> Self {
+        let mirroring = match value.mirroring {
+            RomMirroring::Horizontal => Mirroring::Horizontal,
+            RomMirroring::Vertical => Mirroring::Vertical,
+            RomMirroring::SingleLower => Mirroring::SingleLower,
+            RomMirroring::SingleUpper => Mirroring::SingleUpper,
+        };
+
+        RomFlags {
+            mapper: value.mapper as u32,
+            mirroring: mirroring as i32,
+            battery_ram: value.battery_ram,
+            four_screen: value.four_screen,
+            submapper: value.submapper as u32,
         }
+    }
+}
 
-        if value.up {
-            flags |= ControllerFlags::UP;
-        }
+// What to include in FrameContents, shared by GetFrame and TakeAction.
+struct Observation<'a> {
+    memory_requests: &'a HashMap<String, u32>,
+    name_table: bool,
+    sprites: bool,
+    motion: bool,
+    crop: Option<&'a CropRect>,
+}
 
-        if value.down {
-            flags |= ControllerFlags::DOWN;
+impl<'a> From<&'a GetFrame> for Observation<'a> {
+    fn from(value: &'a GetFrame) -> Self {
+        Observation {
+            memory_requests: &value.memory_requests,
+            name_table: value.include_name_table,
+            sprites: value.include_sprites,
+            motion: value.include_motion,
+            crop: value.crop.as_ref(),
         }
+    }
+}
 
-        if value.left {
-            flags |= ControllerFlags::LEFT;
+impl<'a> From<&'a TakeAction> for Observation<'a> {
+    fn from(value: &'a TakeAction) -> Self {
+        Observation {
+            memory_requests: &value.memory_requests,
+            name_table: value.include_name_table,
+            sprites: value.include_sprites,
+            motion: value.include_motion,
+            crop: value.crop.as_ref(),
         }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ServerOptions {
+    // TakeAction and PlayScript requests running more frames than this are rejected.
+    pub max_frames_per_action: u64,
+}
 
-        if value.right {
-            flags |= ControllerFlags::RIGHT;
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_frames_per_action: 1000,
         }
+    }
+}
 
-        flags
+fn action_error(message: String) -> ActionResult {
+    ActionResult {
+        frame: None,
+        error: Some(ActionError { message }),
+        budget_exceeded: false,
     }
 }
 
-type StreamStates = Arc<Mutex<HashMap<u32, StreamDetails>>>;
+type StreamStates = Arc<Mutex<HashMap<u32, StreamBuffer>>>;
 
 struct NesInstance<'a> {
+    options: ServerOptions,
+    // Read into every observation, see InitializeRequest.symbols.
+    symbols: HashMap<String, u32>,
     frame: Box<RenderedFrame>,
+    // The frame before frame, for motion observations.
+    previous_frame: Box<RenderedFrame>,
+    frame_sequence: u64,
     renderer: SoftwareRenderer,
     cpu: Cpu<'a, GenericController, NoController>
 }
@@ -87,32 +167,295 @@ impl<'a> NesInstance<'a> {
         values
     }
 
-    pub fn run_frames(&mut self, skip_frames: usize, input: ControllerFlags) -> Result<(), CpuError> {
+    // A request's own names win over symbols with the same name.
+    fn observed_values(&mut self, requests: &HashMap<String, u32>) -> HashMap<String, u32> {
+        let symbols = std::mem::take(&mut self.symbols);
+
+        let mut values = self.get_values(&symbols);
+
+        self.symbols = symbols;
+
+        values.extend(self.get_values(requests));
+
+        values
+    }
+
+    // run_frames stops right as a frame completes (before the NMI handler runs),
+    // so the values read here belong to exactly the frame returned with them.
+    fn capture(&mut self, observation: Observation) -> FrameContents {
+        let frame = match observation.crop {
+            Some(crop) => self.frame.crop(crop.x as usize, crop.y as usize, crop.width as usize, crop.height as usize),
+            None => self.frame.frame.to_vec(),
+        };
+
+        let motion = if observation.motion {
+            let motion = self.frame.motion(&self.previous_frame);
+
+            match observation.crop {
+                Some(crop) => crop_pixels(&motion, 1, crop.x as usize, crop.y as usize, crop.width as usize, crop.height as usize),
+                None => motion,
+            }
+        } else {
+            vec![]
+        };
+
+        FrameContents {
+            frame,
+            memory_values: self.observed_values(observation.memory_requests),
+            name_table: observation.name_table.then(|| self.name_table()),
+            sequence: self.frame_sequence,
+            sprites: if observation.sprites { self.sprites() } else { vec![] },
+            motion,
+        }
+    }
+
+    // Empty when the client already has the current frame, see GetFrame.newer_than.
+    fn get_frame(&mut self, request: &GetFrame) -> FrameDetails {
+        let is_new = request.newer_than
+            .is_none_or(|sequence| self.frame_sequence > sequence);
+
+        FrameDetails {
+            frame: is_new.then(|| self.capture(request.into())),
+        }
+    }
+
+    fn sprites(&self) -> Vec<SpriteObservation> {
+        self.cpu.memory.ppu.memory.oam.iter()
+            .map(|sprite| SpriteObservation {
+                x: sprite.x as u32,
+                y: sprite.y as u32,
+                tile: sprite.number as u32,
+                attributes: sprite.mask as u32,
+            })
+            .collect()
+    }
+
+    // Tile and palette under the top left pixel of each 8x8 block on screen.
+    fn name_table(&self) -> NameTableObservation {
+        let ppu = &self.cpu.memory.ppu;
+
+        let cells = (0 .. NES_HEIGHT / 8)
+            .flat_map(|row| (0 .. NES_WIDTH / 8).map(move |col| (col * 8, row * 8)));
+
+        NameTableObservation {
+            tiles: cells.clone().map(|(x, y)| ppu.nametable_byte_at_screen(x, y)).collect(),
+            palettes: cells.map(|(x, y)| background_palette_at(ppu, x, y)).collect(),
+        }
+    }
+
+    fn ppu_registers(&self) -> PpuRegisterDetails {
+        let registers = &self.cpu.memory.ppu.registers;
+
+        PpuRegisterDetails {
+            t: registers.render.t as u32,
+            v: registers.render.v as u32,
+            x: registers.render.x as u32,
+            w: registers.render.w,
+            control: registers.control.bits() as u32,
+            mask: registers.mask.bits() as u32,
+        }
+    }
+
+    fn set_ppu_registers(&mut self, request: &SetPpuRegisters) {
+        let registers = &mut self.cpu.memory.ppu.registers;
+
+        // Bypasses write_ctrl so the name table bits already in t are kept.
+        if let Some(control) = request.control {
+            registers.control = ControlRegister::from_bits(control as u8);
+        }
+
+        if let Some(mask) = request.mask {
+            registers.mask = MaskRegister::from_bits(mask as u8);
+        }
+
+        if let Some(t) = request.t {
+            registers.render.t = (t as u16) & 0x7FFF;
+        }
+
+        if let Some(v) = request.v {
+            registers.render.v = (v as u16) & 0x7FFF;
+        }
+
+        if let Some(x) = request.x {
+            registers.render.x = (x as u8) & 0b111;
+        }
+
+        if let Some(w) = request.w {
+            registers.render.w = w;
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        CpuState::from(&self.cpu)
+            .with_renderer(&self.renderer)
+            .to_bytes()
+            .unwrap_or_default()
+    }
+
+    fn restore(&mut self, rom: &'a Rom, state: &[u8]) -> Result<(), String> {
+        let state = CpuState::from_bytes(state)
+            .map_err(|err| format!("{err}"))?;
+
+        let controllers = (GenericController::default(), NoController);
+
+        let scan = state.scan;
+
+        let cpu = state.restore(rom, controllers)
+            .map_err(|err| format!("Failed to create CPU instance from state ({err})."))?;
+
+        self.cpu = cpu;
+        self.renderer.resume(scan);
+
+        Ok(())
+    }
+
+    fn check_frame_limit(&self, frames: u64) -> Result<(), String> {
+        let limit = self.options.max_frames_per_action;
+
+        if frames > limit {
+            return Err(format!("Requested {frames} frames, but the server limit is {limit} per action."))
+        }
+
+        Ok(())
+    }
+
+    fn take_action(&mut self, action: &TakeAction, states: &StreamStates) -> ActionResult {
+        if let Err(message) = self.check_frame_limit(action.skip_frames) {
+            return action_error(message)
+        }
+
+        let flags = action.input.as_ref()
+            .map(ControllerFlags::from)
+            .unwrap_or(ControllerFlags::empty());
+
+        self.renderer.layers = match action.layers() {
+            Layers::All => RenderLayers::default(),
+            Layers::Background => RenderLayers { background: true, sprites: false },
+            Layers::Sprites => RenderLayers { background: false, sprites: true },
+        };
+
+        let completed = match self.run_frames(action.skip_frames as usize, flags, action.cycle_budget) {
+            Ok(completed) => completed,
+            Err(err) => return action_error(format!("CpuError: {err}"))
+        };
+
+        let contents = self.capture(action.into());
+
+        if let (Some(stream), 1 .., true) = (action.stream_id, action.skip_frames, completed) {
+            let details = StreamDetails {
+                frame: contents.frame.clone(),
+                input: action.input.clone(),
+                memory_values: contents.memory_values.clone(),
+                sequence: 0,
+            };
+
+            let mut states = states.lock().unwrap();
+
+            states.entry(stream).or_default().push(details);
+        }
+
+        ActionResult {
+            frame: Some(contents),
+            error: None,
+            budget_exceeded: !completed,
+        }
+    }
+
+    fn write_memory(&mut self, writes: &[MemoryWrite]) -> Result<(), MemoryError> {
+        for write in writes {
+            self.cpu.memory.pass_set(write.address as u16, write.value as u8)?;
+        }
+
+        Ok(())
+    }
+
+    // The writes and the action run back to back, nothing else reaches the instance in between.
+    fn prepare_action(&mut self, request: &PrepareAction, states: &StreamStates) -> ActionResult {
+        if let Err(err) = self.write_memory(&request.writes) {
+            return action_error(format!("MemoryError: {err}"))
+        }
+
+        self.take_action(&request.action.clone().unwrap_or_default(), states)
+    }
+
+    // Same as SetState, then one TakeAction per input, then GetFrame.
+    fn play_script(&mut self, rom: &'a Rom, script: &PlayScript) -> ActionResult {
+        if let Err(message) = self.check_frame_limit(script.inputs.len() as u64) {
+            return action_error(message)
+        }
+
+        if let Some(state) = &script.initial_state {
+            if let Err(message) = self.restore(rom, state) {
+                return action_error(message)
+            }
+        }
+
+        for input in &script.inputs {
+            if let Err(err) = self.run_frames(1, input.into(), None) {
+                return action_error(format!("CpuError: {err}"))
+            }
+        }
+
+        let mut contents = self.capture(Observation {
+            memory_requests: &script.memory_requests,
+            name_table: false,
+            sprites: false,
+            motion: false,
+            crop: None,
+        });
+
+        if !script.render_final {
+            contents.frame.clear();
+        }
+
+        ActionResult { frame: Some(contents), error: None, budget_exceeded: false }
+    }
+
+    // With skip_frames = 0 the input is held but the emulator does not advance.
+    // Returns false if cycle_budget ran out before skip_frames completed.
+    pub fn run_frames(&mut self, skip_frames: usize, input: ControllerFlags, cycle_budget: Option<u64>) -> Result<bool, CpuError> {
         let mut frame_count = 0;
 
+        let deadline = cycle_budget.map(|budget| self.cpu.memory.cycles.saturating_add(budget));
+
         self.cpu.memory.controllers.0.press(input);
 
         while frame_count < skip_frames {
+            if deadline.is_some_and(|deadline| self.cpu.memory.cycles >= deadline) {
+                return Ok(false)
+            }
+
             self.cpu.step()?;
 
             match self.renderer.render(&mut self.cpu.memory.ppu, self.cpu.memory.cycles) {
                 RenderAction::None => { },
                 RenderAction::SendFrame(frame) => {
                     frame_count += 1;
+                    self.frame_sequence += 1;
 
                     self.cpu.interrupt(self.cpu.vectors.nmi)?;
 
-                    self.frame = frame
+                    self.previous_frame = std::mem::replace(&mut self.frame, frame)
+                }
+                RenderAction::SuppressedFrame(frame) => {
+                    frame_count += 1;
+                    self.frame_sequence += 1;
+
+                    self.previous_frame = std::mem::replace(&mut self.frame, frame)
                 }
             }
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    pub fn new(rom: &Rom) -> NesInstance {
+    pub fn new(rom: &Rom, options: ServerOptions) -> NesInstance<'_> {
         NesInstance {
+            options,
+            symbols: HashMap::new(),
             frame: Box::default(),
+            previous_frame: Box::default(),
+            frame_sequence: 0,
             cpu: Cpu::new(rom, None, (GenericController::default(), NoController)),
             renderer: SoftwareRenderer::new(),
         }
@@ -149,15 +492,30 @@ async fn read_into(delimiter: &mut Delimiter, stream: &mut TcpStream) -> Result<
     Ok(())
 }
 
+const SERVER_NAME: &str = "em-server-1";
+
 async fn pong(stream: &mut TcpStream, request: Ping) -> Result<()> {
     send_message(stream, Pong {
-        server: "em-server-1".to_string(),
+        server: SERVER_NAME.to_string(),
         content: request.content,
     }).await
 }
 
-async fn nes_instance(rom: Rom, mut delimiter: Delimiter, mut stream: TcpStream, states: StreamStates) -> Result<()> {
-    let mut instance = Box::new(NesInstance::new(&rom));
+fn server_info(rom: &Rom) -> ServerInfo {
+    ServerInfo {
+        server: SERVER_NAME.to_string(),
+        flags: Some((&rom.flags).into()),
+    }
+}
+
+async fn nes_instance(rom: Rom, options: ServerOptions, palette: Option<[Color; 0x40]>, symbols: HashMap<String, u32>, mut delimiter: Delimiter, mut stream: TcpStream, states: StreamStates) -> Result<()> {
+    let mut instance = Box::new(NesInstance::new(&rom, options));
+
+    if let Some(palette) = palette {
+        instance.renderer.palette = palette;
+    }
+
+    instance.symbols = symbols;
 
     loop {
         while let Some(packet) = delimiter.pop() {
@@ -177,82 +535,37 @@ async fn nes_instance(rom: Rom, mut delimiter: Delimiter, mut stream: TcpStream,
                     pong(&mut stream, request).await?;
                 }
                 EmulatorContents::GetFrame(frame) => {
-                    send_message(&mut stream, FrameDetails {
-                        frame: Some(FrameContents {
-                            frame: instance.frame.frame.to_vec(),
-                            memory_values: instance.get_values(&frame.memory_requests),
-                        }),
-                    }).await?;
+                    send_message(&mut stream, instance.get_frame(&frame)).await?;
                 }
                 EmulatorContents::TakeAction(action) => {
-                    let flags = action.input.as_ref()
-                        .map(ControllerFlags::from)
-                        .unwrap_or(ControllerFlags::empty());
-
-                    if let Err(err) = instance.run_frames(action.skip_frames as usize, flags) {
-                        send_message(&mut stream, ActionResult {
-                            frame: None,
-                            error: Some(ActionError {
-                                message: format!("CpuError: {err}"),
-                            }),
-                        }).await?;
-
-                        continue
-                    }
-
-                    let memory_values = instance.get_values(&action.memory_requests);
-
-                    if let Some(stream) = action.stream_id {
-                        let details = StreamDetails {
-                            frame: instance.frame.frame.to_vec(),
-                            input: action.input.clone(),
-                            memory_values: memory_values.clone(),
-                        };
-
-                        let mut states = states.lock().unwrap();
-
-                        states.insert(stream, details);
-                    }
-
-                    send_message(&mut stream, ActionResult {
-                        frame: Some(FrameContents {
-                            frame: instance.frame.frame.to_vec(),
-                            memory_values,
-                        }),
-                        error: None,
-                    }).await?;
+                    send_message(&mut stream, instance.take_action(&action, &states)).await?;
                 }
                 EmulatorContents::GetState(_) => {
-                    let state: CpuState = (&instance.cpu).into();
-
-                    let bytes = postcard::to_allocvec(&state)
-                        .unwrap_or_default();
-
                     send_message(&mut stream, StateDetails {
-                        state: bytes,
+                        state: instance.save_state(),
                     }).await?;
                 }
                 EmulatorContents::SetState(state) => {
-                    let error = match postcard::from_bytes::<CpuState>(&state.state) {
-                        Ok(state) => {
-                            let controllers = (GenericController::default(), NoController);
-
-                            if let Some(cpu) = state.restore(&rom, controllers) {
-                                instance.cpu = cpu;
-                                instance.renderer = SoftwareRenderer::new();
-
-                                None
-                            } else {
-                                Some("Failed to create CPU instance from state.".to_string())
-                            }
-                        }
-                        Err(err) => Some(format!("{err}"))
-                    };
+                    let error = instance.restore(&rom, &state.state).err();
 
                     send_message(&mut stream, SetStateResult {
                         parse_error: error
                     }).await?;
                 }
+                EmulatorContents::GetPpuRegisters(_) => {
+                    send_message(&mut stream, instance.ppu_registers()).await?;
+                }
+                EmulatorContents::SetPpuRegisters(request) => {
+                    instance.set_ppu_registers(&request);
+
+                    send_message(&mut stream, instance.ppu_registers()).await?;
+                }
+                EmulatorContents::PrepareAction(request) => {
+                    send_message(&mut stream, instance.prepare_action(&request, &states)).await?;
+                }
+                EmulatorContents::PlayScript(script) => {
+                    send_message(&mut stream, instance.play_script(&rom, &script)).await?;
+                }
             }
         }
 
@@ -261,6 +574,9 @@ async fn nes_instance(rom: Rom, mut delimiter: Delimiter, mut stream: TcpStream,
 }
 
 async fn stream_instance(mut delimiter: Delimiter, mut stream: TcpStream, states: StreamStates) -> Result<()> {
+    // Last sequence sent to this subscriber, per stream id.
+    let mut cursors: HashMap<u32, u64> = HashMap::new();
+
     loop {
         while let Some(packet) = delimiter.pop() {
             let request = match StreamRequest::decode(&packet[..]) {
@@ -279,18 +595,20 @@ async fn stream_instance(mut delimiter: Delimiter, mut stream: TcpStream, states
                 StreamContents::GetStream(request) => {
                     let frame = {
                         let states = states.lock().unwrap();
+                        let cursor = cursors.get(&request.stream_id).copied();
 
-                        states.get(&request.stream_id).cloned()
+                        states.get(&request.stream_id)
+                            .and_then(|buffer| buffer.next(cursor))
+                            .cloned()
                     };
 
                     if let Some(frame) = frame {
+                        cursors.insert(request.stream_id, frame.sequence);
+
                         send_message(&mut stream, frame).await?;
                     } else {
-                        send_message(&mut stream, StreamDetails {
-                            frame: vec![],
-                            input: None,
-                            memory_values: Default::default(),
-                        }).await?;
+                        // Sequence 0, real frames start at 1.
+                        send_message(&mut stream, StreamDetails::default()).await?;
                     }
                 }
             }
@@ -300,7 +618,7 @@ async fn stream_instance(mut delimiter: Delimiter, mut stream: TcpStream, states
     }
 }
 
-async fn client_connection(rom: Rom, mut stream: TcpStream, states: StreamStates) -> Result<()> {
+async fn client_connection(rom: Rom, options: ServerOptions, mut stream: TcpStream, states: StreamStates) -> Result<()> {
     let mut delimiter = Delimiter::default();
 
     loop {
@@ -318,12 +636,24 @@ async fn client_connection(rom: Rom, mut stream: TcpStream, states: StreamStates
 
             match contents {
                 InitializeContents::Ping(request) => pong(&mut stream, request).await?,
+                InitializeContents::GetServerInfo(_) => {
+                    send_message(&mut stream, server_info(&rom)).await?
+                },
                 InitializeContents::Initialize(kind) => {
                     let kind = InitializeType::try_from(kind)?;
 
                     match kind {
                         InitializeType::CreateEmulator => {
-                            return nes_instance(rom, delimiter, stream, states).await
+                            let palette = if request.palette.is_empty() {
+                                None
+                            } else {
+                                let palette = parse_palette(&request.palette)
+                                    .ok_or_else(|| anyhow!("Palette must contain 64 RGB entries."))?;
+
+                                Some(palette)
+                            };
+
+                            return nes_instance(rom, options, palette, request.symbols, delimiter, stream, states).await
                         },
                         InitializeType::OpenStream => {
                             return stream_instance(delimiter, stream, states).await
@@ -337,7 +667,7 @@ async fn client_connection(rom: Rom, mut stream: TcpStream, states: StreamStates
     }
 }
 
-pub async fn run_server(rom: &'_ Rom, address: &'_ str) -> Result<()> {
+pub async fn run_server(rom: &'_ Rom, address: &'_ str, options: ServerOptions) -> Result<()> {
     let stream = TcpListener::bind(address).await?;
     let states: StreamStates = Arc::default();
 
@@ -352,9 +682,664 @@ pub async fn run_server(rom: &'_ Rom, address: &'_ str) -> Result<()> {
         let states_clone = states.clone();
 
         tokio::spawn(async move {
-            if let Err(error) = client_connection(rom_clone, stream, states_clone).await {
+            if let Err(error) = client_connection(rom_clone, options, stream, states_clone).await {
                 println!("{error}")
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use emulateme::software::NES_FRAME_CYCLES;
+    use super::*;
+
+    // Reads the first controller each vblank and moves sprite 0 by it through OAM DMA, so inputs reach the frame.
+    const REPLAY_PROGRAM: [u8; 64] = [
+        0x78,                   // SEI
+        0xA2, 0x00,             // LDX #$00
+        0xA9, 0x01,             // LDA #$01
+        0x8D, 0x01, 0x02,       // STA $0201
+        0xA9, 0x80,             // LDA #$80
+        0x8D, 0x00, 0x20,       // STA $2000
+        0xA9, 0x1E,             // LDA #$1E
+        0x8D, 0x01, 0x20,       // STA $2001
+        0x2C, 0x02, 0x20,       // loop: BIT $2002
+        0x10, 0xFB,             // BPL loop
+        0xA9, 0x01,             // LDA #$01
+        0x8D, 0x16, 0x40,       // STA $4016
+        0xA9, 0x00,             // LDA #$00
+        0x8D, 0x16, 0x40,       // STA $4016
+        0xA0, 0x08,             // LDY #$08
+        0xAD, 0x16, 0x40,       // read: LDA $4016
+        0x4A,                   // LSR A
+        0x26, 0x00,             // ROL $00
+        0x88,                   // DEY
+        0xD0, 0xF7,             // BNE read
+        0xE8,                   // INX
+        0xA5, 0x00,             // LDA $00
+        0x8D, 0x00, 0x02,       // STA $0200
+        0x8A,                   // TXA
+        0x65, 0x00,             // ADC $00
+        0x8D, 0x03, 0x02,       // STA $0203
+        0xA9, 0x02,             // LDA #$02
+        0x8D, 0x14, 0x40,       // STA $4014
+        0x4C, 0x12, 0x80,       // JMP loop
+    ];
+
+    fn replay_instance(rom: &Rom) -> NesInstance<'_> {
+        let mut instance = NesInstance::new(rom, ServerOptions::default());
+
+        // The program enables NMI straight away.
+        instance.cpu.memory.ppu.warmup = false;
+
+        instance
+    }
+
+    fn replay_rom() -> Rom {
+        let mut rom = Rom::from_prg(&REPLAY_PROGRAM, 0x8000);
+
+        // Tile 1 opaque so the sprite shows up.
+        rom.chr_rom[16 .. 24].fill(0xFF);
+
+        rom
+    }
+
+    fn record(instance: &mut NesInstance, inputs: &[ControllerFlags]) -> Vec<Vec<u8>> {
+        inputs.iter()
+            .map(|input| {
+                assert!(instance.run_frames(1, *input, None).unwrap());
+
+                instance.frame.frame.to_vec()
+            })
+            .collect()
+    }
+
+    // Restores the state taken before the recording and plays the same inputs again.
+    // The renderer drops the partly drawn frame on restore, so rows above first_row may differ in frame 0.
+    fn replay<'a>(instance: &mut NesInstance<'a>, rom: &'a Rom, first_row: usize) {
+        let inputs: Vec<ControllerFlags> = (0 .. 20u8)
+            .map(|i| ControllerFlags::from_bits_retain(i.wrapping_mul(37)))
+            .collect();
+
+        let state = instance.save_state();
+        let cycles = instance.cpu.memory.cycles;
+
+        let recorded = record(instance, &inputs);
+        let end_cycles = instance.cpu.memory.cycles;
+
+        // The sprite moves, otherwise matching frames would prove little.
+        assert!(recorded.windows(2).any(|frames| frames[0] != frames[1]));
+
+        instance.restore(rom, &state).unwrap();
+        assert_eq!(instance.cpu.memory.cycles, cycles);
+
+        let replayed = record(instance, &inputs);
+
+        assert_eq!(instance.cpu.memory.cycles, end_cycles);
+
+        for (index, (recorded, replayed)) in recorded.iter().zip(&replayed).enumerate() {
+            let start = if index == 0 { first_row * NES_WIDTH * 4 } else { 0 };
+
+            assert!(recorded[start ..] == replayed[start ..], "frame {index} differs after restoring");
+        }
+    }
+
+    #[test]
+    fn replay_after_set_state() {
+        let rom = replay_rom();
+        let mut instance = replay_instance(&rom);
+
+        assert!(instance.run_frames(30, ControllerFlags::empty(), None).unwrap());
+
+        replay(&mut instance, &rom, 0);
+    }
+
+    #[test]
+    fn replay_from_mid_frame() {
+        let rom = replay_rom();
+        let mut instance = replay_instance(&rom);
+
+        assert!(instance.run_frames(30, ControllerFlags::RIGHT, None).unwrap());
+
+        // Well short of a frame, so no frame completes here.
+        for _ in 0 .. 1000 {
+            instance.cpu.step().unwrap();
+
+            let action = instance.renderer.render(&mut instance.cpu.memory.ppu, instance.cpu.memory.cycles);
+
+            assert!(matches!(action, RenderAction::None));
+        }
+
+        let scan = instance.renderer.scan_position();
+
+        assert!(scan.y < 240, "expected to stop inside the visible frame");
+
+        replay(&mut instance, &rom, scan.y + 1);
+    }
+
+    fn writes(writes: &[(u16, u8)]) -> Vec<MemoryWrite> {
+        writes.iter()
+            .map(|(address, value)| MemoryWrite { address: *address as u32, value: *value as u32 })
+            .collect()
+    }
+
+    #[test]
+    fn scroll_registers_read_back() {
+        let rom = replay_rom();
+        let mut instance = replay_instance(&rom);
+
+        // $2002 resets the write toggle, then X = $7D and Y = $5E.
+        instance.cpu.memory.pass_get(0x2002).unwrap();
+        instance.write_memory(&writes(&[(0x2005, 0x7D), (0x2005, 0x5E)])).unwrap();
+
+        let registers = instance.ppu_registers();
+
+        // Fine Y 6, coarse Y 11, coarse X 15.
+        assert_eq!(registers.t, 0x616F);
+        assert_eq!(registers.x, 5);
+        assert!(!registers.w);
+
+        let ppu = &instance.cpu.memory.ppu.registers.render;
+
+        assert_eq!((ppu.x_scroll(), ppu.y_scroll()), (0x7D, 0x5E));
+
+        instance.write_memory(&writes(&[(0x2005, 0x08)])).unwrap();
+
+        assert!(instance.ppu_registers().w);
+    }
+
+    // Enables NMI and idles, the NMI handler counts frames in $10.
+    // The v-blank flag is set at power on, reading it first keeps enabling NMI from firing one early.
+    const COUNTER_PROGRAM: [u8; 14] = [
+        0x2C, 0x02, 0x20,       // BIT $2002
+        0xA9, 0x80,             // LDA #$80
+        0x8D, 0x00, 0x20,       // STA $2000
+        0x4C, 0x08, 0x80,       // JMP $8008
+        0xE6, 0x10,             // nmi: INC $10
+        0x40,                   // RTI
+    ];
+
+    fn counter_rom() -> Rom {
+        let mut rom = Rom::from_prg(&COUNTER_PROGRAM, 0x8000);
+
+        rom.prg_rom[0x7FFA .. 0x7FFC].copy_from_slice(&0x800Bu16.to_le_bytes());
+
+        rom
+    }
+
+    fn counter_action(skip_frames: u64) -> TakeAction {
+        TakeAction {
+            skip_frames,
+            memory_requests: HashMap::from([("frames".to_string(), 0x10)]),
+            ..TakeAction::default()
+        }
+    }
+
+    #[test]
+    fn memory_values_match_frame() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        for skip_frames in [1, 1, 3, 1, 5] {
+            let result = instance.take_action(&counter_action(skip_frames), &states);
+            let contents = result.frame.unwrap();
+
+            // Read as the frame completed, before its own NMI handler ran.
+            assert_eq!(contents.memory_values["frames"] as u64, contents.sequence - 1);
+        }
+
+        assert_eq!(instance.frame_sequence, 11);
+    }
+
+    #[test]
+    fn server_info_flags_match_rom() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.flags.mapper = 1;
+        rom.flags.mirroring = RomMirroring::Vertical;
+        rom.flags.battery_ram = true;
+
+        let rom = Rom::load(&rom.to_ines_bytes()).unwrap();
+        let info = server_info(&rom);
+
+        assert_eq!(info.server, SERVER_NAME);
+        assert_eq!(info.flags, Some(RomFlags {
+            mapper: 1,
+            mirroring: Mirroring::Vertical as i32,
+            battery_ram: true,
+            four_screen: false,
+            submapper: 0,
+        }));
+
+        let mut rom = rom;
+
+        rom.flags.mapper = 2;
+        rom.flags.submapper = 2;
+        rom.flags.four_screen = true;
+        rom.flags.battery_ram = false;
+
+        let flags = server_info(&rom).flags.unwrap();
+
+        assert_eq!((flags.mapper, flags.submapper, flags.four_screen, flags.battery_ram), (2, 2, true, false));
+    }
+
+    #[test]
+    fn play_script_matches_single_actions() {
+        let rom = replay_rom();
+        let mut scripted = replay_instance(&rom);
+
+        assert!(scripted.run_frames(10, ControllerFlags::empty(), None).unwrap());
+
+        let state = scripted.save_state();
+
+        let inputs: Vec<ControllerInput> = (0 .. 30u8)
+            .map(|i| ButtonState::from(ControllerFlags::from_bits_retain(i.wrapping_mul(53))).into())
+            .collect();
+
+        let memory_requests = HashMap::from([("buttons".to_string(), 0x00), ("sprite_x".to_string(), 0x203)]);
+
+        let result = scripted.play_script(&rom, &PlayScript {
+            initial_state: Some(state.clone()),
+            inputs: inputs.clone(),
+            render_final: true,
+            memory_requests: memory_requests.clone(),
+        });
+
+        assert!(result.error.is_none());
+
+        let scripted_contents = result.frame.unwrap();
+
+        let mut stepped = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        stepped.restore(&rom, &state).unwrap();
+
+        let mut stepped_contents = None;
+
+        for input in inputs {
+            let result = stepped.take_action(&TakeAction {
+                input: Some(input),
+                skip_frames: 1,
+                memory_requests: memory_requests.clone(),
+                ..TakeAction::default()
+            }, &states);
+
+            stepped_contents = result.frame;
+        }
+
+        let stepped_contents = stepped_contents.unwrap();
+
+        assert_eq!(scripted.cpu.memory.cycles, stepped.cpu.memory.cycles);
+        assert_eq!(scripted_contents.memory_values, stepped_contents.memory_values);
+        assert!(!scripted_contents.frame.is_empty());
+        assert!(scripted_contents.frame == stepped_contents.frame, "final frames differ");
+    }
+
+    #[test]
+    fn zero_skip_frames_only_holds_input() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        instance.take_action(&counter_action(2), &states);
+
+        let (sequence, cycles) = (instance.frame_sequence, instance.cpu.memory.cycles);
+        let frame = instance.frame.frame.to_vec();
+
+        let result = instance.take_action(&TakeAction {
+            input: Some(ButtonState::from(ControllerFlags::A | ControllerFlags::LEFT).into()),
+            ..counter_action(0)
+        }, &states);
+
+        let contents = result.frame.unwrap();
+
+        assert!(result.error.is_none() && !result.budget_exceeded);
+        assert_eq!((contents.sequence, instance.frame_sequence), (sequence, sequence));
+        assert_eq!(instance.cpu.memory.cycles, cycles);
+        assert!(contents.frame == frame);
+        assert_eq!(instance.cpu.memory.controllers.0.flags().bits(), (ControllerFlags::A | ControllerFlags::LEFT).bits());
+    }
+
+    #[test]
+    fn name_table_observation_matches_writes() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+
+        let memory = &mut instance.cpu.memory.ppu.memory;
+
+        memory.write(0x2000 + 2 * 32 + 3, 0x42).unwrap();
+        memory.write(0x2000 + 29 * 32 + 31, 0x7E).unwrap();
+        // Top left quadrant of the attribute block covering columns 4-7, rows 0-3.
+        memory.write(0x23C1, 0b11).unwrap();
+
+        let request = GetFrame { include_name_table: true, ..GetFrame::default() };
+        let name_table = instance.capture((&request).into()).name_table.unwrap();
+
+        assert_eq!(name_table.tiles.len(), 32 * 30);
+
+        let mut tiles = vec![0; 32 * 30];
+
+        tiles[2 * 32 + 3] = 0x42;
+        tiles[29 * 32 + 31] = 0x7E;
+
+        assert_eq!(name_table.tiles, tiles);
+
+        let mut palettes = vec![0; 32 * 30];
+
+        for (col, row) in [(4, 0), (5, 0), (4, 1), (5, 1)] {
+            palettes[row * 32 + col] = 3;
+        }
+
+        assert_eq!(name_table.palettes, palettes);
+
+        // One tile of horizontal scroll moves the grid left.
+        instance.cpu.memory.pass_get(0x2002).unwrap();
+        instance.write_memory(&writes(&[(0x2005, 8), (0x2005, 0)])).unwrap();
+
+        let name_table = instance.capture((&request).into()).name_table.unwrap();
+
+        assert_eq!(name_table.tiles[2 * 32 + 2], 0x42);
+        assert_eq!(name_table.tiles[29 * 32 + 30], 0x7E);
+    }
+
+    #[test]
+    fn controller_input_round_trip() {
+        for bits in 0 ..= u8::MAX {
+            let input = ControllerInput::from(ButtonState::from(ControllerFlags::from_bits_retain(bits)));
+
+            assert_eq!(ControllerFlags::from(&input).bits(), bits);
+        }
+
+        let start = ControllerInput { start: true, ..ControllerInput::default() };
+
+        assert_eq!(ControllerFlags::from(&start).bits(), ControllerFlags::START.bits());
+    }
+
+    #[test]
+    fn get_frame_skips_seen_frames() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        instance.take_action(&counter_action(3), &states);
+
+        let sequence = instance.frame_sequence;
+        let request = |newer_than| GetFrame { newer_than, ..GetFrame::default() };
+
+        assert!(instance.get_frame(&request(None)).frame.is_some());
+        assert!(instance.get_frame(&request(Some(sequence))).frame.is_none());
+
+        let previous = instance.get_frame(&request(Some(sequence - 1))).frame.unwrap();
+
+        assert_eq!(previous.sequence, sequence);
+
+        instance.take_action(&counter_action(1), &states);
+
+        assert!(instance.get_frame(&request(Some(sequence))).frame.is_some());
+    }
+
+    #[test]
+    fn sprite_observation_after_set_oam() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+
+        let mut oam = [0xFF; 256];
+
+        oam[.. 8].copy_from_slice(&[0x20, 0x01, 0b00000010, 0x30, 0x48, 0x05, 0b11000001, 0xC8]);
+
+        instance.cpu.memory.set_oam(oam);
+
+        let request = GetFrame { include_sprites: true, ..GetFrame::default() };
+        let sprites = instance.capture((&request).into()).sprites;
+
+        assert_eq!(sprites.len(), 64);
+        assert_eq!(sprites[0], SpriteObservation { x: 0x30, y: 0x20, tile: 0x01, attributes: 0b00000010 });
+        assert_eq!(sprites[1], SpriteObservation { x: 0xC8, y: 0x48, tile: 0x05, attributes: 0b11000001 });
+        assert_eq!((sprites[63].x, sprites[63].y), (0xFF, 0xFF));
+    }
+
+    #[test]
+    fn prepared_writes_reach_the_game() {
+        let program = [
+            0x2C, 0x02, 0x20,       // BIT $2002
+            0xA9, 0x80,             // LDA #$80
+            0x8D, 0x00, 0x20,       // STA $2000
+            0xA5, 0x20,             // loop: LDA $20
+            0x85, 0x21,             // STA $21
+            0x4C, 0x08, 0x80,       // JMP loop
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        let request = PrepareAction {
+            writes: writes(&[(0x20, 0x5A)]),
+            action: Some(TakeAction {
+                skip_frames: 1,
+                memory_requests: HashMap::from([("copy".to_string(), 0x21)]),
+                ..TakeAction::default()
+            }),
+        };
+
+        let result = instance.prepare_action(&request, &states);
+
+        assert_eq!(result.frame.unwrap().memory_values["copy"], 0x5A);
+
+        // A failed write leaves the instance where it was.
+        let cycles = instance.cpu.memory.cycles;
+
+        let failed = PrepareAction { writes: writes(&[(0x5000, 1)]), ..request };
+
+        assert!(instance.prepare_action(&failed, &states).error.is_some());
+        assert_eq!(instance.cpu.memory.cycles, cycles);
+    }
+
+    #[test]
+    fn frame_cap_rejects_large_requests() {
+        let rom = counter_rom();
+        let mut instance = NesInstance::new(&rom, ServerOptions { max_frames_per_action: 5 });
+        let states = StreamStates::default();
+
+        instance.cpu.memory.ppu.warmup = false;
+
+        let cycles = instance.cpu.memory.cycles;
+        let result = instance.take_action(&counter_action(6), &states);
+
+        assert!(result.frame.is_none());
+        assert!(result.error.unwrap().message.contains("limit is 5"));
+        assert_eq!((instance.cpu.memory.cycles, instance.frame_sequence), (cycles, 0));
+
+        let script = PlayScript { inputs: vec![ControllerInput::default(); 6], ..PlayScript::default() };
+
+        assert!(instance.play_script(&rom, &script).error.is_some());
+
+        let result = instance.take_action(&counter_action(5), &states);
+
+        assert!(result.error.is_none());
+        assert_eq!(instance.frame_sequence, 5);
+    }
+
+    #[test]
+    fn custom_palette_changes_pixels() {
+        let rom = replay_rom();
+
+        // Entry $00 (the backdrop here) becomes (1, 2, 3), the rest grey.
+        let mut data = vec![0x80; 0x40 * 3];
+
+        data[.. 3].copy_from_slice(&[1, 2, 3]);
+
+        let pixels: Vec<Vec<u8>> = [None, Some(data)].into_iter()
+            .map(|data| {
+                let mut instance = replay_instance(&rom);
+
+                instance.cpu.memory.ppu.memory.write(0x3F00, 0x00).unwrap();
+
+                if let Some(data) = data {
+                    instance.renderer.palette = parse_palette(&data).unwrap();
+                }
+
+                assert!(instance.run_frames(2, ControllerFlags::empty(), None).unwrap());
+
+                instance.frame.crop(128, 200, 1, 1)
+            })
+            .collect();
+
+        assert_eq!(pixels[0], [98, 98, 98, 255]);
+        assert_eq!(pixels[1], [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn low_budget_returns_early() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        let cycles = instance.cpu.memory.cycles;
+
+        let result = instance.take_action(&TakeAction {
+            cycle_budget: Some(1000),
+            ..counter_action(2)
+        }, &states);
+
+        assert!(result.budget_exceeded);
+        assert!(result.error.is_none());
+
+        // Stopped within an instruction of the budget, with no frame completed.
+        let spent = instance.cpu.memory.cycles - cycles;
+
+        assert!((1000 .. 1010).contains(&spent), "{spent} cycles");
+        assert_eq!(result.frame.unwrap().sequence, 0);
+
+        let result = instance.take_action(&TakeAction {
+            cycle_budget: Some(NES_FRAME_CYCLES * 3),
+            ..counter_action(2)
+        }, &states);
+
+        assert!(!result.budget_exceeded);
+        assert_eq!(instance.frame_sequence, 2);
+    }
+
+    #[test]
+    fn symbols_are_observed() {
+        let rom = counter_rom();
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        // As registered through InitializeRequest.symbols.
+        instance.symbols = HashMap::from([("frames".to_string(), 0x10), ("vector".to_string(), 0xFFFA)]);
+
+        let action = TakeAction { skip_frames: 3, ..TakeAction::default() };
+        let values = instance.take_action(&action, &states).frame.unwrap().memory_values;
+
+        assert_eq!(values, HashMap::from([("frames".to_string(), 2), ("vector".to_string(), 0x0B)]));
+
+        // Request names win over symbols.
+        let request = GetFrame {
+            memory_requests: HashMap::from([("vector".to_string(), 0xFFFB)]),
+            ..GetFrame::default()
+        };
+
+        let values = instance.capture((&request).into()).memory_values;
+
+        assert_eq!((values["frames"], values["vector"]), (2, 0x80));
+    }
+
+    #[test]
+    fn motion_follows_a_moved_sprite() {
+        let mut rom = counter_rom();
+
+        rom.chr_rom[16 .. 24].fill(0xFF);
+
+        let mut instance = replay_instance(&rom);
+        let states = StreamStates::default();
+
+        let ppu = &mut instance.cpu.memory.ppu;
+
+        ppu.registers.mask.show_sprites = true;
+        ppu.memory.write(0x3F00, 0x0F).unwrap();
+        ppu.memory.write(0x3F11, 0x30).unwrap();
+
+        let sprite_at = |x: u8| {
+            let mut oam = [0xFF; 256];
+
+            oam[.. 4].copy_from_slice(&[40, 1, 0, x]);
+
+            oam
+        };
+
+        instance.cpu.memory.set_oam(sprite_at(40));
+
+        let action = TakeAction { include_motion: true, ..counter_action(1) };
+
+        instance.take_action(&action, &states);
+
+        let motion = instance.take_action(&action, &states).frame.unwrap().motion;
+
+        assert_eq!(motion.len(), NES_WIDTH * NES_HEIGHT);
+        assert!(motion.iter().all(|pixel| *pixel == 0), "static scene has motion");
+
+        instance.cpu.memory.set_oam(sprite_at(80));
+
+        let motion = instance.take_action(&action, &states).frame.unwrap().motion;
+        let at = |x: usize, y: usize| motion[y * NES_WIDTH + x];
+
+        // Gone from x 40, drawn at x 80, rows 41..49.
+        assert_ne!(at(44, 44), 0);
+        assert_ne!(at(84, 44), 0);
+        assert_eq!(at(60, 44), 0);
+        assert_eq!(at(44, 100), 0);
+    }
+
+    #[test]
+    fn layers_hide_the_other_layer() {
+        let mut rom = counter_rom();
+
+        // Tile 1 opaque in both pattern tables, the background draws from $1000.
+        for table in [0, 0x1000] {
+            rom.chr_rom[table + 16 .. table + 24].fill(0xFF);
+        }
+
+        let states = StreamStates::default();
+
+        // Pixels at the background tile, the sprite and the backdrop, for each layer mask.
+        let pixels: Vec<Vec<Vec<u8>>> = [Layers::All, Layers::Background, Layers::Sprites].into_iter()
+            .map(|layers| {
+                let mut instance = replay_instance(&rom);
+                let ppu = &mut instance.cpu.memory.ppu;
+
+                ppu.registers.mask.show_background = true;
+                ppu.registers.mask.show_sprites = true;
+
+                // Tile 1 at column 5, row 4 and sprite 0 at x 100, rows 100..108.
+                ppu.memory.write(0x2085, 0x01).unwrap();
+                ppu.memory.write(0x3F00, 0x0F).unwrap();
+                ppu.memory.write(0x3F01, 0x16).unwrap();
+                ppu.memory.write(0x3F11, 0x30).unwrap();
+
+                let mut oam = [0xFF; 256];
+
+                oam[.. 4].copy_from_slice(&[99, 1, 0, 100]);
+
+                instance.cpu.memory.set_oam(oam);
+
+                let action = TakeAction { layers: layers as i32, ..counter_action(2) };
+
+                assert!(instance.take_action(&action, &states).error.is_none());
+
+                [(44, 36), (104, 104), (200, 200)].into_iter()
+                    .map(|(x, y)| instance.frame.crop(x, y, 1, 1))
+                    .collect()
+            })
+            .collect();
+
+        let [background, sprite, backdrop] = [0, 1, 2].map(|index| pixels[0][index].clone());
+
+        assert!(background != backdrop && sprite != backdrop && background != sprite);
+
+        assert_eq!(pixels[1], [background.clone(), backdrop.clone(), backdrop.clone()]);
+        assert_eq!(pixels[2], [backdrop.clone(), sprite, backdrop]);
+    }
+}
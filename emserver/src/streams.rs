@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use crate::messages::StreamDetails;
+
+// Number of recent frames kept per stream for subscribers that fall behind.
+const STREAM_BUFFER_SIZE: usize = 32;
+
+#[derive(Default)]
+pub struct StreamBuffer {
+    last_sequence: u64,
+    frames: VecDeque<StreamDetails>
+}
+
+impl StreamBuffer {
+    // Frames are numbered from 1, sequence 0 tells subscribers there was nothing to send.
+    pub fn push(&mut self, mut details: StreamDetails) {
+        self.last_sequence += 1;
+
+        details.sequence = self.last_sequence;
+
+        if self.frames.len() >= STREAM_BUFFER_SIZE {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(details);
+    }
+
+    // Oldest frame after the cursor (or the latest frame if caught up).
+    // Subscribers that fall too far behind skip to the oldest frame still buffered.
+    pub fn next(&self, cursor: Option<u64>) -> Option<&StreamDetails> {
+        let unseen = self.frames.iter()
+            .find(|frame| cursor.is_none_or(|cursor| frame.sequence > cursor));
+
+        unseen.or(self.frames.back())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u8) -> StreamDetails {
+        StreamDetails { frame: vec![id], ..StreamDetails::default() }
+    }
+
+    // Reads until caught up, advancing the cursor like stream_instance does.
+    fn drain(buffer: &StreamBuffer, cursor: &mut Option<u64>) -> Vec<u8> {
+        let mut frames = vec![];
+
+        while let Some(details) = buffer.next(*cursor) {
+            if cursor.is_some_and(|cursor| details.sequence <= cursor) {
+                break
+            }
+
+            *cursor = Some(details.sequence);
+            frames.push(details.frame[0]);
+        }
+
+        frames
+    }
+
+    #[test]
+    fn subscribers_read_independently() {
+        let mut buffer = StreamBuffer::default();
+        let (mut first, mut second) = (None, None);
+
+        for id in 0 .. 3 {
+            buffer.push(frame(id));
+        }
+
+        assert_eq!(drain(&buffer, &mut first), vec![0, 1, 2]);
+
+        for id in 3 .. 5 {
+            buffer.push(frame(id));
+        }
+
+        assert_eq!(drain(&buffer, &mut first), vec![3, 4]);
+        assert_eq!(drain(&buffer, &mut second), vec![0, 1, 2, 3, 4]);
+
+        // Caught up subscribers get the latest frame again.
+        assert_eq!(buffer.next(first).unwrap().frame, vec![4]);
+    }
+
+    #[test]
+    fn first_frame_is_not_the_empty_marker() {
+        let mut buffer = StreamBuffer::default();
+
+        assert!(buffer.next(None).is_none());
+
+        buffer.push(frame(0));
+
+        assert_eq!(buffer.next(None).unwrap().sequence, 1);
+        assert_ne!(buffer.next(None).unwrap().sequence, StreamDetails::default().sequence);
+    }
+}
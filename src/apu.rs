@@ -0,0 +1,962 @@
+use serde_derive::{Deserialize, Serialize};
+
+// NTSC CPU clock, the APU runs in step with it.
+pub const CPU_CLOCK: u32 = 1_789_773;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// Noise timer periods in CPU cycles.
+const NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+// DMC output periods in CPU cycles.
+const DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+// Frame counter steps in CPU cycles, quarter frames clock envelopes, half frames also clock
+// length counters and sweeps.
+const QUARTER_FRAMES: [u64; 4] = [7457, 14913, 22371, 29829];
+const FOUR_STEP_PERIOD: u64 = 29830;
+const FIVE_STEP_HALF_FRAME: u64 = 37281;
+const FIVE_STEP_PERIOD: u64 = 37282;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+const CHANNEL_COUNT: usize = 5;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Envelope {
+    pub start: bool,
+    pub looping: bool,
+    pub constant: bool,
+    pub volume: u8,
+    pub divider: u8,
+    pub decay: u8,
+}
+
+impl Envelope {
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    pub reload: bool,
+    pub divider: u8,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Pulse {
+    // Pulse 1 negates with one's complement, so its sweep goes one lower.
+    pub ones_complement: bool,
+    pub enabled: bool,
+    pub duty: u8,
+    pub step: u8,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: u8,
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.envelope.looping = value & 0b00100000 != 0;
+                self.envelope.constant = value & 0b00010000 != 0;
+                self.envelope.volume = value & 0b1111;
+            }
+            1 => {
+                self.sweep.enabled = value & 0b10000000 != 0;
+                self.sweep.period = (value >> 4) & 0b111;
+                self.sweep.negate = value & 0b00001000 != 0;
+                self.sweep.shift = value & 0b111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+
+        if self.sweep.negate {
+            self.timer_period.saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    // Silenced while the period is too low or the sweep would push it out of range.
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    // Every APU cycle, which is every other CPU cycle.
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_half_frame(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.muted() || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Triangle {
+    pub enabled: bool,
+    // Also halts the length counter.
+    pub control: bool,
+    pub linear_period: u8,
+    pub linear: u8,
+    pub linear_reload: bool,
+    pub step: u8,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: u8,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0b10000000 != 0;
+                self.linear_period = value & 0b01111111;
+            }
+            1 => { /* Unused */ }
+            2 => self.timer_period = (self.timer_period & 0x700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    // Every CPU cycle. The sequence holds its position while either counter is zero.
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+
+            if self.linear > 0 && self.length > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_period;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_half_frame(&mut self) {
+        if self.length > 0 && !self.control {
+            self.length -= 1;
+        }
+    }
+
+    // Halting keeps the last level rather than dropping to zero, like the hardware.
+    // A constant level is silent, and avoids a pop.
+    fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.step as usize]
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Noise {
+    pub enabled: bool,
+    // Short mode taps bit 6 instead of bit 1, giving a metallic tone.
+    pub short_mode: bool,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub shift: u16,
+    pub length: u8,
+    pub envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise {
+            enabled: false,
+            short_mode: false,
+            timer_period: NOISE_PERIODS[0],
+            timer: 0,
+            // The shift register powers up as 1, all zeros would never change.
+            shift: 1,
+            length: 0,
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.envelope.looping = value & 0b00100000 != 0;
+                self.envelope.constant = value & 0b00010000 != 0;
+                self.envelope.volume = value & 0b1111;
+            }
+            1 => { /* Unused */ }
+            2 => {
+                self.short_mode = value & 0b10000000 != 0;
+                self.timer_period = NOISE_PERIODS[(value & 0b1111) as usize];
+            }
+            _ => {
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    // Every CPU cycle, the periods are in CPU cycles.
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_half_frame(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+// Delta modulation channel, plays 1-bit deltas fetched from CPU memory (see Memory::run_apu).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Dmc {
+    pub irq_enabled: bool,
+    pub looping: bool,
+    pub rate: u16,
+    pub timer: u16,
+    pub level: u8,
+    pub sample_address: u16,
+    pub sample_length: u16,
+    pub address: u16,
+    pub bytes_remaining: u16,
+    // Next byte for the output unit, refilled by a fetch once taken.
+    pub buffer: Option<u8>,
+    pub shift: u8,
+    pub bits_remaining: u8,
+    pub silence: bool,
+    pub irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Dmc {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            rate: DMC_RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0b10000000 != 0;
+                self.looping = value & 0b01000000 != 0;
+                self.rate = DMC_RATES[(value & 0b1111) as usize];
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = value & 0b01111111,
+            2 => self.sample_address = 0xC000 + value as u16 * 64,
+            _ => self.sample_length = value as u16 * 16 + 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    // Address of the next sample byte, while the buffer is waiting for it.
+    pub fn fetch_address(&self) -> Option<u16> {
+        (self.buffer.is_none() && self.bytes_remaining > 0).then_some(self.address)
+    }
+
+    // Delivers the byte read from fetch_address.
+    pub fn fill(&mut self, value: u8) {
+        self.buffer = Some(value);
+
+        // Wraps around to $8000, not $0000.
+        self.address = self.address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // Every CPU cycle, the rates are in CPU cycles.
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+
+            return
+        }
+
+        self.timer = self.rate - 1;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+
+            match self.buffer.take() {
+                Some(value) => {
+                    self.shift = value;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FrameCounter {
+    pub five_step: bool,
+    pub irq_inhibit: bool,
+    // Raised at the end of each 4-step sequence, cleared by reading $4015.
+    pub irq: bool,
+    // CPU cycles since the sequence last started.
+    pub cycle: u64,
+}
+
+pub struct Apu {
+    pub pulse: [Pulse; 2],
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    pub frame_counter: FrameCounter,
+    // CPU cycle the APU has caught up to, see run.
+    pub last_cycle: u64,
+    // Samples are only kept while this is set, nothing drains them otherwise.
+    pub collect_samples: bool,
+    pub sample_rate: u32,
+    samples: Vec<f32>,
+    sample_sum: f32,
+    sample_count: u32,
+    sample_phase: u32,
+    // Mixer switches for debugging, channels keep running while muted.
+    muted: [bool; CHANNEL_COUNT],
+}
+
+impl Default for Apu {
+    fn default() -> Apu {
+        Apu {
+            pulse: [
+                Pulse { ones_complement: true, ..Pulse::default() },
+                Pulse::default(),
+            ],
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            last_cycle: 0,
+            collect_samples: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            samples: vec![],
+            sample_sum: 0.0,
+            sample_count: 0,
+            sample_phase: 0,
+            muted: [false; CHANNEL_COUNT],
+        }
+    }
+}
+
+impl Apu {
+    fn clock_quarter_frame(&mut self) {
+        for pulse in &mut self.pulse {
+            pulse.envelope.clock();
+        }
+
+        self.triangle.clock_quarter_frame();
+        self.noise.envelope.clock();
+    }
+
+    fn clock_half_frame(&mut self) {
+        for pulse in &mut self.pulse {
+            pulse.clock_half_frame();
+        }
+
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        let cycle = self.frame_counter.cycle;
+
+        // 5-step mode skips the fourth step and ends on a later one instead.
+        let last_step = if self.frame_counter.five_step { FIVE_STEP_HALF_FRAME } else { QUARTER_FRAMES[3] };
+
+        if QUARTER_FRAMES[.. 3].contains(&cycle) || cycle == last_step {
+            self.clock_quarter_frame();
+        }
+
+        if cycle == QUARTER_FRAMES[1] || cycle == last_step {
+            self.clock_half_frame();
+        }
+
+        if !self.frame_counter.five_step && !self.frame_counter.irq_inhibit && cycle >= QUARTER_FRAMES[3] - 1 {
+            self.frame_counter.irq = true;
+        }
+
+        let period = if self.frame_counter.five_step { FIVE_STEP_PERIOD } else { FOUR_STEP_PERIOD };
+
+        self.frame_counter.cycle = (cycle + 1) % period;
+    }
+
+    fn step(&mut self, cycle: u64) {
+        self.clock_frame_counter();
+
+        if cycle.is_multiple_of(2) {
+            for pulse in &mut self.pulse {
+                pulse.clock_timer();
+            }
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        if self.collect_samples {
+            self.sample_sum += self.sample();
+            self.sample_count += 1;
+            self.sample_phase += self.sample_rate;
+
+            // Averages the CPU rate output over each sample period.
+            if self.sample_phase >= CPU_CLOCK {
+                self.sample_phase -= CPU_CLOCK;
+
+                self.samples.push(self.sample_sum / self.sample_count as f32);
+
+                self.sample_sum = 0.0;
+                self.sample_count = 0;
+            }
+        }
+    }
+
+    // Catches up to the CPU, call before touching the APU at that cycle.
+    // Stops early while the DMC waits on a fetch, Memory::run_apu serves those.
+    pub fn run(&mut self, cycle: u64) {
+        while self.last_cycle < cycle && self.dmc.fetch_address().is_none() {
+            self.step(self.last_cycle);
+
+            self.last_cycle += 1;
+        }
+    }
+
+    // $4000-$4017 except $4014 and $4016.
+    pub fn write(&mut self, address: u16, value: u8, cycle: u64) {
+        self.run(cycle);
+
+        match address {
+            0x4000..=0x4003 => self.pulse[0].write(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse[1].write(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write(address - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write(address - 0x4010, value),
+            0x4015 => {
+                self.pulse[0].set_enabled(value & 0b0001 != 0);
+                self.pulse[1].set_enabled(value & 0b0010 != 0);
+                self.triangle.set_enabled(value & 0b0100 != 0);
+                self.noise.set_enabled(value & 0b1000 != 0);
+                self.dmc.set_enabled(value & 0b10000 != 0);
+            }
+            0x4017 => {
+                self.frame_counter.five_step = value & 0b10000000 != 0;
+                self.frame_counter.irq_inhibit = value & 0b01000000 != 0;
+                self.frame_counter.cycle = 0;
+
+                if self.frame_counter.irq_inhibit {
+                    self.frame_counter.irq = false;
+                }
+
+                if self.frame_counter.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => { /* Not implemented yet */ }
+        }
+    }
+
+    // $4015, which channels still have length remaining and both IRQs. Reading clears the frame IRQ.
+    pub fn read_status(&mut self, cycle: u64) -> u8 {
+        self.run(cycle);
+
+        let status = (self.pulse[0].length > 0) as u8
+            | ((self.pulse[1].length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_counter.irq as u8) << 6
+            | (self.dmc.irq as u8) << 7;
+
+        self.frame_counter.irq = false;
+
+        status
+    }
+
+    // Level triggered like Mapper::irq_pending, as of the last run.
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.irq || self.dmc.irq
+    }
+
+    // Same as writing $4015 with zero.
+    pub fn reset(&mut self) {
+        for pulse in &mut self.pulse {
+            pulse.set_enabled(false);
+        }
+
+        self.triangle.set_enabled(false);
+        self.noise.set_enabled(false);
+        self.dmc.set_enabled(false);
+    }
+
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.muted[channel as usize] = !enabled;
+    }
+
+    fn channel_output(&self, channel: Channel) -> u8 {
+        if self.muted[channel as usize] {
+            return 0
+        }
+
+        match channel {
+            Channel::Pulse1 => self.pulse[0].output(),
+            Channel::Pulse2 => self.pulse[1].output(),
+            Channel::Triangle => self.triangle.output(),
+            Channel::Noise => self.noise.output(),
+            Channel::Dmc => self.dmc.output(),
+        }
+    }
+
+    // Mixed output right now, 0 to about 1, with the non-linear mixer approximation.
+    pub fn sample(&self) -> f32 {
+        let pulse = (self.channel_output(Channel::Pulse1) + self.channel_output(Channel::Pulse2)) as f32;
+
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let triangle = self.channel_output(Channel::Triangle) as f32;
+        let noise = self.channel_output(Channel::Noise) as f32;
+        let dmc = self.channel_output(Channel::Dmc) as f32;
+
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    // Hands over the samples collected so far (see collect_samples), at sample_rate.
+    // Cpu::step keeps the APU caught up, so call this between steps.
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pulse 1 at a constant volume of 15 and 50% duty, with timer period 0x80.
+    fn pulse_apu() -> Apu {
+        let mut apu = Apu::default();
+
+        apu.write(0x4015, 0b0001, 0);
+        apu.write(0x4000, 0b10111111, 0);
+        apu.write(0x4002, 0x80, 0);
+        apu.write(0x4003, 0x08, 0);
+
+        apu
+    }
+
+    // Loudest level the channel feeds the mixer over the next cycles.
+    fn peak(apu: &mut Apu, channel: Channel, cycles: u64) -> u8 {
+        let start = apu.last_cycle;
+
+        (start + 1 ..= start + cycles)
+            .map(|cycle| {
+                apu.run(cycle);
+
+                apu.channel_output(channel)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn muted_channel_keeps_running() {
+        let mut apu = pulse_apu();
+
+        assert_eq!(peak(&mut apu, Channel::Pulse1, 4000), 15);
+
+        apu.set_channel_enabled(Channel::Pulse1, false);
+
+        assert_eq!(peak(&mut apu, Channel::Pulse1, 4000), 0);
+        assert_eq!(apu.read_status(apu.last_cycle) & 0b0001, 0b0001);
+
+        apu.set_channel_enabled(Channel::Pulse1, true);
+
+        assert_eq!(peak(&mut apu, Channel::Pulse1, 4000), 15);
+    }
+
+    // Channel output on each of the next cycles.
+    fn levels(apu: &mut Apu, channel: Channel, cycles: u64) -> Vec<u8> {
+        let start = apu.last_cycle;
+
+        (start + 1 ..= start + cycles)
+            .map(|cycle| {
+                apu.run(cycle);
+
+                apu.channel_output(channel)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pulse_square_wave_period() {
+        let mut apu = pulse_apu();
+
+        let levels = levels(&mut apu, Channel::Pulse1, 10000);
+
+        assert!(levels.iter().all(|level| [0, 15].contains(level)));
+
+        let rising: Vec<usize> = levels.windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] == 0 && pair[1] == 15)
+            .map(|(i, _)| i + 1)
+            .collect();
+
+        // 8 steps of (0x80 + 1) APU cycles, two CPU cycles each, high for half of them.
+        let period = 8 * (0x80 + 1) * 2;
+
+        assert!(rising.len() >= 3);
+        assert!(rising.windows(2).all(|pair| pair[1] - pair[0] == period));
+        assert!(levels[rising[0] .. rising[0] + period / 2].iter().all(|level| *level == 15));
+        assert!(levels[rising[0] + period / 2 .. rising[1]].iter().all(|level| *level == 0));
+    }
+
+    // Pulse 1, pulse 2, triangle and noise all playing, past the first quarter frame so the
+    // triangle's linear counter is loaded. The triangle timer period is 10.
+    fn four_channel_apu() -> Apu {
+        let mut apu = Apu::default();
+
+        let writes = [
+            (0x4015, 0b1111),
+            (0x4000, 0b10111111), (0x4002, 0x80), (0x4003, 0x08),
+            (0x4004, 0b10111111), (0x4006, 0x80), (0x4007, 0x08),
+            (0x4008, 0b11111111), (0x400A, 10), (0x400B, 0x08),
+            (0x400C, 0b00111111), (0x400E, 0x04), (0x400F, 0x08),
+        ];
+
+        for (address, value) in writes {
+            apu.write(address, value, 0);
+        }
+
+        apu.run(8000);
+
+        apu
+    }
+
+    #[test]
+    fn triangle_steps() {
+        let mut apu = four_channel_apu();
+
+        let levels = levels(&mut apu, Channel::Triangle, 11 * 32 * 3);
+
+        let mut runs: Vec<(u8, usize)> = vec![];
+
+        for level in levels {
+            match runs.last_mut() {
+                Some((last, length)) if *last == level => *length += 1,
+                _ => runs.push((level, 1)),
+            }
+        }
+
+        // One step down or up every 11 cycles, repeating 0 and 15 at the turns.
+        assert!(runs.windows(2).all(|pair| pair[0].0.abs_diff(pair[1].0) == 1));
+        assert!(runs[1 .. runs.len() - 1].iter().all(|&(level, length)| {
+            length == if level == 0 || level == 15 { 22 } else { 11 }
+        }));
+
+        assert!(runs.iter().any(|run| run.0 == 0));
+        assert!(runs.iter().any(|run| run.0 == 15));
+    }
+
+    #[test]
+    fn status_disables_each_channel() {
+        let channels = [Channel::Pulse1, Channel::Pulse2, Channel::Triangle, Channel::Noise];
+
+        // Triangle output varies while it plays, the others reach a non-zero level.
+        let playing = |apu: &mut Apu, channel: Channel| {
+            let levels = levels(apu, channel, 4000);
+
+            levels.iter().any(|level| *level != levels[0])
+        };
+
+        let mut apu = four_channel_apu();
+
+        assert!(channels.iter().all(|channel| playing(&mut apu, *channel)));
+
+        for (bit, channel) in channels.iter().enumerate() {
+            let mut apu = four_channel_apu();
+
+            apu.write(0x4015, 0b1111 & !(1 << bit), apu.last_cycle);
+
+            assert_eq!(apu.read_status(apu.last_cycle) & 0b1111, 0b1111 & !(1 << bit));
+
+            for other in channels {
+                assert_eq!(playing(&mut apu, other), other != *channel, "{other:?} with {channel:?} disabled");
+            }
+        }
+    }
+
+    #[test]
+    fn frame_irq_set_and_cleared_on_read() {
+        let mut apu = Apu::default();
+
+        // 4-step mode with the IRQ enabled is the power on state.
+        apu.run(QUARTER_FRAMES[3] - 1);
+
+        assert!(!apu.irq_pending());
+
+        apu.run(QUARTER_FRAMES[3]);
+
+        assert!(apu.irq_pending());
+
+        assert_eq!(apu.read_status(apu.last_cycle) & 0b01000000, 0b01000000);
+        assert!(!apu.irq_pending());
+        assert_eq!(apu.read_status(apu.last_cycle) & 0b01000000, 0);
+
+        // Inhibited, or in 5-step mode, it never fires.
+        for mode in [0b01000000, 0b10000000] {
+            let mut apu = Apu::default();
+
+            apu.write(0x4017, mode, 0);
+            apu.run(FIVE_STEP_PERIOD * 2);
+
+            assert!(!apu.irq_pending());
+        }
+    }
+
+    #[test]
+    fn resampled_sample_count() {
+        let mut apu = pulse_apu();
+
+        apu.collect_samples = true;
+
+        // One second of CPU cycles.
+        apu.run(CPU_CLOCK as u64);
+
+        let samples = apu.drain_samples();
+
+        assert_eq!(samples.len(), DEFAULT_SAMPLE_RATE as usize);
+        assert!(samples.iter().any(|sample| *sample > 0.0));
+        assert!(apu.drain_samples().is_empty());
+
+        apu.sample_rate = 48_000;
+        apu.run(CPU_CLOCK as u64 * 3 / 2);
+
+        assert!(apu.drain_samples().len().abs_diff(24_000) <= 1);
+    }
+}
@@ -0,0 +1,100 @@
+// 2-bit color index of each pixel in an 8x8 tile, by row then column.
+pub type TilePixels = [[u8; 8]; 8];
+
+const TILE_SIZE: usize = 16;
+
+// read fetches a byte of pattern memory ($0000-$1FFF).
+pub fn decode_tile<F: FnMut(usize) -> u8>(mut read: F, tile: usize) -> TilePixels {
+    let base = tile * TILE_SIZE;
+
+    std::array::from_fn(|y| {
+        let plane_0 = read(base + y);
+        let plane_1 = read(base + y + 8);
+
+        std::array::from_fn(|x| {
+            let shift = 7 - x;
+
+            ((plane_0 >> shift) & 1) | (((plane_1 >> shift) & 1) << 1)
+        })
+    })
+}
+
+// Pattern table tiles, decoded from their bitplanes on first use.
+#[derive(Default)]
+pub struct ChrCache {
+    tiles: Vec<Option<TilePixels>>
+}
+
+impl ChrCache {
+    pub fn tile<F: FnMut(usize) -> u8>(&mut self, read: F, tile: usize) -> &TilePixels {
+        if self.tiles.len() <= tile {
+            self.tiles.resize(tile + 1, None);
+        }
+
+        self.tiles[tile].get_or_insert_with(|| decode_tile(read, tile))
+    }
+
+    // Call after writing to CHR at this address.
+    pub fn invalidate(&mut self, address: usize) {
+        if let Some(tile) = self.tiles.get_mut(address / TILE_SIZE) {
+            *tile = None
+        }
+    }
+
+    // Call after the whole pattern table changes, e.g. on a bank switch.
+    pub fn clear(&mut self) {
+        self.tiles.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::PpuMemory;
+    use crate::rom::Rom;
+    use super::*;
+
+    // One pixel straight from the bitplanes.
+    fn direct_pixel(pattern: &[u8], tile: usize, x: usize, y: usize) -> u8 {
+        let low = (pattern[tile * 16 + y] >> (7 - x)) & 1;
+        let high = (pattern[tile * 16 + y + 8] >> (7 - x)) & 1;
+
+        low | (high << 1)
+    }
+
+    #[test]
+    fn cache_matches_bitplanes() {
+        let pattern: Vec<u8> = (0 .. 0x2000).map(|i| (i * 37 % 253) as u8).collect();
+        let mut cache = ChrCache::default();
+
+        for tile in [0, 1, 77, 511] {
+            let pixels = *cache.tile(|address| pattern[address], tile);
+
+            for (y, row) in pixels.iter().enumerate() {
+                for (x, pixel) in row.iter().enumerate() {
+                    assert_eq!(*pixel, direct_pixel(&pattern, tile, x, y), "tile {tile} at ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chr_writes_invalidate() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.chr_rom.clear();
+
+        let mut memory = PpuMemory::new(&rom);
+
+        assert_eq!(memory.tile(2)[0], [0; 8]);
+
+        // Low plane of row 0 in tile 2.
+        memory.write(0x0020, 0b10000001).unwrap();
+
+        assert_eq!(memory.tile(2)[0], [1, 0, 0, 0, 0, 0, 0, 1]);
+
+        // High plane of the same row.
+        memory.write(0x0028, 0b10000000).unwrap();
+
+        assert_eq!(memory.tile(2)[0], [3, 0, 0, 0, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::mapper::Mapper;
+use crate::rom::Rom;
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// Mapper 3, PRG is fixed like NROM and any write to $8000-$FFFF selects the 8KB CHR bank.
+pub struct CnRom<'a> {
+    rom: &'a Rom,
+    bank: u8,
+    // Boards without a write enable on the ROM see the written value ANDed with the ROM byte there.
+    bus_conflicts: bool,
+}
+
+impl<'a> CnRom<'a> {
+    pub fn new(rom: &'a Rom, bus_conflicts: bool) -> CnRom<'a> {
+        CnRom { rom, bank: 0, bus_conflicts }
+    }
+}
+
+impl<'a> Mapper for CnRom<'a> {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                let target = (address - 0x8000) as usize % self.rom.prg_rom.len();
+
+                Some(self.rom.prg_rom[target])
+            }
+            _ => None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8, _: u64) -> bool {
+        if address < 0x8000 {
+            return false
+        }
+
+        self.bank = match self.cpu_read(address) {
+            Some(rom) if self.bus_conflicts => value & rom,
+            _ => value,
+        };
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if self.rom.chr_rom.is_empty() {
+            return None
+        }
+
+        let offset = self.bank as usize * CHR_BANK_SIZE + address as usize % CHR_BANK_SIZE;
+
+        Some(self.rom.chr_rom[offset % self.rom.chr_rom.len()])
+    }
+
+    fn ppu_write(&mut self, _: u16, _: u8) -> bool {
+        false
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        if let Some(bank) = state.first() {
+            self.bank = *bank
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four 8KB CHR banks, each filled with its own index.
+    fn banked_rom() -> Rom {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.flags.mapper = 3;
+        rom.chr_rom = (0 .. 4 * CHR_BANK_SIZE).map(|i| (i / CHR_BANK_SIZE) as u8).collect();
+
+        rom
+    }
+
+    #[test]
+    fn switches_chr_bank() {
+        let rom = banked_rom();
+        let mut mapper = CnRom::new(&rom, false);
+
+        assert_eq!(mapper.ppu_read(0x1000), Some(0));
+
+        mapper.cpu_write(0x8000, 3, 0);
+
+        assert_eq!(mapper.ppu_read(0x1000), Some(3));
+    }
+
+    #[test]
+    fn bus_conflict_ands_with_rom() {
+        let mut rom = banked_rom();
+
+        rom.prg_rom[0x20] = 0x05;
+
+        let mut mapper = CnRom::new(&rom, true);
+
+        mapper.cpu_write(0x8020, 0x03, 0);
+
+        assert_eq!(mapper.ppu_read(0x0000), Some(1));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::controller::NoController;
+use crate::interpreter::CpuError;
+use crate::nes::Nes;
+use crate::renderer::RenderedFrame;
+use crate::rom::{fnv1a, Rom};
+use crate::software::SoftwareRenderer;
+
+pub struct FrameDiff {
+    pub frame: usize,
+    pub hashes: (u64, u64),
+    // Number of pixels with any differing channel.
+    pub differing_pixels: usize,
+}
+
+impl FrameDiff {
+    pub fn matches(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+// Stable across builds, so reports can be compared over time.
+pub fn hash_frame(frame: &RenderedFrame) -> u64 {
+    fnv1a(&frame.frame)
+}
+
+// Runs the ROM once per renderer with no input, comparing each of the first frames.
+// The software renderer is the only one in this tree, so this compares two of its
+// configurations (e.g. the sprite limit), not software against hardware.
+pub fn compare_renderers(
+    rom: &Rom, frames: usize, a: SoftwareRenderer, b: SoftwareRenderer
+) -> Result<Vec<FrameDiff>, CpuError> {
+    let mut nes_a = Nes::new(rom, (NoController, NoController));
+    let mut nes_b = Nes::new(rom, (NoController, NoController));
+
+    nes_a.renderer = a;
+    nes_b.renderer = b;
+
+    (0 .. frames).map(|frame| {
+        let frame_a = nes_a.step_frame()?;
+        let frame_b = nes_b.step_frame()?;
+
+        let differing_pixels = frame_a.frame.chunks(4)
+            .zip(frame_b.frame.chunks(4))
+            .filter(|(x, y)| x != y)
+            .count();
+
+        Ok(FrameDiff {
+            frame,
+            hashes: (hash_frame(frame_a), hash_frame(frame_b)),
+            differing_pixels,
+        })
+    }).collect()
+}
+
+// One line per frame, for tracking differences between configurations over time.
+pub fn format_report(diffs: &[FrameDiff]) -> String {
+    let mismatched = diffs.iter().filter(|diff| !diff.matches()).count();
+
+    let mut report = format!("{mismatched}/{} frames differ\n", diffs.len());
+
+    for diff in diffs {
+        let (a, b) = diff.hashes;
+
+        report += &format!("{:>5} {a:016X} {b:016X} {}\n", diff.frame, diff.differing_pixels);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Twelve sprites of tile 1 on lines $51-$58, 16 pixels apart, copied to OAM by DMA
+    // each vblank once the PPU has warmed up. Only the first eight fit on a real scanline.
+    const SPRITE_ROW_PROGRAM: [u8; 73] = [
+        0xA2, 0x00,             // LDX #$00
+        0xA9, 0x50,             // init: LDA #$50
+        0x9D, 0x00, 0x02,       // STA $0200,X
+        0xA9, 0x01,             // LDA #$01
+        0x9D, 0x01, 0x02,       // STA $0201,X
+        0xA9, 0x00,             // LDA #$00
+        0x9D, 0x02, 0x02,       // STA $0202,X
+        0x8A,                   // TXA
+        0x0A,                   // ASL A
+        0x0A,                   // ASL A
+        0x9D, 0x03, 0x02,       // STA $0203,X
+        0xE8, 0xE8, 0xE8, 0xE8, // INX x4
+        0xE0, 0x30,             // CPX #$30
+        0xD0, 0xE3,             // BNE init
+        0xA9, 0xFF,             // hide: LDA #$FF
+        0x9D, 0x00, 0x02,       // STA $0200,X
+        0xE8,                   // INX
+        0xD0, 0xF8,             // BNE hide
+        0x2C, 0x02, 0x20,       // BIT $2002
+        0x10, 0xFB,             // BPL $8027
+        0x2C, 0x02, 0x20,       // BIT $2002
+        0x10, 0xFB,             // BPL $802C
+        0x2C, 0x02, 0x20,       // BIT $2002
+        0x10, 0xFB,             // BPL $8031
+        0xA9, 0x14,             // LDA #$14
+        0x8D, 0x01, 0x20,       // STA $2001
+        0xA9, 0x80,             // LDA #$80
+        0x8D, 0x00, 0x20,       // STA $2000
+        0x4C, 0x40, 0x80,       // JMP $8040
+        0xA9, 0x02,             // nmi: LDA #$02
+        0x8D, 0x14, 0x40,       // STA $4014
+        0x40,                   // RTI
+    ];
+
+    #[test]
+    fn sprite_limit_baseline() {
+        let mut rom = Rom::from_prg(&SPRITE_ROW_PROGRAM, 0x8000);
+
+        rom.prg_rom[0x7FFA .. 0x7FFC].copy_from_slice(&0x8043u16.to_le_bytes());
+        rom.chr_rom[16 .. 24].fill(0xFF);
+
+        let mut unlimited = SoftwareRenderer::new();
+
+        unlimited.unlimited_sprites = true;
+
+        let diffs = compare_renderers(&rom, 8, SoftwareRenderer::new(), unlimited).unwrap();
+
+        assert_eq!(format_report(&diffs), include_str!("compare_baseline.txt"));
+    }
+
+    #[test]
+    fn frames_without_nmi() {
+        let rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+
+        let diffs = compare_renderers(&rom, 3, SoftwareRenderer::new(), SoftwareRenderer::new()).unwrap();
+
+        assert_eq!(diffs.len(), 3);
+        assert!(diffs.iter().all(|diff| diff.matches()));
+    }
+}
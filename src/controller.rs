@@ -2,6 +2,14 @@ use bitflags::bitflags;
 
 pub trait Controller {
     fn read(&mut self, cycle: u64) -> u8;
+
+    // Called when the game writes to $4016, bit 0 is the strobe.
+    fn write(&mut self, _value: u8) { }
+
+    // Moves the shift position so the next read returns button number cycles (1 past the eighth).
+    // Only for controllers that keep their own position, others are clocked by
+    // Memory::controller_cycles. Memory::set_controller_cycles sets both.
+    fn set_cycles(&mut self, _cycles: u64) { }
 }
 
 #[derive(Default)]
@@ -12,7 +20,7 @@ impl Controller for NoController {
 }
 
 
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 pub struct ControllerFlags(u8);
 
 bitflags! {
@@ -28,12 +36,92 @@ bitflags! {
     }
 }
 
+// Plain field form of ControllerFlags, for frontends and wire formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ButtonState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl From<ButtonState> for ControllerFlags {
+    fn from(value: ButtonState) -> Self {
+        let mut flags = ControllerFlags::empty();
+
+        flags.set(ControllerFlags::A, value.a);
+        flags.set(ControllerFlags::B, value.b);
+        flags.set(ControllerFlags::SELECT, value.select);
+        flags.set(ControllerFlags::START, value.start);
+        flags.set(ControllerFlags::UP, value.up);
+        flags.set(ControllerFlags::DOWN, value.down);
+        flags.set(ControllerFlags::LEFT, value.left);
+        flags.set(ControllerFlags::RIGHT, value.right);
+
+        flags
+    }
+}
+
+impl From<ControllerFlags> for ButtonState {
+    fn from(value: ControllerFlags) -> Self {
+        ButtonState {
+            a: value.contains(ControllerFlags::A),
+            b: value.contains(ControllerFlags::B),
+            select: value.contains(ControllerFlags::SELECT),
+            start: value.contains(ControllerFlags::START),
+            up: value.contains(ControllerFlags::UP),
+            down: value.contains(ControllerFlags::DOWN),
+            left: value.contains(ControllerFlags::LEFT),
+            right: value.contains(ControllerFlags::RIGHT),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Latch {
+    snapshot: ControllerFlags,
+    shift: u8,
+    // Forgiving mode, latch again on the first read after any write.
+    auto_latch: bool,
+    pending: bool
+}
+
 #[derive(Default)]
 pub struct GenericController {
-    flags: ControllerFlags
+    flags: ControllerFlags,
+    latch: Option<Latch>
 }
 
 impl GenericController {
+    // Snapshots the buttons on strobe and shifts them out independent of the read clock.
+    pub fn latched() -> GenericController {
+        GenericController {
+            flags: ControllerFlags::empty(),
+            latch: Some(Latch::default())
+        }
+    }
+
+    // Like latched, but games that never strobe still see fresh input.
+    pub fn forgiving() -> GenericController {
+        GenericController {
+            flags: ControllerFlags::empty(),
+            latch: Some(Latch { auto_latch: true, pending: true, ..Latch::default() })
+        }
+    }
+
+    // A latched controller part way through shifting out its snapshot, see set_cycles.
+    pub fn latched_at(cycles: u64) -> GenericController {
+        let mut controller = GenericController::latched();
+
+        controller.set_cycles(cycles);
+
+        controller
+    }
+
     pub fn press(&mut self, flags: ControllerFlags) {
         self.flags = flags
     }
@@ -41,14 +129,156 @@ impl GenericController {
     pub fn set(&mut self, flag: ControllerFlags, value: bool) {
         self.flags.set(flag, value)
     }
+
+    pub fn flags(&self) -> ControllerFlags {
+        self.flags
+    }
 }
 
 impl Controller for GenericController {
     fn read(&mut self, clock: u64) -> u8 {
+        if let Some(latch) = &mut self.latch {
+            if latch.pending {
+                latch.snapshot = self.flags;
+                latch.shift = 0;
+                latch.pending = false;
+            }
+
+            // Official controllers report 1 after all eight buttons are read.
+            let value = if latch.shift >= 8 {
+                true
+            } else {
+                latch.snapshot.0 & (1 << latch.shift) != 0
+            };
+
+            latch.shift = latch.shift.saturating_add(1);
+
+            return if value { 1 } else { 0 }
+        }
+
         let clock = clock % 8;
 
         let value = self.flags.0 & (1 << clock) != 0;
 
         if value { 1 } else { 0 }
     }
+
+    fn write(&mut self, value: u8) {
+        if let Some(latch) = &mut self.latch {
+            if value & 1 != 0 {
+                latch.snapshot = self.flags;
+                latch.shift = 0;
+            } else if latch.auto_latch {
+                latch.pending = true;
+            }
+        }
+    }
+
+    // Latched controllers only, unlatched ones read at Memory::controller_cycles.
+    fn set_cycles(&mut self, cycles: u64) {
+        if let Some(latch) = &mut self.latch {
+            latch.shift = cycles.min(u8::MAX as u64) as u8;
+            latch.pending = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One read per clock, the first button in bit 0.
+    fn read_byte(controller: &mut GenericController, clocks: impl IntoIterator<Item=u64>) -> u8 {
+        clocks.into_iter()
+            .enumerate()
+            .fold(0, |byte, (bit, clock)| byte | controller.read(clock) << bit)
+    }
+
+    fn strobe(controller: &mut GenericController) {
+        controller.write(1);
+        controller.write(0);
+    }
+
+    #[test]
+    fn latched_reads_ignore_clock() {
+        let mut controller = GenericController::latched();
+
+        controller.press(ControllerFlags::A | ControllerFlags::START | ControllerFlags::RIGHT);
+        strobe(&mut controller);
+
+        // Presses after the strobe wait for the next one.
+        controller.press(ControllerFlags::B);
+
+        assert_eq!(read_byte(&mut controller, [5, 5, 100, 3, 0, 7, 7, 42]), 0b10001001);
+        assert_eq!(controller.read(1), 1);
+
+        strobe(&mut controller);
+
+        assert_eq!(read_byte(&mut controller, [0; 8]), ControllerFlags::B.bits());
+    }
+
+    #[test]
+    fn forgiving_reads_without_strobe() {
+        let mut strict = GenericController::latched();
+        let mut forgiving = GenericController::forgiving();
+
+        for controller in [&mut strict, &mut forgiving] {
+            controller.press(ControllerFlags::A | ControllerFlags::START);
+        }
+
+        // Never strobed, the strict controller still holds its empty power on snapshot.
+        assert_eq!(read_byte(&mut strict, [0; 8]), 0);
+        assert_eq!(read_byte(&mut forgiving, [0; 8]), 0b00001001);
+
+        // A write without the strobe bit, only the forgiving controller picks up the new input.
+        for controller in [&mut strict, &mut forgiving] {
+            controller.press(ControllerFlags::B);
+            controller.write(0);
+        }
+
+        assert_eq!(read_byte(&mut strict, [0; 8]), 0xFF);
+        assert_eq!(read_byte(&mut forgiving, [0; 8]), ControllerFlags::B.bits());
+    }
+
+    #[test]
+    fn button_state_round_trip() {
+        let a = ButtonState { a: true, ..ButtonState::default() };
+
+        assert_eq!(ControllerFlags::from(a).bits(), ControllerFlags::A.bits());
+
+        let right = ButtonState { right: true, ..ButtonState::default() };
+
+        assert_eq!(ControllerFlags::from(right).bits(), ControllerFlags::RIGHT.bits());
+
+        for bits in 0 ..= u8::MAX {
+            let flags = ControllerFlags::from_bits_retain(bits);
+            let state = ButtonState::from(flags);
+
+            assert_eq!(ControllerFlags::from(state).bits(), bits);
+        }
+    }
+
+    #[test]
+    fn set_cycles_picks_the_next_button() {
+        let mut controller = GenericController::latched();
+
+        controller.press(ControllerFlags::SELECT | ControllerFlags::DOWN);
+        strobe(&mut controller);
+
+        // Select is button 2 and down button 5, reading from 2 gives 1, 0, 0, 1.
+        controller.set_cycles(2);
+
+        assert_eq!(read_byte(&mut controller, [0; 4]), 0b1001);
+
+        controller.set_cycles(8);
+
+        assert_eq!(controller.read(0), 1);
+
+        // Constructed part way through, with an empty power on snapshot.
+        let mut controller = GenericController::latched_at(6);
+
+        controller.press(ControllerFlags::LEFT);
+
+        assert_eq!(read_byte(&mut controller, [0; 3]), 0b100);
+    }
 }
@@ -35,10 +35,19 @@ pub struct Vectors {
     pub interrupt: u16
 }
 
+pub type OpcodeHistogram = [u64; 256];
+
+// Called with the PC and opcode of an unimplemented instruction, before step errors.
+pub type InvalidOpHook = Box<dyn FnMut(u16, u8) + Send>;
+
 pub struct Cpu<'a, C1: Controller, C2: Controller> {
     pub vectors: Vectors,
     pub registers: Registers,
-    pub memory: Memory<'a, C1, C2>
+    pub memory: Memory<'a, C1, C2>,
+    pub histogram: Option<Box<OpcodeHistogram>>,
+    pub invalid_op_hook: Option<InvalidOpHook>,
+    // The 2A03 ignores the decimal flag, set for plain 6502 code that relies on it.
+    pub decimal_enabled: bool,
 }
 
 impl Registers {
@@ -77,7 +86,107 @@ impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
         Cpu {
             registers: Registers::new(pc.unwrap_or(vectors.reset)),
             vectors,
-            memory
+            memory,
+            histogram: None,
+            invalid_op_hook: None,
+            decimal_enabled: false,
+        }
+    }
+
+    // Like pressing the reset button, RAM and the PPU are left alone.
+    pub fn reset(&mut self) {
+        self.registers.pc = self.vectors.reset;
+        self.registers.sp = 0xFD;
+        self.registers.p = StatusRegister::from_bits_retain(0x34);
+
+        self.memory.apu.reset();
+    }
+
+    // Bytes currently on the stack, from the most recent push up to $01FF.
+    pub fn stack_snapshot(&mut self) -> Vec<u8> {
+        let start = 0x100 + self.registers.sp as u16 + 1;
+
+        (start ..= 0x1FF)
+            .map(|address| self.memory.pass_get(address).unwrap_or(0))
+            .collect()
+    }
+
+    // Starts counting executed opcodes from zero.
+    pub fn enable_opcode_histogram(&mut self) {
+        self.histogram = Some(Box::new([0; 256]))
+    }
+
+    pub fn disable_opcode_histogram(&mut self) {
+        self.histogram = None
+    }
+
+    pub fn opcode_histogram(&self) -> Option<&OpcodeHistogram> {
+        self.histogram.as_deref()
+    }
+
+    pub fn on_invalid_op<F: FnMut(u16, u8) + Send + 'static>(&mut self, hook: F) {
+        self.invalid_op_hook = Some(Box::new(hook))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use super::*;
+
+    #[test]
+    fn reset_reloads_pc_and_sp() {
+        let program = [
+            0xA9, 0x42,             // LDA #$42
+            0x85, 0x10,             // STA $10
+            0x48,                   // PHA
+            0xF8,                   // SED
+            0x58,                   // CLI
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        for _ in 0 .. 5 {
+            cpu.step().unwrap();
         }
+
+        assert_eq!((cpu.registers.pc, cpu.registers.sp), (0x8007, 0xFC));
+
+        cpu.reset();
+
+        assert_eq!(cpu.registers.pc, cpu.vectors.reset);
+        assert_eq!(cpu.registers.pc, 0x8000);
+        assert_eq!(cpu.registers.sp, 0xFD);
+        assert_eq!(cpu.registers.p.bits(), 0x34);
+        assert!(cpu.registers.p.contains(StatusRegister::INTERUPT));
+
+        // RAM is kept, including the pushed byte.
+        assert_eq!((cpu.memory.ram[0x10], cpu.memory.ram[0x1FD]), (0x42, 0x42));
+    }
+
+    #[test]
+    fn stack_snapshot_after_jsr() {
+        // JSR $8010, with LDA #$07, PHA at the subroutine.
+        let mut program = [0; 0x13];
+
+        program[.. 3].copy_from_slice(&[0x20, 0x10, 0x80]);
+        program[0x10 ..].copy_from_slice(&[0xA9, 0x07, 0x48]);
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        assert_eq!(cpu.stack_snapshot(), vec![0, 0]);
+
+        cpu.step().unwrap();
+
+        // JSR pushes the address of its last byte, high byte first.
+        assert_eq!(cpu.registers.sp, 0xFB);
+        assert_eq!(cpu.stack_snapshot(), vec![0x02, 0x80, 0, 0]);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.stack_snapshot()[.. 3], [0x07, 0x02, 0x80]);
     }
 }
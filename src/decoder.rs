@@ -156,6 +156,12 @@ pub trait Decoder<T>: Sized {
     fn sty_zx(&mut self, offset: u8) -> T;
     fn sty_a(&mut self, address: u16) -> T;
 
+    // Unofficial, stores A & X.
+    fn sax_z(&mut self, offset: u8) -> T;
+    fn sax_zy(&mut self, offset: u8) -> T;
+    fn sax_a(&mut self, address: u16) -> T;
+    fn sax_dx(&mut self, offset: u8) -> T;
+
     fn lda_i(&mut self, value: u8) -> T;
     fn lda_z(&mut self, offset: u8) -> T;
     fn lda_zx(&mut self, offset: u8) -> T;
@@ -165,6 +171,20 @@ pub trait Decoder<T>: Sized {
     fn lda_dx(&mut self, offset: u8) -> T;
     fn lda_dy(&mut self, offset: u8) -> T;
 
+    // Unofficial, LDA and LDX at once.
+    fn lax_z(&mut self, offset: u8) -> T;
+    fn lax_zy(&mut self, offset: u8) -> T;
+    fn lax_a(&mut self, address: u16) -> T;
+    fn lax_ay(&mut self, address: u16) -> T;
+    fn lax_dx(&mut self, offset: u8) -> T;
+    fn lax_dy(&mut self, offset: u8) -> T;
+
+    // Unofficial immediate operations.
+    fn anc_i(&mut self, value: u8) -> T;
+    fn alr_i(&mut self, value: u8) -> T;
+    fn arr_i(&mut self, value: u8) -> T;
+    fn axs_i(&mut self, value: u8) -> T;
+
     fn cmp_i(&mut self, value: u8) -> T;
     fn cmp_z(&mut self, offset: u8) -> T;
     fn cmp_zx(&mut self, offset: u8) -> T;
@@ -207,6 +227,55 @@ pub trait Decoder<T>: Sized {
     fn lsr_a(&mut self, address: u16) -> T;
     fn lsr_ax(&mut self, address: u16) -> T;
 
+    // Unofficial read-modify-write combinations.
+    fn slo_z(&mut self, offset: u8) -> T;
+    fn slo_zx(&mut self, offset: u8) -> T;
+    fn slo_a(&mut self, address: u16) -> T;
+    fn slo_ax(&mut self, address: u16) -> T;
+    fn slo_ay(&mut self, address: u16) -> T;
+    fn slo_dx(&mut self, offset: u8) -> T;
+    fn slo_dy(&mut self, offset: u8) -> T;
+
+    fn rla_z(&mut self, offset: u8) -> T;
+    fn rla_zx(&mut self, offset: u8) -> T;
+    fn rla_a(&mut self, address: u16) -> T;
+    fn rla_ax(&mut self, address: u16) -> T;
+    fn rla_ay(&mut self, address: u16) -> T;
+    fn rla_dx(&mut self, offset: u8) -> T;
+    fn rla_dy(&mut self, offset: u8) -> T;
+
+    fn sre_z(&mut self, offset: u8) -> T;
+    fn sre_zx(&mut self, offset: u8) -> T;
+    fn sre_a(&mut self, address: u16) -> T;
+    fn sre_ax(&mut self, address: u16) -> T;
+    fn sre_ay(&mut self, address: u16) -> T;
+    fn sre_dx(&mut self, offset: u8) -> T;
+    fn sre_dy(&mut self, offset: u8) -> T;
+
+    fn rra_z(&mut self, offset: u8) -> T;
+    fn rra_zx(&mut self, offset: u8) -> T;
+    fn rra_a(&mut self, address: u16) -> T;
+    fn rra_ax(&mut self, address: u16) -> T;
+    fn rra_ay(&mut self, address: u16) -> T;
+    fn rra_dx(&mut self, offset: u8) -> T;
+    fn rra_dy(&mut self, offset: u8) -> T;
+
+    fn dcp_z(&mut self, offset: u8) -> T;
+    fn dcp_zx(&mut self, offset: u8) -> T;
+    fn dcp_a(&mut self, address: u16) -> T;
+    fn dcp_ax(&mut self, address: u16) -> T;
+    fn dcp_ay(&mut self, address: u16) -> T;
+    fn dcp_dx(&mut self, offset: u8) -> T;
+    fn dcp_dy(&mut self, offset: u8) -> T;
+
+    fn isc_z(&mut self, offset: u8) -> T;
+    fn isc_zx(&mut self, offset: u8) -> T;
+    fn isc_a(&mut self, address: u16) -> T;
+    fn isc_ax(&mut self, address: u16) -> T;
+    fn isc_ay(&mut self, address: u16) -> T;
+    fn isc_dx(&mut self, offset: u8) -> T;
+    fn isc_dy(&mut self, offset: u8) -> T;
+
     fn decode<F: FnMut(&mut Self) -> Option<u8>>(&mut self, mut next: F) -> Option<T> {
         let op = next(self)?;
 
@@ -214,139 +283,139 @@ pub trait Decoder<T>: Sized {
             0x00 => self.brk(),
             0x01 => { let x = next(self)?; self.ora_dx(x) }, // NOOO
             0x02 => self.stp(),
-            0x03 => return None,
+            0x03 => { let x = next(self)?; self.slo_dx(x) }, // SLO
             0x04 => { let x = next(self)?; self.nop_z(x) },
             0x05 => { let x = next(self)?; self.ora_z(x) },
             0x06 => { let x = next(self)?; self.asl_z(x) },
-            0x07 => return None,
+            0x07 => { let x = next(self)?; self.slo_z(x) }, // SLO
             0x08 => self.php(),
             0x09 => { let x = next(self)?; self.ora_i(x) },
             0x0A => self.asl_g(),
-            0x0B => return None,
+            0x0B => { let x = next(self)?; self.anc_i(x) }, // ANC
             0x0C => { let x = get_address(self, &mut next)?; self.nop_a(x) }, // NOP
             0x0D => { let x = get_address(self, &mut next)?; self.ora_a(x) },
             0x0E => { let x = get_address(self, &mut next)?; self.asl_a(x) },
-            0x0F => return None,
+            0x0F => { let x = get_address(self, &mut next)?; self.slo_a(x) }, // SLO
             0x10 => { let x = next(self)?; self.bpl(x) },
             0x11 => { let x = next(self)?; self.ora_dy(x) },
             0x12 => self.stp(),
-            0x13 => return None,
+            0x13 => { let x = next(self)?; self.slo_dy(x) }, // SLO
             0x14 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0x15 => { let x = next(self)?; self.ora_zx(x) },
             0x16 => { let x = next(self)?; self.asl_zx(x) },
-            0x17 => return None,
+            0x17 => { let x = next(self)?; self.slo_zx(x) }, // SLO
             0x18 => self.clc(),
             0x19 => { let x = get_address(self, &mut next)?; self.ora_ay(x) },
             0x1A => self.nop_g(), // NOP
-            0x1B => return None,
+            0x1B => { let x = get_address(self, &mut next)?; self.slo_ay(x) }, // SLO
             0x1C => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0x1D => { let x = get_address(self, &mut next)?; self.ora_ax(x) },
             0x1E => { let x = get_address(self, &mut next)?; self.asl_ax(x) },
-            0x1F => return None,
+            0x1F => { let x = get_address(self, &mut next)?; self.slo_ax(x) }, // SLO
             0x20 => { let x = get_address(self, &mut next)?; self.jsr(x) },
             0x21 => { let x = next(self)?; self.and_dx(x) },
             0x22 => self.stp(),
-            0x23 => return None,
+            0x23 => { let x = next(self)?; self.rla_dx(x) }, // RLA
             0x24 => { let x = next(self)?; self.bit_z(x) },
             0x25 => { let x = next(self)?; self.and_z(x) },
             0x26 => { let x = next(self)?; self.rol_z(x) },
-            0x27 => return None,
+            0x27 => { let x = next(self)?; self.rla_z(x) }, // RLA
             0x28 => self.plp(),
             0x29 => { let x = next(self)?; self.and_i(x) },
             0x2A => self.rol_g(),
-            0x2B => return None,
+            0x2B => { let x = next(self)?; self.anc_i(x) }, // ANC
             0x2C => { let x = get_address(self, &mut next)?; self.bit_a(x) },
             0x2D => { let x = get_address(self, &mut next)?; self.and_a(x) },
             0x2E => { let x = get_address(self, &mut next)?; self.rol_a(x) },
-            0x2F => return None,
+            0x2F => { let x = get_address(self, &mut next)?; self.rla_a(x) }, // RLA
             0x30 => { let x = next(self)?; self.bmi(x) },
             0x31 => { let x = next(self)?; self.and_dy(x) },
             0x32 => self.stp(),
-            0x33 => return None,
+            0x33 => { let x = next(self)?; self.rla_dy(x) }, // RLA
             0x34 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0x35 => { let x = next(self)?; self.and_zx(x) },
             0x36 => { let x = next(self)?; self.rol_zx(x) },
-            0x37 => return None,
+            0x37 => { let x = next(self)?; self.rla_zx(x) }, // RLA
             0x38 => self.sec(),
             0x39 => { let x = get_address(self, &mut next)?; self.and_ay(x) },
             0x3A => self.nop_g(), // NOP
-            0x3B => return None,
+            0x3B => { let x = get_address(self, &mut next)?; self.rla_ay(x) }, // RLA
             0x3C => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0x3D => { let x = get_address(self, &mut next)?; self.and_ax(x) },
             0x3E => { let x = get_address(self, &mut next)?; self.rol_ax(x) },
-            0x3F => return None,
+            0x3F => { let x = get_address(self, &mut next)?; self.rla_ax(x) }, // RLA
             0x40 => self.rti(),
             0x41 => { let x = next(self)?; self.eor_dx(x) },
             0x42 => self.stp(),
-            0x43 => return None,
+            0x43 => { let x = next(self)?; self.sre_dx(x) }, // SRE
             0x44 => { let x = next(self)?; self.nop_z(x) }, // NOP
             0x45 => { let x = next(self)?; self.eor_z(x) },
             0x46 => { let x = next(self)?; self.lsr_z(x) },
-            0x47 => return None,
+            0x47 => { let x = next(self)?; self.sre_z(x) }, // SRE
             0x48 => self.pha(),
             0x49 => { let x = next(self)?; self.eor_i(x) },
             0x4A => self.lsr_g(),
-            0x4B => return None,
+            0x4B => { let x = next(self)?; self.alr_i(x) }, // ALR
             0x4C => { let x = get_address(self, &mut next)?; self.jmp_a(x) },
             0x4D => { let x = get_address(self, &mut next)?; self.eor_a(x) },
             0x4E => { let x = get_address(self, &mut next)?; self.lsr_a(x) },
-            0x4F => return None,
+            0x4F => { let x = get_address(self, &mut next)?; self.sre_a(x) }, // SRE
             0x50 => { let x = next(self)?; self.bvc(x) },
             0x51 => { let x = next(self)?; self.eor_dy(x) },
             0x52 => self.stp(),
-            0x53 => return None,
+            0x53 => { let x = next(self)?; self.sre_dy(x) }, // SRE
             0x54 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0x55 => { let x = next(self)?; self.eor_zx(x) },
             0x56 => { let x = next(self)?; self.lsr_zx(x) },
-            0x57 => return None,
+            0x57 => { let x = next(self)?; self.sre_zx(x) }, // SRE
             0x58 => self.cli(),
             0x59 => { let x = get_address(self, &mut next)?; self.eor_ay(x) },
             0x5A => self.nop_g(), // NOP
-            0x5B => return None,
+            0x5B => { let x = get_address(self, &mut next)?; self.sre_ay(x) }, // SRE
             0x5C => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0x5D => { let x = get_address(self, &mut next)?; self.eor_ax(x) },
             0x5E => { let x = get_address(self, &mut next)?; self.lsr_ax(x) },
-            0x5F => return None,
+            0x5F => { let x = get_address(self, &mut next)?; self.sre_ax(x) }, // SRE
             0x60 => self.rts(),
             0x61 => { let x = next(self)?; self.adc_dx(x) },
             0x62 => self.stp(),
-            0x63 => return None,
+            0x63 => { let x = next(self)?; self.rra_dx(x) }, // RRA
             0x64 => { let x = next(self)?; self.nop_z(x) }, // NOP
             0x65 => { let x = next(self)?; self.adc_z(x) },
             0x66 => { let x = next(self)?; self.ror_z(x) },
-            0x67 => return None,
+            0x67 => { let x = next(self)?; self.rra_z(x) }, // RRA
             0x68 => self.pla(),
             0x69 => { let x = next(self)?; self.adc_i(x) },
             0x6A => self.ror_g(),
-            0x6B => return None,
+            0x6B => { let x = next(self)?; self.arr_i(x) }, // ARR
             0x6C => { let x = get_address(self, &mut next)?; self.jmp_ad(x) },
             0x6D => { let x = get_address(self, &mut next)?; self.adc_a(x) },
             0x6E => { let x = get_address(self, &mut next)?; self.ror_a(x) },
-            0x6F => return None,
+            0x6F => { let x = get_address(self, &mut next)?; self.rra_a(x) }, // RRA
             0x70 => { let x = next(self)?; self.bvs(x) },
             0x71 => { let x = next(self)?; self.adc_dy(x) },
             0x72 => self.stp(),
-            0x73 => return None,
+            0x73 => { let x = next(self)?; self.rra_dy(x) }, // RRA
             0x74 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0x75 => { let x = next(self)?; self.adc_zx(x) },
             0x76 => { let x = next(self)?; self.ror_zx(x) },
-            0x77 => return None,
+            0x77 => { let x = next(self)?; self.rra_zx(x) }, // RRA
             0x78 => self.sei(),
             0x79 => { let x = get_address(self, &mut next)?; self.adc_ay(x) },
             0x7A => self.nop_g(), // NOP
-            0x7B => return None,
+            0x7B => { let x = get_address(self, &mut next)?; self.rra_ay(x) }, // RRA
             0x7C => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0x7D => { let x = get_address(self, &mut next)?; self.adc_ax(x) },
             0x7E => { let x = get_address(self, &mut next)?; self.ror_ax(x) },
-            0x7F => return None, // NOP
+            0x7F => { let x = get_address(self, &mut next)?; self.rra_ax(x) }, // RRA
             0x80 => { let x = next(self)?; self.nop_i(x) }, // NOP
             0x81 => { let x = next(self)?; self.sta_dx(x) },
             0x82 => { let x = next(self)?; self.nop_i(x) }, // NOP
-            0x83 => return None,
+            0x83 => { let x = next(self)?; self.sax_dx(x) }, // SAX
             0x84 => { let x = next(self)?; self.sty_z(x) },
             0x85 => { let x = next(self)?; self.sta_z(x) },
             0x86 => { let x = next(self)?; self.stx_z(x) },
-            0x87 => return None,
+            0x87 => { let x = next(self)?; self.sax_z(x) }, // SAX
             0x88 => self.dey(),
             0x89 => { let x = next(self)?; self.nop_i(x) }, // NOP
             0x8A => self.txa(),
@@ -354,7 +423,7 @@ pub trait Decoder<T>: Sized {
             0x8C => { let x = get_address(self, &mut next)?; self.sty_a(x) },
             0x8D => { let x = get_address(self, &mut next)?; self.sta_a(x) },
             0x8E => { let x = get_address(self, &mut next)?; self.stx_a(x) },
-            0x8F => return None,
+            0x8F => { let x = get_address(self, &mut next)?; self.sax_a(x) }, // SAX
             0x90 => { let x = next(self)?; self.bcc(x) },
             0x91 => { let x = next(self)?; self.sta_dy(x) },
             0x92 => self.stp(),
@@ -362,7 +431,7 @@ pub trait Decoder<T>: Sized {
             0x94 => { let x = next(self)?; self.sty_zx(x) },
             0x95 => { let x = next(self)?; self.sta_zx(x) },
             0x96 => { let x = next(self)?; self.stx_zy(x) },
-            0x97 => return None,
+            0x97 => { let x = next(self)?; self.sax_zy(x) }, // SAX
             0x98 => self.tya(),
             0x99 => { let x = get_address(self, &mut next)?; self.sta_ay(x) },
             0x9A => self.txs(),
@@ -374,11 +443,11 @@ pub trait Decoder<T>: Sized {
             0xA0 => { let x = next(self)?; self.ldy_i(x) },
             0xA1 => { let x = next(self)?; self.lda_dx(x) },
             0xA2 => { let x = next(self)?; self.ldx_i(x) },
-            0xA3 => return None,
+            0xA3 => { let x = next(self)?; self.lax_dx(x) }, // LAX
             0xA4 => { let x = next(self)?; self.ldy_z(x) },
             0xA5 => { let x = next(self)?; self.lda_z(x) },
             0xA6 => { let x = next(self)?; self.ldx_z(x) },
-            0xA7 => return None,
+            0xA7 => { let x = next(self)?; self.lax_z(x) }, // LAX
             0xA8 => self.tay(),
             0xA9 => { let x = next(self)?; self.lda_i(x) },
             0xAA => self.tax(),
@@ -386,15 +455,15 @@ pub trait Decoder<T>: Sized {
             0xAC => { let x = get_address(self, &mut next)?; self.ldy_a(x) },
             0xAD => { let x = get_address(self, &mut next)?; self.lda_a(x) },
             0xAE => { let x = get_address(self, &mut next)?; self.ldx_a(x) },
-            0xAF => return None,
+            0xAF => { let x = get_address(self, &mut next)?; self.lax_a(x) }, // LAX
             0xB0 => { let x = next(self)?; self.bcs(x) },
             0xB1 => { let x = next(self)?; self.lda_dy(x) },
             0xB2 => self.stp(),
-            0xB3 => return None,
+            0xB3 => { let x = next(self)?; self.lax_dy(x) }, // LAX
             0xB4 => { let x = next(self)?; self.ldy_zx(x) },
             0xB5 => { let x = next(self)?; self.lda_zx(x) },
             0xB6 => { let x = next(self)?; self.ldx_zy(x) },
-            0xB7 => return None,
+            0xB7 => { let x = next(self)?; self.lax_zy(x) }, // LAX
             0xB8 => self.clv(),
             0xB9 => { let x = get_address(self, &mut next)?; self.lda_ay(x) },
             0xBA => self.tsx(),
@@ -402,47 +471,47 @@ pub trait Decoder<T>: Sized {
             0xBC => { let x = get_address(self, &mut next)?; self.ldy_ax(x) },
             0xBD => { let x = get_address(self, &mut next)?; self.lda_ax(x) },
             0xBE => { let x = get_address(self, &mut next)?; self.ldx_ay(x) },
-            0xBF => return None,
+            0xBF => { let x = get_address(self, &mut next)?; self.lax_ay(x) }, // LAX
             0xC0 => { let x = next(self)?; self.cpy_i(x) },
             0xC1 => { let x = next(self)?; self.cmp_dx(x) },
             0xC2 => { let x = next(self)?; self.nop_i(x) }, // NOP
-            0xC3 => return None,
+            0xC3 => { let x = next(self)?; self.dcp_dx(x) }, // DCP
             0xC4 => { let x = next(self)?; self.cpy_z(x) },
             0xC5 => { let x = next(self)?; self.cmp_z(x) },
             0xC6 => { let x = next(self)?; self.dec_z(x) },
-            0xC7 => return None,
+            0xC7 => { let x = next(self)?; self.dcp_z(x) }, // DCP
             0xC8 => self.iny(),
             0xC9 => { let x = next(self)?; self.cmp_i(x) },
             0xCA => self.dex(),
-            0xCB => return None,
+            0xCB => { let x = next(self)?; self.axs_i(x) }, // AXS
             0xCC => { let x = get_address(self, &mut next)?; self.cpy_a(x) },
             0xCD => { let x = get_address(self, &mut next)?; self.cmp_a(x) },
             0xCE => { let x = get_address(self, &mut next)?; self.dec_a(x) },
-            0xCF => return None,
+            0xCF => { let x = get_address(self, &mut next)?; self.dcp_a(x) }, // DCP
             0xD0 => { let x = next(self)?; self.bne(x) },
             0xD1 => { let x = next(self)?; self.cmp_dy(x) },
             0xD2 => self.stp(),
-            0xD3 => return None,
+            0xD3 => { let x = next(self)?; self.dcp_dy(x) }, // DCP
             0xD4 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0xD5 => { let x = next(self)?; self.cmp_zx(x) },
             0xD6 => { let x = next(self)?; self.dec_zx(x) },
-            0xD7 => return None,
+            0xD7 => { let x = next(self)?; self.dcp_zx(x) }, // DCP
             0xD8 => self.cld(),
             0xD9 => { let x = get_address(self, &mut next)?; self.cmp_ay(x) },
             0xDA => self.nop_g(), // NOP
-            0xDB => return None,
+            0xDB => { let x = get_address(self, &mut next)?; self.dcp_ay(x) }, // DCP
             0xDC => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0xDD => { let x = get_address(self, &mut next)?; self.cmp_ax(x) },
             0xDE => { let x = get_address(self, &mut next)?; self.dec_ax(x) },
-            0xDF => return None,
+            0xDF => { let x = get_address(self, &mut next)?; self.dcp_ax(x) }, // DCP
             0xE0 => { let x = next(self)?; self.cpx_i(x) },
             0xE1 => { let x = next(self)?; self.sbc_dx(x) },
             0xE2 => { let x = next(self)?; self.nop_i(x) }, // NOP
-            0xE3 => return None,
+            0xE3 => { let x = next(self)?; self.isc_dx(x) }, // ISC
             0xE4 => { let x = next(self)?; self.cpx_z(x) },
             0xE5 => { let x = next(self)?; self.sbc_z(x) },
             0xE6 => { let x = next(self)?; self.inc_z(x) },
-            0xE7 => return None,
+            0xE7 => { let x = next(self)?; self.isc_z(x) }, // ISC
             0xE8 => self.inx(),
             0xE9 => { let x = next(self)?; self.sbc_i(x) },
             0xEA => self.nop_g(),
@@ -450,23 +519,23 @@ pub trait Decoder<T>: Sized {
             0xEC => { let x = get_address(self, &mut next)?; self.cpx_a(x) },
             0xED => { let x = get_address(self, &mut next)?; self.sbc_a(x) },
             0xEE => { let x = get_address(self, &mut next)?; self.inc_a(x) },
-            0xEF => return None,
+            0xEF => { let x = get_address(self, &mut next)?; self.isc_a(x) }, // ISC
             0xF0 => { let x = next(self)?; self.beq(x) },
             0xF1 => { let x = next(self)?; self.sbc_dy(x) },
             0xF2 => self.stp(),
-            0xF3 => return None,
+            0xF3 => { let x = next(self)?; self.isc_dy(x) }, // ISC
             0xF4 => { let x = next(self)?; self.nop_zx(x) }, // NOP
             0xF5 => { let x = next(self)?; self.sbc_zx(x) },
             0xF6 => { let x = next(self)?; self.inc_zx(x) },
-            0xF7 => return None,
+            0xF7 => { let x = next(self)?; self.isc_zx(x) }, // ISC
             0xF8 => self.sed(),
             0xF9 => { let x = get_address(self, &mut next)?; self.sbc_ay(x) },
             0xFA => self.nop_g(), // NOP
-            0xFB => return None,
+            0xFB => { let x = get_address(self, &mut next)?; self.isc_ay(x) }, // ISC
             0xFC => { let x = get_address(self, &mut next)?; self.nop_ax(x) }, // NOP
             0xFD => { let x = get_address(self, &mut next)?; self.sbc_ax(x) },
             0xFE => { let x = get_address(self, &mut next)?; self.inc_ax(x) },
-            0xFF => return None
+            0xFF => { let x = get_address(self, &mut next)?; self.isc_ax(x) }, // ISC
         })
     }
 }
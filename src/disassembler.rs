@@ -1,3 +1,6 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::controller::Controller;
+use crate::cpu::Cpu;
 use crate::decoder::Decoder;
 
 pub struct Disassembler {
@@ -399,6 +402,18 @@ impl Decoder<String> for Disassembler {
     fn sty_a(&mut self, address: u16) -> String {
         format_a("STY", address)
     }
+    fn sax_z(&mut self, offset: u8) -> String {
+        format_z("SAX", offset)
+    }
+    fn sax_zy(&mut self, offset: u8) -> String {
+        format_zy("SAX", offset)
+    }
+    fn sax_a(&mut self, address: u16) -> String {
+        format_a("SAX", address)
+    }
+    fn sax_dx(&mut self, offset: u8) -> String {
+        format_dx("SAX", offset)
+    }
     fn lda_i(&mut self, value: u8) -> String {
         format_i("LDA", value)
     }
@@ -423,6 +438,36 @@ impl Decoder<String> for Disassembler {
     fn lda_dy(&mut self, offset: u8) -> String {
         format_dy("LDA", offset)
     }
+    fn lax_z(&mut self, offset: u8) -> String {
+        format_z("LAX", offset)
+    }
+    fn lax_zy(&mut self, offset: u8) -> String {
+        format_zy("LAX", offset)
+    }
+    fn lax_a(&mut self, address: u16) -> String {
+        format_a("LAX", address)
+    }
+    fn lax_ay(&mut self, address: u16) -> String {
+        format_ay("LAX", address)
+    }
+    fn lax_dx(&mut self, offset: u8) -> String {
+        format_dx("LAX", offset)
+    }
+    fn lax_dy(&mut self, offset: u8) -> String {
+        format_dy("LAX", offset)
+    }
+    fn anc_i(&mut self, value: u8) -> String {
+        format_i("ANC", value)
+    }
+    fn alr_i(&mut self, value: u8) -> String {
+        format_i("ALR", value)
+    }
+    fn arr_i(&mut self, value: u8) -> String {
+        format_i("ARR", value)
+    }
+    fn axs_i(&mut self, value: u8) -> String {
+        format_i("AXS", value)
+    }
     fn cmp_i(&mut self, value: u8) -> String {
         format_i("CMP", value)
     }
@@ -531,4 +576,259 @@ impl Decoder<String> for Disassembler {
     fn lsr_ax(&mut self, address: u16) -> String {
         format_ax("LSR", address)
     }
+    fn slo_z(&mut self, offset: u8) -> String {
+        format_z("SLO", offset)
+    }
+    fn slo_zx(&mut self, offset: u8) -> String {
+        format_zx("SLO", offset)
+    }
+    fn slo_a(&mut self, address: u16) -> String {
+        format_a("SLO", address)
+    }
+    fn slo_ax(&mut self, address: u16) -> String {
+        format_ax("SLO", address)
+    }
+    fn slo_ay(&mut self, address: u16) -> String {
+        format_ay("SLO", address)
+    }
+    fn slo_dx(&mut self, offset: u8) -> String {
+        format_dx("SLO", offset)
+    }
+    fn slo_dy(&mut self, offset: u8) -> String {
+        format_dy("SLO", offset)
+    }
+    fn rla_z(&mut self, offset: u8) -> String {
+        format_z("RLA", offset)
+    }
+    fn rla_zx(&mut self, offset: u8) -> String {
+        format_zx("RLA", offset)
+    }
+    fn rla_a(&mut self, address: u16) -> String {
+        format_a("RLA", address)
+    }
+    fn rla_ax(&mut self, address: u16) -> String {
+        format_ax("RLA", address)
+    }
+    fn rla_ay(&mut self, address: u16) -> String {
+        format_ay("RLA", address)
+    }
+    fn rla_dx(&mut self, offset: u8) -> String {
+        format_dx("RLA", offset)
+    }
+    fn rla_dy(&mut self, offset: u8) -> String {
+        format_dy("RLA", offset)
+    }
+    fn sre_z(&mut self, offset: u8) -> String {
+        format_z("SRE", offset)
+    }
+    fn sre_zx(&mut self, offset: u8) -> String {
+        format_zx("SRE", offset)
+    }
+    fn sre_a(&mut self, address: u16) -> String {
+        format_a("SRE", address)
+    }
+    fn sre_ax(&mut self, address: u16) -> String {
+        format_ax("SRE", address)
+    }
+    fn sre_ay(&mut self, address: u16) -> String {
+        format_ay("SRE", address)
+    }
+    fn sre_dx(&mut self, offset: u8) -> String {
+        format_dx("SRE", offset)
+    }
+    fn sre_dy(&mut self, offset: u8) -> String {
+        format_dy("SRE", offset)
+    }
+    fn rra_z(&mut self, offset: u8) -> String {
+        format_z("RRA", offset)
+    }
+    fn rra_zx(&mut self, offset: u8) -> String {
+        format_zx("RRA", offset)
+    }
+    fn rra_a(&mut self, address: u16) -> String {
+        format_a("RRA", address)
+    }
+    fn rra_ax(&mut self, address: u16) -> String {
+        format_ax("RRA", address)
+    }
+    fn rra_ay(&mut self, address: u16) -> String {
+        format_ay("RRA", address)
+    }
+    fn rra_dx(&mut self, offset: u8) -> String {
+        format_dx("RRA", offset)
+    }
+    fn rra_dy(&mut self, offset: u8) -> String {
+        format_dy("RRA", offset)
+    }
+    fn dcp_z(&mut self, offset: u8) -> String {
+        format_z("DCP", offset)
+    }
+    fn dcp_zx(&mut self, offset: u8) -> String {
+        format_zx("DCP", offset)
+    }
+    fn dcp_a(&mut self, address: u16) -> String {
+        format_a("DCP", address)
+    }
+    fn dcp_ax(&mut self, address: u16) -> String {
+        format_ax("DCP", address)
+    }
+    fn dcp_ay(&mut self, address: u16) -> String {
+        format_ay("DCP", address)
+    }
+    fn dcp_dx(&mut self, offset: u8) -> String {
+        format_dx("DCP", offset)
+    }
+    fn dcp_dy(&mut self, offset: u8) -> String {
+        format_dy("DCP", offset)
+    }
+    fn isc_z(&mut self, offset: u8) -> String {
+        format_z("ISC", offset)
+    }
+    fn isc_zx(&mut self, offset: u8) -> String {
+        format_zx("ISC", offset)
+    }
+    fn isc_a(&mut self, address: u16) -> String {
+        format_a("ISC", address)
+    }
+    fn isc_ax(&mut self, address: u16) -> String {
+        format_ax("ISC", address)
+    }
+    fn isc_ay(&mut self, address: u16) -> String {
+        format_ay("ISC", address)
+    }
+    fn isc_dx(&mut self, offset: u8) -> String {
+        format_dx("ISC", offset)
+    }
+    fn isc_dy(&mut self, offset: u8) -> String {
+        format_dy("ISC", offset)
+    }
+}
+
+// Length in bytes of the instruction at pc, None if it can't be decoded.
+fn instruction_length<F: FnMut(u16) -> Option<u8>>(pc: u16, get: &mut F) -> Option<u16> {
+    let mut length = 0u16;
+
+    Disassembler { pc }.decode(|_| {
+        let value = get(pc.wrapping_add(length));
+
+        length += 1;
+
+        value
+    })?;
+
+    Some(length)
+}
+
+#[derive(Default)]
+pub struct Trace {
+    // Address of every instruction reached.
+    pub visited: BTreeSet<u16>,
+    // Targets of every JSR reached.
+    pub subroutines: BTreeSet<u16>,
+}
+
+impl Trace {
+    // Auto-generated sub_XXXX labels for each subroutine.
+    pub fn labels(&self) -> BTreeMap<u16, String> {
+        self.subroutines.iter()
+            .map(|address| (*address, format!("sub_{address:04X}")))
+            .collect()
+    }
+}
+
+// Walks every path reachable from entries, following branches, jumps and calls.
+// Indirect jumps end a path, since their target depends on memory at runtime.
+pub fn trace_from<F: FnMut(u16) -> Option<u8>>(entries: &[u16], mut get: F) -> Trace {
+    let mut trace = Trace::default();
+    let mut pending = entries.to_vec();
+
+    while let Some(pc) = pending.pop() {
+        if !trace.visited.insert(pc) {
+            continue
+        }
+
+        let Some(op) = get(pc) else { continue };
+        let Some(length) = instruction_length(pc, &mut get) else { continue };
+
+        let next = pc.wrapping_add(length);
+
+        let operand = |get: &mut F| -> Option<u16> {
+            let low = get(pc.wrapping_add(1))? as u16;
+            let high = get(pc.wrapping_add(2))? as u16;
+
+            Some((high << 8) | low)
+        };
+
+        match op {
+            // JSR
+            0x20 => {
+                if let Some(target) = operand(&mut get) {
+                    trace.subroutines.insert(target);
+                    pending.push(target);
+                }
+
+                pending.push(next);
+            }
+            // JMP
+            0x4C => {
+                if let Some(target) = operand(&mut get) {
+                    pending.push(target);
+                }
+            }
+            // BRK, RTI, RTS, JMP (indirect)
+            0x00 | 0x40 | 0x60 | 0x6C => { }
+            // Branches
+            0x10 | 0x30 | 0x50 | 0x70 | 0x90 | 0xB0 | 0xD0 | 0xF0 => {
+                if let Some(rel) = get(pc.wrapping_add(1)) {
+                    pending.push(compute_target(rel, pc));
+                }
+
+                pending.push(next);
+            }
+            // STP
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => { }
+            _ => pending.push(next)
+        }
+    }
+
+    trace
+}
+
+impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
+    // Subroutines reachable from the reset, NMI and IRQ vectors.
+    pub fn trace_subroutines(&mut self) -> Trace {
+        let entries = [self.vectors.reset, self.vectors.nmi, self.vectors.interrupt];
+
+        trace_from(&entries, |address| self.memory.pass_get(address).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::rom::Rom;
+    use super::*;
+
+    #[test]
+    fn finds_both_subroutines() {
+        let program = [
+            0x20, 0x08, 0x80,       // JSR $8008
+            0x4C, 0x03, 0x80,       // JMP $8003
+            0x00, 0x00,
+            0x20, 0x0C, 0x80,       // JSR $800C
+            0x60,                   // RTS
+            0xE8,                   // INX
+            0x60,                   // RTS
+            0x20, 0x00, 0x90,       // JSR $9000, never reached
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        let trace = cpu.trace_subroutines();
+
+        assert_eq!(trace.subroutines, BTreeSet::from([0x8008, 0x800C]));
+        assert_eq!(trace.labels()[&0x800C], "sub_800C");
+        assert!(!trace.visited.contains(&0x800E));
+    }
 }
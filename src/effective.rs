@@ -0,0 +1,754 @@
+use crate::controller::Controller;
+use crate::cpu::Cpu;
+use crate::decoder::Decoder;
+
+// Resolves the memory address an instruction touches, reading through Memory::peek so
+// nothing is cycled, written or otherwise disturbed.
+struct AddressResolver<'b, 'a, C1: Controller, C2: Controller> {
+    cpu: &'b mut Cpu<'a, C1, C2>,
+    fetched: u16
+}
+
+impl<'b, 'a, C1: Controller, C2: Controller> AddressResolver<'b, 'a, C1, C2> {
+    fn get_ptr(&mut self, offset: u8) -> Option<u16> {
+        let low = self.cpu.memory.peek(offset as u16)? as u16;
+        let high = self.cpu.memory.peek(offset.wrapping_add(1) as u16)? as u16;
+
+        Some((high << 8) | low)
+    }
+
+    fn get_ptr_a(&mut self, address: u16) -> Option<u16> {
+        let second = (address & 0xFF00) | (((address & 0xFF) as u8).wrapping_add(1) as u16);
+
+        let low = self.cpu.memory.peek(address)? as u16;
+        let high = self.cpu.memory.peek(second)? as u16;
+
+        Some((high << 8) | low)
+    }
+}
+
+impl<'b, 'a, C1: Controller, C2: Controller> Decoder<Option<u16>> for AddressResolver<'b, 'a, C1, C2> {
+    fn brk(&mut self) -> Option<u16> {
+        None
+    }
+    fn stp(&mut self) -> Option<u16> {
+        None
+    }
+    fn nop_g(&mut self) -> Option<u16> {
+        None
+    }
+    fn nop_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn nop_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn nop_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn nop_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn nop_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn dex(&mut self) -> Option<u16> {
+        None
+    }
+    fn dey(&mut self) -> Option<u16> {
+        None
+    }
+    fn iny(&mut self) -> Option<u16> {
+        None
+    }
+    fn inx(&mut self) -> Option<u16> {
+        None
+    }
+    fn inc_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn inc_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn inc_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn inc_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn dec_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn dec_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn dec_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn dec_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn php(&mut self) -> Option<u16> {
+        None
+    }
+    fn plp(&mut self) -> Option<u16> {
+        None
+    }
+    fn pha(&mut self) -> Option<u16> {
+        None
+    }
+    fn pla(&mut self) -> Option<u16> {
+        None
+    }
+    fn bit_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn bit_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn tay(&mut self) -> Option<u16> {
+        None
+    }
+    fn tya(&mut self) -> Option<u16> {
+        None
+    }
+    fn txa(&mut self) -> Option<u16> {
+        None
+    }
+    fn txs(&mut self) -> Option<u16> {
+        None
+    }
+    fn tax(&mut self) -> Option<u16> {
+        None
+    }
+    fn tsx(&mut self) -> Option<u16> {
+        None
+    }
+    fn clc(&mut self) -> Option<u16> {
+        None
+    }
+    fn sec(&mut self) -> Option<u16> {
+        None
+    }
+    fn cli(&mut self) -> Option<u16> {
+        None
+    }
+    fn sei(&mut self) -> Option<u16> {
+        None
+    }
+    fn clv(&mut self) -> Option<u16> {
+        None
+    }
+    fn cld(&mut self) -> Option<u16> {
+        None
+    }
+    fn sed(&mut self) -> Option<u16> {
+        None
+    }
+    fn jmp_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn jmp_ad(&mut self, address: u16) -> Option<u16> {
+        self.get_ptr_a(address)
+    }
+    fn jsr(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn rti(&mut self) -> Option<u16> {
+        None
+    }
+    fn rts(&mut self) -> Option<u16> {
+        None
+    }
+    fn bpl(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bmi(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bvc(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bvs(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bcc(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bcs(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn bne(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn beq(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn cpx_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn cpx_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn cpx_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn cpy_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn cpy_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn cpy_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn ldy_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn ldy_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn ldy_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn ldy_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn ldy_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn ldx_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn ldx_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn ldx_zy(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.y) as u16)
+    }
+    fn ldx_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn ldx_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn ora_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn ora_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn ora_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn ora_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn ora_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn ora_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn ora_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn ora_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn and_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn and_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn and_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn and_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn and_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn and_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn and_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn and_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn eor_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn eor_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn eor_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn eor_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn eor_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn eor_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn eor_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn eor_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn adc_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn adc_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn adc_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn adc_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn adc_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn adc_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn adc_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn adc_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sta_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn sta_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn sta_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sta_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn sta_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sta_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn sta_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn stx_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn stx_zy(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.y) as u16)
+    }
+    fn stx_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sty_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn sty_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn sty_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sax_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn sax_zy(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.y) as u16)
+    }
+    fn sax_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sax_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn lda_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn lda_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn lda_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn lda_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn lda_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn lda_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn lda_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn lda_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn lax_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn lax_zy(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.y) as u16)
+    }
+    fn lax_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn lax_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn lax_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn lax_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn anc_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn alr_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn arr_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn axs_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn cmp_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn cmp_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn cmp_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn cmp_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn cmp_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn cmp_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn cmp_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn cmp_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sbc_i(&mut self, _: u8) -> Option<u16> {
+        None
+    }
+    fn sbc_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn sbc_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn sbc_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sbc_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn sbc_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sbc_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn sbc_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn asl_g(&mut self) -> Option<u16> {
+        None
+    }
+    fn asl_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn asl_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn asl_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn asl_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn rol_g(&mut self) -> Option<u16> {
+        None
+    }
+    fn rol_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn rol_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn rol_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn rol_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn ror_g(&mut self) -> Option<u16> {
+        None
+    }
+    fn ror_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn ror_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn ror_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn ror_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn lsr_g(&mut self) -> Option<u16> {
+        None
+    }
+    fn lsr_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn lsr_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn lsr_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn lsr_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn slo_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn slo_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn slo_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn slo_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn slo_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn slo_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn slo_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn rla_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn rla_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn rla_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn rla_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn rla_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn rla_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn rla_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sre_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn sre_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn sre_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn sre_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn sre_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn sre_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn sre_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn rra_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn rra_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn rra_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn rra_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn rra_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn rra_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn rra_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn dcp_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn dcp_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn dcp_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn dcp_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn dcp_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn dcp_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn dcp_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn isc_z(&mut self, offset: u8) -> Option<u16> {
+        Some(offset as u16)
+    }
+    fn isc_zx(&mut self, offset: u8) -> Option<u16> {
+        Some(offset.wrapping_add(self.cpu.registers.x) as u16)
+    }
+    fn isc_a(&mut self, address: u16) -> Option<u16> {
+        Some(address)
+    }
+    fn isc_ax(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.x as u16))
+    }
+    fn isc_ay(&mut self, address: u16) -> Option<u16> {
+        Some(address.wrapping_add(self.cpu.registers.y as u16))
+    }
+    fn isc_dx(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset.wrapping_add(self.cpu.registers.x))
+    }
+    fn isc_dy(&mut self, offset: u8) -> Option<u16> {
+        self.get_ptr(offset).map(|x| x.wrapping_add(self.cpu.registers.y as u16))
+    }
+}
+
+impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
+    // Target address of the instruction at PC, None if it doesn't touch memory
+    // or a pointer it goes through is in a register.
+    pub fn effective_address(&mut self) -> Option<u16> {
+        let mut resolver = AddressResolver { cpu: self, fetched: 0 };
+
+        resolver.decode(|resolver| {
+            let address = resolver.cpu.registers.pc.wrapping_add(resolver.fetched);
+
+            resolver.fetched += 1;
+
+            resolver.cpu.memory.peek(address)
+        }).flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::rom::Rom;
+    use super::*;
+
+    #[test]
+    fn store_zero_page_x() {
+        let program = [
+            0xA2, 0x05,             // LDX #$05
+            0x95, 0x10,             // STA $10,X
+            0xE8,                   // INX
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        cpu.step().unwrap();
+
+        let (pc, cycles) = (cpu.registers.pc, cpu.memory.cycles);
+
+        assert_eq!(cpu.effective_address(), Some(0x15));
+        assert_eq!((cpu.registers.pc, cpu.memory.cycles), (pc, cycles));
+
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.effective_address(), None);
+    }
+
+    #[test]
+    fn resolving_leaves_io_alone() {
+        let program = [
+            0x6C, 0x02, 0x20,       // JMP ($2002)
+            0x6C, 0x16, 0x40,       // JMP ($4016)
+            0xBD, 0x02, 0x20,       // LDA $2002,X
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        cpu.memory.ppu.registers.render.write_scroll(0);
+
+        let state = |cpu: &Cpu<NoController, NoController>| {
+            let ppu = &cpu.memory.ppu.registers;
+
+            (ppu.status.bits(), ppu.render.w, cpu.memory.controller_cycles, cpu.memory.cycles)
+        };
+
+        let before = state(&cpu);
+
+        // Pointers in registers can't be read without side effects.
+        assert_eq!(cpu.effective_address(), None);
+
+        cpu.registers.pc = 0x8003;
+
+        assert_eq!(cpu.effective_address(), None);
+
+        // Resolving a register as the target is fine, it is never read.
+        cpu.registers.pc = 0x8006;
+        cpu.registers.x = 0x14;
+
+        assert_eq!(cpu.effective_address(), Some(0x2016));
+
+        assert!(before.0 & 0x80 != 0);
+        assert_eq!(state(&cpu), before);
+    }
+}
@@ -0,0 +1,106 @@
+use serde_derive::{Deserialize, Serialize};
+
+const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    // 8 letter codes only patch when the ROM byte matches compare.
+    pub compare: Option<u8>,
+}
+
+fn decode_letter(letter: u8) -> Option<u16> {
+    LETTERS.iter()
+        .position(|x| *x == letter.to_ascii_uppercase())
+        .map(|x| x as u16)
+}
+
+impl GameGenieCode {
+    pub fn parse(code: &str) -> Option<GameGenieCode> {
+        let n = code.bytes()
+            .map(decode_letter)
+            .collect::<Option<Vec<u16>>>()?;
+
+        if n.len() != 6 && n.len() != 8 {
+            return None
+        }
+
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8) | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4) | ((n[1] & 8) << 4)
+            | (n[4] & 7) | (n[3] & 8);
+
+        let value_low = if n.len() == 8 { n[7] } else { n[5] };
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (value_low & 8);
+
+        let compare = if n.len() == 8 {
+            Some((((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8)) as u8)
+        } else {
+            None
+        };
+
+        Some(GameGenieCode {
+            address,
+            value: value as u8,
+            compare,
+        })
+    }
+
+    pub fn apply(&self, address: u16, value: u8) -> u8 {
+        if address != self.address {
+            return value
+        }
+
+        match self.compare {
+            Some(compare) if compare != value => value,
+            _ => self.value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::memory::Memory;
+    use crate::rom::Rom;
+    use super::*;
+
+    #[test]
+    fn decodes_known_codes() {
+        assert_eq!(GameGenieCode::parse("SXIOPO"), Some(GameGenieCode { address: 0x91D9, value: 0xAD, compare: None }));
+        assert_eq!(GameGenieCode::parse("zexpygla"), Some(GameGenieCode { address: 0x94A7, value: 0x02, compare: Some(0x03) }));
+
+        assert_eq!(GameGenieCode::parse("SXIOP"), None);
+        assert_eq!(GameGenieCode::parse("SXIOPB"), None);
+    }
+
+    #[test]
+    fn patches_reads() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.prg_rom[0x11D9] = 0xCE;
+        rom.prg_rom[0x14A7] = 0x03;
+
+        let mut memory = Memory::new(&rom, (NoController, NoController));
+
+        memory.add_game_genie(GameGenieCode::parse("SXIOPO").unwrap());
+        memory.add_game_genie(GameGenieCode::parse("ZEXPYGLA").unwrap());
+
+        assert_eq!(memory.pass_get(0x91D9).unwrap(), 0xAD);
+        assert_eq!(memory.pass_get(0x94A7).unwrap(), 0x02);
+        assert_eq!(memory.pass_get(0x91DA).unwrap(), 0x00);
+
+        drop(memory);
+
+        // The compare value no longer matches, so the 8 letter code leaves the read alone.
+        rom.prg_rom[0x14A7] = 0x04;
+
+        let mut memory = Memory::new(&rom, (NoController, NoController));
+
+        memory.add_game_genie(GameGenieCode::parse("ZEXPYGLA").unwrap());
+
+        assert_eq!(memory.pass_get(0x94A7).unwrap(), 0x04);
+    }
+}
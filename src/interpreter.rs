@@ -137,52 +137,219 @@ impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
         Ok((high << 8) | low)
     }
 
+    // Hardware interrupts push the status with BREAK clear (only PHP and BRK set it).
     pub fn interrupt(&mut self, pc: u16) -> Result<(), MemoryError> {
         self.push_address(self.registers.pc)?;
 
-        let status = self.registers.p.clone()
-            | StatusRegister::ENABLED
-            | StatusRegister::BREAK;
+        let status = (self.registers.p.clone() | StatusRegister::ENABLED)
+            - StatusRegister::BREAK;
 
         self.push(status.bits())?;
 
+        self.registers.p.insert(StatusRegister::INTERUPT);
         self.registers.pc = pc;
 
         Ok(())
     }
 
+    // Maskable interrupt, returns false if it was ignored because of SEI.
+    pub fn irq(&mut self) -> Result<bool, MemoryError> {
+        if self.registers.p.contains(StatusRegister::INTERUPT) {
+            return Ok(false)
+        }
+
+        self.interrupt(self.vectors.interrupt)?;
+
+        Ok(true)
+    }
+
     fn set_flags(&mut self, value: u8) {
         self.registers.p.set(StatusRegister::ZERO, value == 0);
         self.registers.p.set(StatusRegister::NEGATIVE, value & 0b10000000 != 0);
     }
 
+    fn decimal_mode(&self) -> bool {
+        self.decimal_enabled && self.registers.p.contains(StatusRegister::DECIMAL)
+    }
+
     fn add(&mut self, a: u8, b: u8) -> u8 {
-        let a_signed = a as i8;
-        let b_signed = b as i8;
+        if self.decimal_mode() {
+            self.add_decimal(a, b)
+        } else {
+            self.add_binary(a, b)
+        }
+    }
 
-        let mut result = a.wrapping_add(b);
+    // NMOS 6502 behaviour: Z comes from the binary sum, N and V from the sum
+    // before the high nibble is adjusted.
+    fn add_decimal(&mut self, a: u8, b: u8) -> u8 {
+        let carry = self.registers.p.contains(StatusRegister::CARRY) as i16;
 
-        let mut has_carry = a.checked_add(b).is_none();
-        let mut has_overflow = a_signed.checked_add(b_signed).is_none();
+        let binary = a.wrapping_add(b).wrapping_add(carry as u8);
 
-        if self.registers.p.contains(StatusRegister::CARRY) {
-            let result_signed = result as i8;
+        let mut low = (a & 0x0F) as i16 + (b & 0x0F) as i16 + carry;
+
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+
+        let mut sum = (a & 0xF0) as i16 + (b & 0xF0) as i16 + low;
+        let signed = (a & 0xF0) as i8 as i16 + (b & 0xF0) as i8 as i16 + low;
 
-            has_carry = has_carry || result.checked_add(1).is_none();
-            has_overflow = has_overflow || result_signed.checked_add(1).is_none();
+        self.registers.p.set(StatusRegister::NEGATIVE, sum & 0x80 != 0);
+        self.registers.p.set(StatusRegister::OVERFLOW, !(-128 ..= 127).contains(&signed));
+        self.registers.p.set(StatusRegister::ZERO, binary == 0);
 
-            result = result.wrapping_add(1);
+        if sum >= 0xA0 {
+            sum += 0x60;
         }
 
+        self.registers.p.set(StatusRegister::CARRY, sum >= 0x100);
+
+        sum as u8
+    }
+
+    fn add_binary(&mut self, a: u8, b: u8) -> u8 {
+        let carry = self.registers.p.contains(StatusRegister::CARRY) as u16;
+
+        let sum = a as u16 + b as u16 + carry;
+        let result = sum as u8;
+
+        // Overflow when both inputs share a sign that the result does not.
+        let has_overflow = (a ^ result) & (b ^ result) & 0x80 != 0;
+
         self.set_flags(result);
-        self.registers.p.set(StatusRegister::CARRY, has_carry);
+        self.registers.p.set(StatusRegister::CARRY, sum > 0xFF);
         self.registers.p.set(StatusRegister::OVERFLOW, has_overflow);
 
         result
     }
 
     fn sub(&mut self, a: u8, b: u8) -> u8 {
-        self.add(a, !b)
+        let carry = self.registers.p.contains(StatusRegister::CARRY) as i16;
+
+        // Flags always match the binary subtraction, even in decimal mode.
+        let result = self.add_binary(a, !b);
+
+        if !self.decimal_mode() {
+            return result
+        }
+
+        let mut low = (a & 0x0F) as i16 - (b & 0x0F) as i16 + carry - 1;
+
+        if low < 0 {
+            low = ((low - 0x06) & 0x0F) - 0x10;
+        }
+
+        let mut difference = (a & 0xF0) as i16 - (b & 0xF0) as i16 + low;
+
+        if difference < 0 {
+            difference -= 0x60;
+        }
+
+        difference as u8
+    }
+
+    // Read, modify and write back, the modify step spends the dummy write cycle.
+    fn modify<F: FnOnce(&mut Self, u8) -> u8>(&mut self, address: u16, op: F) -> Result<u8, MemoryError> {
+        let value = self.memory.get(address)?;
+        let value = op(self, value);
+
+        self.memory.set(address, value)?;
+
+        Ok(value)
+    }
+
+    // Indexed read-modify-write always takes the extra cycle, page cross or not.
+    fn index_zp(&mut self, offset: u8, register: u8) -> u16 {
+        self.memory.cycle();
+
+        offset.wrapping_add(register) as u16
+    }
+
+    fn index_a(&mut self, address: u16, register: u8) -> u16 {
+        self.memory.cycle();
+
+        address.wrapping_add(register as u16)
+    }
+
+    fn index_di(&mut self, offset: u8) -> Result<u16, MemoryError> {
+        let pointer = self.get_ptr(offset.wrapping_add(self.registers.x))?;
+
+        self.memory.cycle();
+
+        Ok(pointer)
+    }
+
+    fn index_do(&mut self, offset: u8) -> Result<u16, MemoryError> {
+        let pointer = self.get_ptr(offset)?;
+
+        self.memory.cycle();
+
+        Ok(pointer.wrapping_add(self.registers.y as u16))
+    }
+
+    fn increment(&mut self, value: u8) -> u8 {
+        self.memory.cycle();
+
+        value.wrapping_add(1)
+    }
+
+    fn decrement(&mut self, value: u8) -> u8 {
+        self.memory.cycle();
+
+        value.wrapping_sub(1)
+    }
+
+    fn slo(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::asl)?;
+
+        self.registers.a |= value;
+        self.set_flags(self.registers.a);
+
+        Ok(())
+    }
+
+    fn rla(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::rol)?;
+
+        self.registers.a &= value;
+        self.set_flags(self.registers.a);
+
+        Ok(())
+    }
+
+    fn sre(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::lsr)?;
+
+        self.registers.a ^= value;
+        self.set_flags(self.registers.a);
+
+        Ok(())
+    }
+
+    fn rra(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::ror)?;
+
+        self.registers.a = self.add(self.registers.a, value);
+
+        Ok(())
+    }
+
+    fn dcp(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::decrement)?;
+
+        self.cmp(self.registers.a, value);
+
+        Ok(())
+    }
+
+    fn isc(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.modify(address, Self::increment)?;
+
+        self.registers.a = self.sub(self.registers.a, value);
+
+        Ok(())
     }
 
     fn cmp(&mut self, a: u8, b: u8) {
@@ -1196,6 +1363,32 @@ impl<'a, C1: Controller, C2: Controller> Decoder<Result<(), CpuError>> for Cpu<'
         Ok(())
     }
 
+    fn sax_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.set_zp(offset, self.registers.a & self.registers.x)?;
+
+        Ok(())
+    }
+
+    fn sax_zy(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.set_zpo(offset, self.registers.y, self.registers.a & self.registers.x)?;
+
+        self.memory.cycle();
+
+        Ok(())
+    }
+
+    fn sax_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.set_a(address, self.registers.a & self.registers.x)?;
+
+        Ok(())
+    }
+
+    fn sax_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.set_di(offset, self.registers.x, self.registers.a & self.registers.x)?;
+
+        Ok(())
+    }
+
     fn lda_i(&mut self, value: u8) -> Result<(), CpuError> {
         self.registers.a = value;
 
@@ -1260,6 +1453,122 @@ impl<'a, C1: Controller, C2: Controller> Decoder<Result<(), CpuError>> for Cpu<'
         Ok(())
     }
 
+    fn lax_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        let value = self.get_zp(offset)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn lax_zy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let value = self.get_zpo(offset, self.registers.y)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn lax_a(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.get_a(address)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn lax_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let value = self.get_ao(address, self.registers.y)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn lax_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let value = self.get_di(offset, self.registers.x)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn lax_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let value = self.get_do(offset, self.registers.y)?;
+
+        self.registers.a = value;
+        self.registers.x = value;
+
+        self.set_flags(value);
+
+        Ok(())
+    }
+
+    fn anc_i(&mut self, value: u8) -> Result<(), CpuError> {
+        self.registers.a &= value;
+
+        self.set_flags(self.registers.a);
+        self.registers.p.set(StatusRegister::CARRY, self.registers.a & 0b10000000 != 0);
+
+        Ok(())
+    }
+
+    fn alr_i(&mut self, value: u8) -> Result<(), CpuError> {
+        let input = self.registers.a & value;
+
+        self.registers.a = input >> 1;
+
+        self.set_flags(self.registers.a);
+        self.registers.p.set(StatusRegister::CARRY, input & 0b00000001 != 0);
+
+        Ok(())
+    }
+
+    // AND then ROR, but carry and overflow come from bits 6 and 5 of the result.
+    fn arr_i(&mut self, value: u8) -> Result<(), CpuError> {
+        let carry = if self.registers.p.contains(StatusRegister::CARRY) {
+            0b10000000u8
+        } else {
+            0b00000000u8
+        };
+
+        let result = ((self.registers.a & value) >> 1) | carry;
+
+        self.registers.a = result;
+
+        self.set_flags(result);
+        self.registers.p.set(StatusRegister::CARRY, result & 0b01000000 != 0);
+        self.registers.p.set(StatusRegister::OVERFLOW, ((result >> 6) ^ (result >> 5)) & 1 != 0);
+
+        Ok(())
+    }
+
+    // Compares like CMP, so the incoming carry is ignored.
+    fn axs_i(&mut self, value: u8) -> Result<(), CpuError> {
+        let input = self.registers.a & self.registers.x;
+
+        self.cmp(input, value);
+
+        self.registers.x = input.wrapping_sub(value);
+
+        Ok(())
+    }
+
     fn cmp_i(&mut self, value: u8) -> Result<(), CpuError> {
         self.cmp(self.registers.a, value);
 
@@ -1541,6 +1850,234 @@ impl<'a, C1: Controller, C2: Controller> Decoder<Result<(), CpuError>> for Cpu<'
 
         Ok(())
     }
+
+    fn slo_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.slo(offset as u16)
+    }
+
+    fn slo_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.slo(address)
+    }
+
+    fn slo_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.slo(address)
+    }
+
+    fn slo_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.slo(address)
+    }
+
+    fn slo_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.slo(address)
+    }
+
+    fn slo_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.slo(address)
+    }
+
+    fn slo_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.slo(address)
+    }
+
+    fn rla_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.rla(offset as u16)
+    }
+
+    fn rla_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.rla(address)
+    }
+
+    fn rla_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.rla(address)
+    }
+
+    fn rla_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.rla(address)
+    }
+
+    fn rla_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.rla(address)
+    }
+
+    fn rla_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.rla(address)
+    }
+
+    fn rla_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.rla(address)
+    }
+
+    fn sre_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.sre(offset as u16)
+    }
+
+    fn sre_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.sre(address)
+    }
+
+    fn sre_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.sre(address)
+    }
+
+    fn sre_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.sre(address)
+    }
+
+    fn sre_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.sre(address)
+    }
+
+    fn sre_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.sre(address)
+    }
+
+    fn sre_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.sre(address)
+    }
+
+    fn rra_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.rra(offset as u16)
+    }
+
+    fn rra_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.rra(address)
+    }
+
+    fn rra_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.rra(address)
+    }
+
+    fn rra_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.rra(address)
+    }
+
+    fn rra_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.rra(address)
+    }
+
+    fn rra_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.rra(address)
+    }
+
+    fn rra_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.rra(address)
+    }
+
+    fn dcp_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.dcp(offset as u16)
+    }
+
+    fn dcp_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.dcp(address)
+    }
+
+    fn dcp_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.dcp(address)
+    }
+
+    fn dcp_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.dcp(address)
+    }
+
+    fn dcp_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.dcp(address)
+    }
+
+    fn dcp_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.dcp(address)
+    }
+
+    fn dcp_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.dcp(address)
+    }
+
+    fn isc_z(&mut self, offset: u8) -> Result<(), CpuError> {
+        self.isc(offset as u16)
+    }
+
+    fn isc_zx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_zp(offset, self.registers.x);
+
+        self.isc(address)
+    }
+
+    fn isc_a(&mut self, address: u16) -> Result<(), CpuError> {
+        self.isc(address)
+    }
+
+    fn isc_ax(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.x);
+
+        self.isc(address)
+    }
+
+    fn isc_ay(&mut self, address: u16) -> Result<(), CpuError> {
+        let address = self.index_a(address, self.registers.y);
+
+        self.isc(address)
+    }
+
+    fn isc_dx(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_di(offset)?;
+
+        self.isc(address)
+    }
+
+    fn isc_dy(&mut self, offset: u8) -> Result<(), CpuError> {
+        let address = self.index_do(offset)?;
+
+        self.isc(address)
+    }
 }
 
 impl From<MemoryError> for CpuError {
@@ -1566,6 +2103,8 @@ impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
     pub fn step(&mut self) -> Result<(), CpuError> {
         let pc = self.registers.pc;
 
+        let mut fetched_op = false;
+
         let next = |cpu: &mut Cpu<C1, C2>| {
             let pc = cpu.registers.pc;
 
@@ -1573,6 +2112,14 @@ impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
 
             cpu.registers.pc += 1;
 
+            if !fetched_op {
+                fetched_op = true;
+
+                if let (Some(histogram), Ok(op)) = (&mut cpu.histogram, &value) {
+                    histogram[*op as usize] += 1;
+                }
+            }
+
             value.ok()
         };
 
@@ -1580,9 +2127,525 @@ impl<'a, C1: Controller, C2: Controller> Cpu<'a, C1, C2> {
 
         result.unwrap_or_else(|| {
             match self.memory.get(pc) {
-                Ok(op) => Err(InvalidOp(op)),
+                Ok(op) => {
+                    if let Some(hook) = &mut self.invalid_op_hook {
+                        hook(pc, op)
+                    }
+
+                    Err(InvalidOp(op))
+                },
                 Err(error) => Err(Memory(error))
             }
-        })
+        })?;
+
+        if self.memory.ppu.pending_nmi {
+            self.memory.ppu.pending_nmi = false;
+
+            self.interrupt(self.vectors.nmi).map_err(Memory)?;
+        }
+
+        self.memory.run_apu();
+
+        if self.memory.ppu.memory.mapper.irq_pending() || self.memory.apu.irq_pending() {
+            self.irq().map_err(Memory)?;
+        }
+
+        Ok(())
+    }
+
+    // Steps once, returning the cycles the instruction (and any NMI it raised) took.
+    pub fn step_with_cycles(&mut self) -> Result<u64, CpuError> {
+        let start = self.memory.cycles;
+
+        self.step()?;
+
+        Ok(self.memory.cycles - start)
+    }
+
+    // Steps whole instructions until at least target cycles have passed, so it can overshoot.
+    pub fn run_until_cycles(&mut self, target: u64) -> Result<(), CpuError> {
+        while self.memory.cycles < target {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::NoController;
+    use crate::rom::Rom;
+    use super::*;
+
+    type TestCpu<'a> = Cpu<'a, NoController, NoController>;
+
+    fn cpu(rom: &Rom) -> TestCpu<'_> {
+        Cpu::new(rom, None, (NoController, NoController))
+    }
+
+    fn run(cpu: &mut TestCpu, steps: usize) {
+        for _ in 0 .. steps {
+            cpu.step().unwrap();
+        }
+    }
+
+    // Most recently pushed byte.
+    fn stack_top(cpu: &mut TestCpu) -> u8 {
+        cpu.stack_snapshot()[0]
+    }
+
+    #[test]
+    fn php_pushes_break_and_unused() {
+        // SED, SEC, PHP
+        let rom = Rom::from_prg(&[0xF8, 0x38, 0x08], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        run(&mut cpu, 3);
+
+        let expected = StatusRegister::ENABLED | StatusRegister::INTERUPT
+            | StatusRegister::DECIMAL | StatusRegister::CARRY;
+
+        assert_eq!(stack_top(&mut cpu), expected.bits() | StatusRegister::BREAK.bits());
+        assert_eq!(cpu.registers.p.bits(), expected.bits());
+    }
+
+    #[test]
+    fn interrupts_push_break_clear() {
+        // SED, CLI
+        let rom = Rom::from_prg(&[0xF8, 0x58], 0x8000);
+        let expected = (StatusRegister::ENABLED | StatusRegister::DECIMAL).bits();
+
+        let mut nmi = cpu(&rom);
+
+        run(&mut nmi, 2);
+        nmi.interrupt(nmi.vectors.nmi).unwrap();
+
+        assert_eq!(stack_top(&mut nmi), expected);
+        assert!(nmi.registers.p.contains(StatusRegister::INTERUPT));
+
+        let mut irq = cpu(&rom);
+
+        run(&mut irq, 2);
+
+        assert!(irq.irq().unwrap());
+        assert_eq!(stack_top(&mut irq), expected);
+    }
+
+    #[test]
+    fn plp_ignores_break_and_unused() {
+        // LDA #$FF, PHA, PLP, LDA #$00, PHA, PLP
+        let rom = Rom::from_prg(&[0xA9, 0xFF, 0x48, 0x28, 0xA9, 0x00, 0x48, 0x28], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        run(&mut cpu, 3);
+
+        assert_eq!(cpu.registers.p.bits(), !StatusRegister::BREAK.bits());
+
+        run(&mut cpu, 3);
+
+        assert_eq!(cpu.registers.p.bits(), StatusRegister::ENABLED.bits());
+    }
+
+    #[test]
+    fn php_plp_restores_flags() {
+        // SED, SEC, PHP, CLD, CLC, PLP
+        let rom = Rom::from_prg(&[0xF8, 0x38, 0x08, 0xD8, 0x18, 0x28], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        run(&mut cpu, 5);
+
+        assert!(!cpu.registers.p.contains(StatusRegister::DECIMAL));
+
+        run(&mut cpu, 1);
+
+        let expected = StatusRegister::ENABLED | StatusRegister::INTERUPT
+            | StatusRegister::DECIMAL | StatusRegister::CARRY;
+
+        assert_eq!(cpu.registers.p.bits(), expected.bits());
+    }
+
+    #[test]
+    fn opcode_histogram_counts() {
+        // LDX #$03, loop: DEX, BNE loop, NOP
+        let rom = Rom::from_prg(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xEA], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        assert!(cpu.opcode_histogram().is_none());
+
+        cpu.enable_opcode_histogram();
+
+        run(&mut cpu, 8);
+
+        let histogram = cpu.opcode_histogram().unwrap();
+
+        assert_eq!([0xA2, 0xCA, 0xD0, 0xEA].map(|op| histogram[op]), [1, 3, 3, 1]);
+        assert_eq!(histogram.iter().sum::<u64>(), 8);
+
+        cpu.disable_opcode_histogram();
+
+        assert!(cpu.opcode_histogram().is_none());
+    }
+
+    // a + b (or a - b) with the decimal flag and carry set up, returning the result and carry.
+    fn decimal(cpu: &mut TestCpu, subtract: bool, a: u8, b: u8, carry: bool) -> (u8, bool) {
+        cpu.registers.p.insert(StatusRegister::DECIMAL);
+        cpu.registers.p.set(StatusRegister::CARRY, carry);
+
+        let result = if subtract { cpu.sub(a, b) } else { cpu.add(a, b) };
+
+        (result, cpu.registers.p.contains(StatusRegister::CARRY))
+    }
+
+    #[test]
+    fn decimal_add() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        // The 2A03 ignores the decimal flag.
+        assert_eq!(decimal(&mut cpu, false, 0x09, 0x01, false), (0x0A, false));
+
+        cpu.decimal_enabled = true;
+
+        assert_eq!(decimal(&mut cpu, false, 0x09, 0x01, false), (0x10, false));
+        assert_eq!(decimal(&mut cpu, false, 0x99, 0x01, false), (0x00, true));
+
+        // NMOS quirks, Z follows the binary sum ($9A) and N the unadjusted high nibble.
+        assert!(!cpu.registers.p.contains(StatusRegister::ZERO));
+        assert!(cpu.registers.p.contains(StatusRegister::NEGATIVE));
+
+        assert_eq!(decimal(&mut cpu, false, 0x25, 0x48, true), (0x74, false));
+    }
+
+    #[test]
+    fn decimal_subtract() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        cpu.decimal_enabled = true;
+
+        assert_eq!(decimal(&mut cpu, true, 0x10, 0x01, true), (0x09, true));
+        assert_eq!(decimal(&mut cpu, true, 0x00, 0x01, true), (0x99, false));
+        // Borrowing in from a previous subtraction.
+        assert_eq!(decimal(&mut cpu, true, 0x05, 0x05, false), (0x99, false));
+        assert_eq!(decimal(&mut cpu, true, 0x42, 0x17, true), (0x25, true));
+    }
+
+    // Steps through each instruction, returning A, X and the cycles taken.
+    fn trace(cpu: &mut TestCpu, steps: usize) -> Vec<(u8, u8, u64)> {
+        (0 .. steps)
+            .map(|_| {
+                let cycles = cpu.step_with_cycles().unwrap();
+
+                (cpu.registers.a, cpu.registers.x, cycles)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lax_loads_a_and_x() {
+        let program = [
+            0xA0, 0x02,             // LDY #$02
+            0xA7, 0x10,             // LAX $10
+            0xB7, 0x10,             // LAX $10,Y
+            0xAF, 0x00, 0x03,       // LAX $0300
+            0xBF, 0xFF, 0x02,       // LAX $02FF,Y (page cross)
+            0xBF, 0x00, 0x03,       // LAX $0300,Y
+            0xA2, 0x04,             // LDX #$04
+            0xA3, 0x1C,             // LAX ($1C,X)
+            0xB3, 0x30,             // LAX ($30),Y
+            0xB3, 0x32,             // LAX ($32),Y (page cross)
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = cpu(&rom);
+
+        let ram = &mut cpu.memory.ram;
+
+        ram[0x10] = 0x80;
+        ram[0x12] = 0x00;
+        ram[0x300 .. 0x304].copy_from_slice(&[0x11, 0x22, 0x33, 0x55]);
+        ram[0x401] = 0x44;
+        ram[0x20 .. 0x22].copy_from_slice(&[0x03, 0x03]);
+        ram[0x30 .. 0x34].copy_from_slice(&[0x00, 0x03, 0xFF, 0x03]);
+
+        run(&mut cpu, 1);
+
+        assert_eq!(trace(&mut cpu, 2), vec![(0x80, 0x80, 3), (0x00, 0x00, 4)]);
+        assert!(cpu.registers.p.contains(StatusRegister::ZERO));
+
+        assert_eq!(trace(&mut cpu, 3), vec![(0x11, 0x11, 4), (0x22, 0x22, 5), (0x33, 0x33, 4)]);
+
+        run(&mut cpu, 1);
+
+        assert_eq!(trace(&mut cpu, 3), vec![(0x55, 0x55, 6), (0x33, 0x33, 5), (0x44, 0x44, 6)]);
+        assert!(!cpu.registers.p.contains(StatusRegister::ZERO));
+        assert!(!cpu.registers.p.contains(StatusRegister::NEGATIVE));
+    }
+
+    #[test]
+    fn sax_stores_a_and_x() {
+        let program = [
+            0xA9, 0xF0,             // LDA #$F0
+            0xA2, 0x0F,             // LDX #$0F
+            0xA0, 0x01,             // LDY #$01
+            0x38,                   // SEC
+            0x87, 0x10,             // SAX $10
+            0x97, 0x10,             // SAX $10,Y
+            0x8F, 0x00, 0x03,       // SAX $0300
+            0x83, 0x20,             // SAX ($20,X)
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = cpu(&rom);
+
+        cpu.memory.ram[0x2F .. 0x31].copy_from_slice(&[0x05, 0x03]);
+
+        for address in [0x10, 0x11, 0x300, 0x305] {
+            cpu.memory.ram[address] = 0xFF;
+        }
+
+        run(&mut cpu, 4);
+
+        let flags = cpu.registers.p.bits();
+
+        let cycles: Vec<u64> = (0 .. 4).map(|_| cpu.step_with_cycles().unwrap()).collect();
+
+        assert_eq!(cycles, vec![3, 4, 4, 6]);
+
+        for address in [0x10, 0x11, 0x300, 0x305] {
+            assert_eq!(cpu.memory.ram[address], 0x00, "${address:04X}");
+        }
+
+        // C set, N and Z clear, as the loads and SEC left them, even though the stored byte is zero.
+        assert_eq!(cpu.registers.p.bits(), flags);
+        assert_eq!(flags & (StatusRegister::CARRY | StatusRegister::NEGATIVE | StatusRegister::ZERO).bits(), StatusRegister::CARRY.bits());
+    }
+
+    #[test]
+    fn dcp_counter_loop() {
+        let program = [
+            0xA9, 0x02,             // LDA #$02
+            0xC7, 0x10,             // loop: DCP $10
+            0xD0, 0xFC,             // BNE loop
+            0xA2, 0x00,             // LDX #$00
+            0xA0, 0x00,             // LDY #$00
+            0xCF, 0x00, 0x03,       // DCP $0300
+            0xDF, 0x00, 0x03,       // DCP $0300,X
+            0xDB, 0x00, 0x03,       // DCP $0300,Y
+            0xD7, 0x10,             // DCP $10,X
+            0xC3, 0x20,             // DCP ($20,X)
+            0xD3, 0x20,             // DCP ($20),Y
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = cpu(&rom);
+
+        cpu.memory.ram[0x10] = 5;
+        cpu.memory.ram[0x300] = 0x10;
+        cpu.memory.ram[0x20 .. 0x22].copy_from_slice(&[0x00, 0x03]);
+
+        run(&mut cpu, 1);
+
+        let mut iterations = 0;
+
+        while cpu.registers.pc != 0x8006 {
+            assert_eq!(cpu.step_with_cycles().unwrap(), 5);
+
+            // CMP against A, the loop ends once the counter reaches it.
+            let carry = cpu.registers.p.contains(StatusRegister::CARRY);
+
+            assert_eq!(carry, cpu.memory.ram[0x10] <= 2);
+
+            iterations += 1;
+
+            cpu.step().unwrap();
+        }
+
+        assert_eq!((iterations, cpu.memory.ram[0x10]), (3, 2));
+        assert!(cpu.registers.p.contains(StatusRegister::ZERO));
+
+        run(&mut cpu, 2);
+
+        let cycles: Vec<u64> = (0 .. 6).map(|_| cpu.step_with_cycles().unwrap()).collect();
+
+        assert_eq!(cycles, vec![6, 7, 7, 6, 8, 8]);
+        assert_eq!((cpu.memory.ram[0x300], cpu.memory.ram[0x10]), (0x0B, 1));
+        assert_eq!(cpu.registers.a, 0x02);
+    }
+
+    // Runs a single immediate mode opcode from the given A, X and carry,
+    // returning A, X, the N, V, Z and C flags (in that order) and the cycles taken.
+    fn immediate(opcode: u8, value: u8, a: u8, x: u8, carry: bool) -> (u8, u8, [bool; 4], u64) {
+        let rom = Rom::from_prg(&[opcode, value], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        cpu.registers.a = a;
+        cpu.registers.x = x;
+        cpu.registers.p.set(StatusRegister::CARRY, carry);
+
+        let cycles = cpu.step_with_cycles().unwrap();
+
+        let flags = [StatusRegister::NEGATIVE, StatusRegister::OVERFLOW, StatusRegister::ZERO, StatusRegister::CARRY]
+            .map(|flag| cpu.registers.p.contains(flag));
+
+        (cpu.registers.a, cpu.registers.x, flags, cycles)
+    }
+
+    #[test]
+    fn anc_copies_bit_7_to_carry() {
+        for opcode in [0x0B, 0x2B] {
+            assert_eq!(immediate(opcode, 0x80, 0xFF, 0, false), (0x80, 0, [true, false, false, true], 2));
+            assert_eq!(immediate(opcode, 0x0F, 0xFF, 0, true), (0x0F, 0, [false, false, false, false], 2));
+        }
+    }
+
+    #[test]
+    fn alr_ands_then_shifts() {
+        assert_eq!(immediate(0x4B, 0xFF, 0x03, 0, false), (0x01, 0, [false, false, false, true], 2));
+        assert_eq!(immediate(0x4B, 0x01, 0x01, 0, false), (0x00, 0, [false, false, true, true], 2));
+        assert_eq!(immediate(0x4B, 0xF0, 0xAA, 0, true), (0x50, 0, [false, false, false, false], 2));
+    }
+
+    #[test]
+    fn arr_carry_and_overflow_from_bits_6_and_5() {
+        assert_eq!(immediate(0x6B, 0xFF, 0xFF, 0, true), (0xFF, 0, [true, false, false, true], 2));
+        assert_eq!(immediate(0x6B, 0x80, 0xFF, 0, false), (0x40, 0, [false, true, false, true], 2));
+        assert_eq!(immediate(0x6B, 0x40, 0xFF, 0, false), (0x20, 0, [false, true, false, false], 2));
+        assert_eq!(immediate(0x6B, 0x01, 0xFF, 0, false), (0x00, 0, [false, false, true, false], 2));
+    }
+
+    #[test]
+    fn axs_subtracts_from_a_and_x() {
+        assert_eq!(immediate(0xCB, 0x10, 0xF0, 0x3C, false), (0xF0, 0x20, [false, false, false, true], 2));
+        assert_eq!(immediate(0xCB, 0x40, 0xF0, 0x3C, true), (0xF0, 0xF0, [true, false, false, false], 2));
+        assert_eq!(immediate(0xCB, 0x30, 0xF0, 0x3C, false), (0xF0, 0x00, [false, false, true, true], 2));
+    }
+
+    #[test]
+    fn irq_respects_interrupt_disable() {
+        // SEI, CLI, then the handler at $8010.
+        let mut rom = Rom::from_prg(&[0x78, 0x58], 0x8000);
+
+        rom.prg_rom[0x7FFE .. 0x8000].copy_from_slice(&0x8010u16.to_le_bytes());
+
+        let mut cpu = cpu(&rom);
+
+        run(&mut cpu, 1);
+
+        assert!(!cpu.irq().unwrap());
+        assert_eq!((cpu.registers.pc, cpu.registers.sp), (0x8001, 0xFD));
+
+        run(&mut cpu, 1);
+
+        assert!(cpu.irq().unwrap());
+        assert_eq!(cpu.registers.pc, 0x8010);
+        assert!(cpu.registers.p.contains(StatusRegister::INTERUPT));
+
+        // Return address, then status with BREAK clear and INTERUPT as it was (clear).
+        assert_eq!(cpu.stack_snapshot()[1 .. 3], [0x02, 0x80]);
+        assert_eq!(stack_top(&mut cpu) & (StatusRegister::BREAK | StatusRegister::INTERUPT).bits(), 0);
+    }
+
+    #[test]
+    fn invalid_op_hook_reports_pc() {
+        // NOP, NOP, XAA #$00 (unimplemented)
+        let rom = Rom::from_prg(&[0xEA, 0xEA, 0x8B, 0x00], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let hook_seen = seen.clone();
+
+        cpu.on_invalid_op(move |pc, op| hook_seen.lock().unwrap().push((pc, op)));
+
+        run(&mut cpu, 2);
+
+        assert!(seen.lock().unwrap().is_empty());
+        assert!(matches!(cpu.step(), Err(InvalidOp(0x8B))));
+        assert_eq!(*seen.lock().unwrap(), vec![(0x8002, 0x8B)]);
+    }
+
+    #[test]
+    fn adc_overflow_quadrants() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut cpu = cpu(&rom);
+
+        // (a, b, carry in) => (result, carry out, overflow)
+        let table = [
+            ((0x50, 0x10, false), (0x60, false, false)),
+            ((0x50, 0x50, false), (0xA0, false, true)),
+            ((0x50, 0x90, false), (0xE0, false, false)),
+            ((0x50, 0xD0, false), (0x20, true, false)),
+            ((0xD0, 0x10, false), (0xE0, false, false)),
+            ((0xD0, 0x50, false), (0x20, true, false)),
+            ((0xD0, 0x90, false), (0x60, true, true)),
+            ((0xD0, 0xD0, false), (0xA0, true, false)),
+            ((0xFF, 0x01, true), (0x01, true, false)),
+            ((0xFF, 0x00, true), (0x00, true, false)),
+            ((0x7F, 0x00, true), (0x80, false, true)),
+            ((0x7F, 0x01, false), (0x80, false, true)),
+            ((0x80, 0xFF, false), (0x7F, true, true)),
+        ];
+
+        for ((a, b, carry), expected) in table {
+            cpu.registers.p.set(StatusRegister::CARRY, carry);
+
+            let result = cpu.add(a, b);
+
+            let flags = (
+                result,
+                cpu.registers.p.contains(StatusRegister::CARRY),
+                cpu.registers.p.contains(StatusRegister::OVERFLOW),
+            );
+
+            assert_eq!(flags, expected, "${a:02X} + ${b:02X} + {}", carry as u8);
+        }
+    }
+
+    const TIMED_PROGRAM: [u8; 17] = [
+        0xA9, 0x01,             // LDA #$01
+        0x85, 0x10,             // STA $10
+        0xE6, 0x10,             // INC $10
+        0xAE, 0x00, 0x03,       // LDX $0300
+        0x20, 0x10, 0x80,       // JSR $8010
+        0x00, 0x00, 0x00, 0x00,
+        0x60,                   // RTS
+    ];
+
+    #[test]
+    fn step_with_cycles_totals() {
+        let rom = Rom::from_prg(&TIMED_PROGRAM, 0x8000);
+        let mut cpu = cpu(&rom);
+
+        let start = cpu.memory.cycles;
+        let cycles: Vec<u64> = (0 .. 6).map(|_| cpu.step_with_cycles().unwrap()).collect();
+
+        assert_eq!(cycles, vec![2, 3, 5, 4, 6, 6]);
+        assert_eq!(cpu.memory.cycles - start, 26);
+        assert_eq!(cpu.registers.pc, 0x800C);
+    }
+
+    #[test]
+    fn run_until_cycles_stops_after_target() {
+        let rom = Rom::from_prg(&TIMED_PROGRAM, 0x8000);
+        let mut cpu = cpu(&rom);
+
+        let start = cpu.memory.cycles;
+
+        // Lands exactly on the end of INC.
+        cpu.run_until_cycles(start + 10).unwrap();
+
+        assert_eq!((cpu.memory.cycles - start, cpu.registers.pc), (10, 0x8006));
+
+        // Never stops part way through an instruction, LDX runs to its end.
+        cpu.run_until_cycles(start + 11).unwrap();
+
+        assert_eq!((cpu.memory.cycles - start, cpu.registers.pc), (14, 0x8009));
+
+        // Already past the target, nothing runs.
+        cpu.run_until_cycles(start).unwrap();
+
+        assert_eq!(cpu.memory.cycles - start, 14);
     }
 }
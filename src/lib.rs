@@ -9,3 +9,16 @@ pub mod renderer;
 pub mod software;
 pub mod controller;
 pub mod state;
+pub mod genie;
+pub mod recorder;
+pub mod effective;
+pub mod compare;
+pub mod chr_cache;
+pub mod palette;
+pub mod mapper;
+pub mod mmc1;
+pub mod mmc3;
+pub mod uxrom;
+pub mod cnrom;
+pub mod apu;
+pub mod nes;
@@ -0,0 +1,153 @@
+use crate::cnrom::CnRom;
+use crate::mmc1::Mmc1;
+use crate::mmc3::Mmc3;
+use crate::rom::{Mirroring, Rom};
+use crate::uxrom::UxRom;
+
+// Cartridge hardware. The CPU side sees $4020-$5FFF and $8000-$FFFF
+// ($6000-$7FFF stays with Memory::saved), the PPU side sees $0000-$1FFF.
+pub trait Mapper: Send {
+    // None leaves the address unmapped.
+    fn cpu_read(&mut self, address: u16) -> Option<u8>;
+    // Returns false if nothing is mapped at address. cycle is Memory::cycles at the write.
+    fn cpu_write(&mut self, address: u16, value: u8, cycle: u64) -> bool;
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8>;
+    fn ppu_write(&mut self, address: u16, value: u8) -> bool;
+
+    // Name table mirroring chosen by the mapper, None leaves it to the PPU.
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    // Called by the renderer once per rendered scanline (where PPU A12 would rise).
+    fn on_scanline(&mut self) { }
+
+    // Level triggered, Cpu::step services it while the interrupt flag is clear.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    // Bank registers and other mapper internals, stored in CpuState.
+    fn save_state(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn restore_state(&mut self, _: &[u8]) { }
+}
+
+// Mapper 0, PRG and CHR are fixed. 16KB of PRG is mirrored into $C000.
+pub struct NromMapper<'a> {
+    rom: &'a Rom
+}
+
+impl<'a> NromMapper<'a> {
+    pub fn new(rom: &'a Rom) -> NromMapper<'a> {
+        NromMapper { rom }
+    }
+}
+
+impl<'a> Mapper for NromMapper<'a> {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                let target = (address - 0x8000) as usize % self.rom.prg_rom.len();
+
+                Some(self.rom.prg_rom[target])
+            }
+            _ => None
+        }
+    }
+
+    fn cpu_write(&mut self, _: u16, _: u8, _: u64) -> bool {
+        false
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        self.rom.chr_rom.get(address as usize).copied()
+    }
+
+    fn ppu_write(&mut self, _: u16, _: u8) -> bool {
+        false
+    }
+}
+
+pub fn is_supported_mapper(mapper: u16) -> bool {
+    matches!(mapper, 0 ..= 4)
+}
+
+// Unknown mapper numbers fall back to NROM, Rom::load rejects them before that.
+pub fn create_mapper(rom: &Rom) -> Box<dyn Mapper + '_> {
+    // NES 2.0 submapper 2 marks UxROM and CNROM boards with AND bus conflicts.
+    let bus_conflicts = rom.flags.is_nes2() && rom.flags.submapper == 2;
+
+    match rom.flags.mapper {
+        1 => Box::new(Mmc1::new(rom)),
+        2 => Box::new(UxRom::new(rom, bus_conflicts)),
+        3 => Box::new(CnRom::new(rom, bus_conflicts)),
+        4 => Box::new(Mmc3::new(rom)),
+        _ => Box::new(NromMapper::new(rom)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::PpuMemory;
+    use super::*;
+
+    // Each byte holds its 1KB block number, so the block a read lands in is visible.
+    fn nrom(prg_size: usize, mirroring: Mirroring) -> Rom {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.prg_rom = (0 .. prg_size).map(|i| (i / 0x400) as u8).collect();
+        rom.flags.mirroring = mirroring;
+
+        rom
+    }
+
+    #[test]
+    fn nrom_16k_mirrors_into_c000() {
+        let rom = nrom(0x4000, Mirroring::Horizontal);
+        let mut mapper = NromMapper::new(&rom);
+
+        for offset in [0, 0x1234, 0x3FFF] {
+            assert_eq!(mapper.cpu_read(0x8000 + offset), mapper.cpu_read(0xC000 + offset));
+        }
+
+        assert_eq!(mapper.cpu_read(0xFFFF), Some(15));
+    }
+
+    #[test]
+    fn nrom_32k_does_not_mirror() {
+        let rom = nrom(0x8000, Mirroring::Horizontal);
+        let mut mapper = NromMapper::new(&rom);
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(0));
+        assert_eq!(mapper.cpu_read(0xC000), Some(16));
+        assert_eq!(mapper.cpu_read(0xFFFF), Some(31));
+        assert_eq!(mapper.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn nrom_name_table_mirroring() {
+        // Logical tables at $2000, $2400, $2800 and $2C00.
+        for (mirroring, pages) in [(Mirroring::Horizontal, [0, 0, 1, 1]), (Mirroring::Vertical, [0, 1, 0, 1])] {
+            let rom = nrom(0x4000, mirroring);
+            let mut memory = PpuMemory::new(&rom);
+
+            assert_eq!([0, 1, 2, 3].map(|table| memory.name_table_page(table)), pages);
+
+            // $3000-$3EFF mirrors $2000-$2EFF.
+            assert_eq!(memory.name_table_page(4 + 2), pages[2]);
+
+            for (table, page) in pages.into_iter().enumerate() {
+                let address = 0x2000 + table as u16 * 0x400 + 0x21;
+
+                memory.write(address, table as u8 + 1).unwrap();
+
+                assert_eq!(memory.names[page].contents[0x21], table as u8 + 1);
+                assert_eq!(memory.read(address + 0x1000).unwrap(), table as u8 + 1);
+            }
+        }
+    }
+}
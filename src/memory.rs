@@ -1,8 +1,13 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use crate::apu::Apu;
 use crate::controller::Controller;
+use crate::genie::GameGenieCode;
 use crate::ppu::{Ppu, PpuMemoryError};
-use crate::rom::Rom;
+use crate::rom::{Rom, TRAINER_SIZE};
+
+// CPU cycles lost to each DMC sample fetch.
+pub const DMC_STALL_CYCLES: u64 = 4;
 
 #[derive(Clone, Debug)]
 pub enum MemoryError {
@@ -11,14 +16,29 @@ pub enum MemoryError {
     PpuError(PpuMemoryError)
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnmappedWrites {
+    // Return MemoryError::UnmappedWrite, halting the CPU.
+    #[default]
+    Error,
+    Ignore,
+    // Ignore, but keep the address and value in Memory::ignored_writes.
+    Record,
+}
+
 pub struct Memory<'a, C1: Controller, C2: Controller> {
     pub cycles: u64,
     pub ram: [u8; 0x800],
     pub rom: &'a Rom,
     pub ppu: Ppu<'a>,
+    pub apu: Apu,
     pub saved: [u8; 0x2000], // 0x6000
     pub controller_cycles: (u64, u64),
     pub controllers: (C1, C2),
+    pub game_genie: Vec<GameGenieCode>,
+    pub unmapped_writes: UnmappedWrites,
+    // Filled under UnmappedWrites::Record, see take_ignored_writes.
+    pub ignored_writes: Vec<(u16, u8)>,
 }
 
 impl From<PpuMemoryError> for MemoryError {
@@ -76,10 +96,14 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
 
                 self.ram[target]
             },
-            0x2002 => self.ppu.read_status(),
+            0x2002 => self.ppu.read_status(self.cycles),
             0x2004 => self.ppu.read_oam_data(),
             0x2007 => self.ppu.read_data()?,
-            0x4015 => 0, // APU Status
+            0x4015 => {
+                self.run_apu();
+
+                self.apu.read_status(self.cycles)
+            }
             0x4016 => {
                 let value = self.controllers.0.read(self.controller_cycles.0);
 
@@ -99,15 +123,51 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
 
                 self.saved[target]
             },
-            0x8000..=0xffff => {
-                let target = (address - 0x8000) as usize % self.rom.prg_rom.len();
+            0x4020..=0xFFFF => {
+                let value = self.ppu.memory.mapper.cpu_read(address)
+                    .ok_or(MemoryError::UnmappedRead(address))?;
 
-                self.rom.prg_rom[target]
+                self.game_genie.iter()
+                    .fold(value, |value, code| code.apply(address, value))
             },
             _ => return Err(MemoryError::UnmappedRead(address))
         })
     }
 
+    // Catches the APU up to the CPU, reading DMC samples for it.
+    // Each fetch stalls the CPU, so cycles moves forward along the way.
+    pub fn run_apu(&mut self) {
+        loop {
+            self.apu.run(self.cycles);
+
+            let Some(address) = self.apu.dmc.fetch_address() else {
+                break
+            };
+
+            // Open bus is not modelled, unmapped samples read as silence.
+            let value = self.pass_get(address).unwrap_or(0);
+
+            self.apu.dmc.fill(value);
+
+            self.cycle_many(DMC_STALL_CYCLES);
+        }
+    }
+
+    // Like pass_get, but leaves every register alone. Reads of registers with side effects
+    // (PPU, APU and controller ports) give None rather than a value.
+    pub fn peek(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0..=0x1fff => Some(self.ram[(address % 0x800) as usize]),
+            0x2000..=0x401F => None,
+            0x6000..=0x7FFF => Some(self.saved[(address - 0x6000) as usize]),
+            _ => {
+                let value = self.ppu.memory.mapper.cpu_read(address)?;
+
+                Some(self.game_genie.iter().fold(value, |value, code| code.apply(address, value)))
+            }
+        }
+    }
+
     pub fn get(&mut self, address: u16) -> Result<u8, MemoryError> {
         self.cycle();
 
@@ -121,6 +181,8 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
 
                 self.ram[target] = value
             },
+            // Ignored for the first frame after power on.
+            0x2000 | 0x2001 | 0x2005 | 0x2006 if self.ppu.warming_up(self.cycles) => (),
             0x2000 => self.ppu.write_ctrl(value),
             0x2001 => self.ppu.write_mask(value),
             0x2003 => self.ppu.write_oam_address(value),
@@ -128,17 +190,27 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
             0x2005 => self.ppu.write_scroll(value),
             0x2006 => self.ppu.write_address(value),
             0x2007 => self.ppu.write_data(value)?,
-            0x4000..=0x4013 => (), // APU
             0x4014 => self.oam_dma(value)?,
-            0x4015 => (), // APU Status
-            0x4016 => (), // Controller
-            0x4017 => (), // APU Frame Counter
+            0x4016 => {
+                self.controllers.0.write(value);
+                self.controllers.1.write(value);
+            }, // Controller
+            0x4000..=0x4017 => {
+                self.run_apu();
+
+                self.apu.write(address, value, self.cycles)
+            }
             0x6000..=0x7FFF => {
                 let target = (address - 0x6000) as usize;
 
                 self.saved[target] = value
             }
-            _ => return Err(MemoryError::UnmappedWrite(address))
+            0x4020..=0xFFFF if self.ppu.memory.write_mapper(address, value, self.cycles) => (),
+            _ => match self.unmapped_writes {
+                UnmappedWrites::Error => return Err(MemoryError::UnmappedWrite(address)),
+                UnmappedWrites::Ignore => (),
+                UnmappedWrites::Record => self.ignored_writes.push((address, value)),
+            }
         }
 
         Ok(())
@@ -150,6 +222,39 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
         self.pass_set(address, value)
     }
     
+    // Replaces all of OAM at once, without the cycles of a real DMA.
+    // Pins the next $4016 and $4017 reads to a button, whichever way the controllers are clocked.
+    pub fn set_controller_cycles(&mut self, cycles: (u64, u64)) {
+        self.controller_cycles = cycles;
+
+        self.controllers.0.set_cycles(cycles.0);
+        self.controllers.1.set_cycles(cycles.1);
+    }
+
+    pub fn set_oam(&mut self, oam: [u8; 256]) {
+        self.ppu.replace_oam(oam)
+    }
+
+    // PRG RAM at $6000-$7FFF, what a battery keeps between sessions.
+    pub fn save_ram(&self) -> &[u8] {
+        &self.saved
+    }
+
+    // Returns false (leaving RAM alone) if data is not exactly the size of PRG RAM.
+    pub fn load_ram(&mut self, data: &[u8]) -> bool {
+        let Ok(data) = data.try_into() else {
+            return false
+        };
+
+        self.saved = data;
+
+        true
+    }
+
+    pub fn add_game_genie(&mut self, code: GameGenieCode) {
+        self.game_genie.push(code)
+    }
+
     pub fn get_short(&mut self, address: u16) -> Result<u16, MemoryError> {
         let low = self.get(address)? as u16;
         let high = self.get(address.wrapping_add(1))? as u16;
@@ -168,14 +273,132 @@ impl<'a, C1: Controller, C2: Controller> Memory<'a, C1, C2> {
     */
 
     pub fn new(rom: &'a Rom, controllers: (C1, C2)) -> Memory<'a, C1, C2> {
+        let mut saved = [0; 0x2000];
+
+        if let Some(trainer) = &rom.trainer {
+            saved[0x1000 .. 0x1000 + TRAINER_SIZE].copy_from_slice(trainer);
+        }
+
         Memory {
             cycles: 0,
             ram: [0; 0x800],
             ppu: Ppu::new(rom),
+            apu: Apu::default(),
             rom,
-            saved: [0; 0x2000],
+            saved,
             controller_cycles: (0, 0),
             controllers,
+            game_genie: vec![],
+            unmapped_writes: UnmappedWrites::default(),
+            ignored_writes: vec![],
         }
     }
+
+    // (address, value) of writes ignored since the last call, oldest first.
+    pub fn take_ignored_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.ignored_writes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::{ControllerFlags, GenericController, NoController};
+    use crate::rom::Rom;
+    use super::*;
+
+    #[test]
+    fn unmapped_writes() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut memory = Memory::new(&rom, (NoController, NoController));
+
+        assert!(matches!(memory.set(0x5000, 0x12), Err(MemoryError::UnmappedWrite(0x5000))));
+
+        memory.unmapped_writes = UnmappedWrites::Ignore;
+
+        assert!(memory.set(0x5000, 0x12).is_ok());
+        assert!(memory.take_ignored_writes().is_empty());
+
+        memory.unmapped_writes = UnmappedWrites::Record;
+
+        assert!(memory.set(0x5000, 0x34).is_ok());
+        assert_eq!(memory.take_ignored_writes(), vec![(0x5000, 0x34)]);
+        assert!(memory.take_ignored_writes().is_empty());
+    }
+
+    #[test]
+    fn set_oam_reads_back() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut memory = Memory::new(&rom, (NoController, NoController));
+
+        let mut oam = [0xFF; 256];
+
+        oam[12 .. 16].copy_from_slice(&[0x40, 0x07, 0b01000001, 0x90]);
+
+        let cycles = memory.cycles;
+
+        memory.set_oam(oam);
+
+        assert_eq!(memory.cycles, cycles);
+
+        let sprite = memory.ppu.memory.oam[3];
+
+        assert_eq!((sprite.y, sprite.number, sprite.mask, sprite.x), (0x40, 0x07, 0b01000001, 0x90));
+        assert_eq!(memory.ppu.oam_bytes()[12 .. 16], oam[12 .. 16]);
+    }
+
+    #[test]
+    fn dmc_fetches_stall_the_cpu() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut memory = Memory::new(&rom, (NoController, NoController));
+
+        // 17 bytes from $C000 at the fastest rate, no loop or IRQ.
+        for (address, value) in [(0x4010, 0x0F), (0x4012, 0x00), (0x4013, 0x01), (0x4015, 0b10000)] {
+            memory.pass_set(address, value).unwrap();
+        }
+
+        let mut stalled = 0;
+
+        // Long enough to play every byte (8 bits of 54 cycles each).
+        while memory.cycles < 17 * 8 * 54 + 1000 {
+            memory.cycle();
+
+            let cycles = memory.cycles;
+
+            memory.run_apu();
+
+            stalled += memory.cycles - cycles;
+        }
+
+        assert_eq!(stalled, 17 * DMC_STALL_CYCLES);
+        assert_eq!(memory.apu.dmc.bytes_remaining, 0);
+        assert_eq!(memory.apu.read_status(memory.cycles) & 0b10000, 0);
+    }
+
+    #[test]
+    fn controller_cycles_pin_the_next_read() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let controllers = (GenericController::default(), GenericController::latched());
+        let mut memory = Memory::new(&rom, controllers);
+
+        // Select is button 2, down button 5.
+        memory.controllers.0.press(ControllerFlags::SELECT | ControllerFlags::DOWN);
+        memory.controllers.1.press(ControllerFlags::SELECT | ControllerFlags::DOWN);
+
+        // Strobed after the presses so the latched controller snapshots them.
+        memory.pass_set(0x4016, 1).unwrap();
+        memory.pass_set(0x4016, 0).unwrap();
+
+        memory.set_controller_cycles((2, 2));
+
+        let read = |memory: &mut Memory<GenericController, GenericController>, address: u16| {
+            (0 .. 4).fold(0, |byte, bit| byte | memory.pass_get(address).unwrap() << bit)
+        };
+
+        // Unlatched, clocked by Memory::controller_cycles.
+        assert_eq!(read(&mut memory, 0x4016), 0b1001);
+        assert_eq!(memory.controller_cycles.0, 6);
+
+        // Latched, the controller keeps its own shift position.
+        assert_eq!(read(&mut memory, 0x4017), 0b1001);
+    }
 }
@@ -0,0 +1,232 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::mapper::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+// Shift register value after a reset, the 1 reaches bit 0 on the fifth write.
+const SHIFT_RESET: u8 = 0b10000;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Mmc1Registers {
+    shift: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    last_write_cycle: Option<u64>,
+}
+
+// Mapper 1 (SxROM), registers are loaded one bit at a time through $8000-$FFFF.
+pub struct Mmc1<'a> {
+    rom: &'a Rom,
+    registers: Mmc1Registers,
+}
+
+impl<'a> Mmc1<'a> {
+    pub fn new(rom: &'a Rom) -> Mmc1<'a> {
+        Mmc1 {
+            rom,
+            registers: Mmc1Registers {
+                shift: SHIFT_RESET,
+                // PRG mode 3, $C000 fixed to the last bank so the vectors are reachable.
+                control: 0b01100,
+                chr_bank_0: 0,
+                chr_bank_1: 0,
+                prg_bank: 0,
+                last_write_cycle: None,
+            },
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.registers.control = value,
+            0xA000..=0xBFFF => self.registers.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.registers.chr_bank_1 = value,
+            _ => self.registers.prg_bank = value,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let banks = (self.rom.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = (self.registers.prg_bank & 0b1111) as usize;
+
+        let upper = address >= 0xC000;
+
+        let selected = match (self.registers.control >> 2) & 0b11 {
+            // 32KB, the low bit of the bank number is ignored.
+            0 | 1 => (bank & !1) + upper as usize,
+            2 => if upper { bank } else { 0 },
+            _ => if upper { banks - 1 } else { bank },
+        };
+
+        (selected % banks) * PRG_BANK_SIZE + (address as usize % PRG_BANK_SIZE)
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize;
+
+        let bank = if self.registers.control & 0b10000 == 0 {
+            // 8KB, the low bit of the bank number is ignored.
+            (self.registers.chr_bank_0 & !1) as usize + address / CHR_BANK_SIZE
+        } else if address < CHR_BANK_SIZE {
+            self.registers.chr_bank_0 as usize
+        } else {
+            self.registers.chr_bank_1 as usize
+        };
+
+        bank * CHR_BANK_SIZE + address % CHR_BANK_SIZE
+    }
+}
+
+impl<'a> Mapper for Mmc1<'a> {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                let offset = self.prg_offset(address) % self.rom.prg_rom.len();
+
+                Some(self.rom.prg_rom[offset])
+            }
+            _ => None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8, cycle: u64) -> bool {
+        if address < 0x8000 {
+            return false
+        }
+
+        // Read-modify-write instructions write twice in a row, MMC1 only sees the first.
+        let consecutive = self.registers.last_write_cycle == Some(cycle.wrapping_sub(1));
+
+        self.registers.last_write_cycle = Some(cycle);
+
+        if consecutive {
+            return true
+        }
+
+        if value & 0b10000000 != 0 {
+            self.registers.shift = SHIFT_RESET;
+            self.registers.control |= 0b01100;
+
+            return true
+        }
+
+        let full = self.registers.shift & 1 != 0;
+
+        self.registers.shift = (self.registers.shift >> 1) | ((value & 1) << 4);
+
+        if full {
+            let value = self.registers.shift;
+
+            self.registers.shift = SHIFT_RESET;
+
+            self.write_register(address, value);
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if self.rom.chr_rom.is_empty() {
+            return None
+        }
+
+        let offset = self.chr_offset(address) % self.rom.chr_rom.len();
+
+        Some(self.rom.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _: u16, _: u8) -> bool {
+        false
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.registers.control & 0b11 {
+            0 => Mirroring::SingleLower,
+            1 => Mirroring::SingleUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        })
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        postcard::to_allocvec(&self.registers).unwrap_or_default()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        if let Ok(registers) = postcard::from_bytes(state) {
+            self.registers = registers
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four 16KB banks, each filled with its own index.
+    fn banked_rom() -> Rom {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.flags.mapper = 1;
+        rom.prg_rom = (0 .. 4 * PRG_BANK_SIZE).map(|i| (i / PRG_BANK_SIZE) as u8).collect();
+
+        rom
+    }
+
+    // Five writes, low bit first, a few cycles apart like STA would be.
+    fn write_serial(mapper: &mut Mmc1, address: u16, value: u8, cycle: &mut u64) {
+        for bit in 0 .. 5 {
+            *cycle += 4;
+
+            assert!(mapper.cpu_write(address, (value >> bit) & 1, *cycle));
+        }
+    }
+
+    #[test]
+    fn serial_write_switches_prg_bank() {
+        let rom = banked_rom();
+        let mut mapper = Mmc1::new(&rom);
+        let mut cycle = 0;
+
+        assert_eq!((mapper.cpu_read(0x8000), mapper.cpu_read(0xC000)), (Some(0), Some(3)));
+
+        write_serial(&mut mapper, 0xE000, 2, &mut cycle);
+
+        assert_eq!((mapper.cpu_read(0x8000), mapper.cpu_read(0xC000)), (Some(2), Some(3)));
+
+        // 32KB mode (control 0b00000) with bank 2 maps banks 2 and 3.
+        write_serial(&mut mapper, 0x8000, 0b00000, &mut cycle);
+        write_serial(&mut mapper, 0xE000, 3, &mut cycle);
+
+        assert_eq!((mapper.cpu_read(0x8000), mapper.cpu_read(0xC000)), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn reset_and_consecutive_writes() {
+        let rom = banked_rom();
+        let mut mapper = Mmc1::new(&rom);
+
+        // Two bits in, then a reset, then a full sequence selecting bank 1.
+        mapper.cpu_write(0xE000, 1, 10);
+        mapper.cpu_write(0xE000, 1, 20);
+        mapper.cpu_write(0xE000, 0x80, 30);
+
+        let mut cycle = 40;
+
+        write_serial(&mut mapper, 0xE000, 1, &mut cycle);
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(1));
+
+        // The second write of a read-modify-write lands on the next cycle and is dropped,
+        // so six writes only shift in five bits.
+        for (bit, cycle) in [(0, 100), (1, 101), (1, 110), (0, 120), (0, 130), (0, 140)] {
+            mapper.cpu_write(0xE000, bit, cycle);
+        }
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(2));
+    }
+}
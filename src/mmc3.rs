@@ -0,0 +1,198 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::mapper::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Mmc3Registers {
+    bank_select: u8,
+    banks: [u8; 8],
+    mirroring: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+// Mapper 4 (TxROM), 8KB PRG and 1KB/2KB CHR banks with a scanline counter IRQ.
+pub struct Mmc3<'a> {
+    rom: &'a Rom,
+    registers: Mmc3Registers,
+}
+
+impl<'a> Mmc3<'a> {
+    pub fn new(rom: &'a Rom) -> Mmc3<'a> {
+        Mmc3 {
+            rom,
+            registers: Mmc3Registers {
+                bank_select: 0,
+                banks: [0, 2, 4, 5, 6, 7, 0, 1],
+                mirroring: 0,
+                irq_latch: 0,
+                irq_counter: 0,
+                irq_reload: false,
+                irq_enabled: false,
+                irq_pending: false,
+            },
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let banks = (self.rom.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let second_last = banks.saturating_sub(2);
+
+        let swapped = self.registers.bank_select & 0b01000000 != 0;
+
+        let bank = match (address - 0x8000) as usize / PRG_BANK_SIZE {
+            0 => if swapped { second_last } else { self.registers.banks[6] as usize },
+            1 => self.registers.banks[7] as usize,
+            2 => if swapped { self.registers.banks[6] as usize } else { second_last },
+            _ => banks - 1,
+        };
+
+        (bank % banks) * PRG_BANK_SIZE + address as usize % PRG_BANK_SIZE
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        // With A12 inversion the two 2KB banks sit at $1000 instead of $0000.
+        let inverted = self.registers.bank_select & 0b10000000 != 0;
+
+        let address = if inverted { address ^ 0x1000 } else { address } as usize;
+
+        let bank = match address / CHR_BANK_SIZE {
+            0 | 1 => (self.registers.banks[0] & !1) as usize + address / CHR_BANK_SIZE,
+            2 | 3 => (self.registers.banks[1] & !1) as usize + address / CHR_BANK_SIZE - 2,
+            slot => self.registers.banks[slot - 2] as usize,
+        };
+
+        bank * CHR_BANK_SIZE + address % CHR_BANK_SIZE
+    }
+}
+
+impl<'a> Mapper for Mmc3<'a> {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                let offset = self.prg_offset(address) % self.rom.prg_rom.len();
+
+                Some(self.rom.prg_rom[offset])
+            }
+            _ => None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8, _: u64) -> bool {
+        let even = address & 1 == 0;
+
+        match address {
+            0x8000..=0x9FFF if even => self.registers.bank_select = value,
+            0x8000..=0x9FFF => {
+                let index = (self.registers.bank_select & 0b111) as usize;
+
+                self.registers.banks[index] = value
+            }
+            0xA000..=0xBFFF if even => self.registers.mirroring = value & 1,
+            0xA000..=0xBFFF => { /* PRG RAM protect, ignored */ }
+            0xC000..=0xDFFF if even => self.registers.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.registers.irq_counter = 0;
+                self.registers.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.registers.irq_enabled = false;
+                self.registers.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.registers.irq_enabled = true,
+            _ => return false
+        }
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        if self.rom.chr_rom.is_empty() {
+            return None
+        }
+
+        let offset = self.chr_offset(address) % self.rom.chr_rom.len();
+
+        Some(self.rom.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _: u16, _: u8) -> bool {
+        false
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        if self.rom.flags.four_screen {
+            return None
+        }
+
+        Some(if self.registers.mirroring == 0 { Mirroring::Vertical } else { Mirroring::Horizontal })
+    }
+
+    fn on_scanline(&mut self) {
+        if self.registers.irq_counter == 0 || self.registers.irq_reload {
+            self.registers.irq_counter = self.registers.irq_latch;
+            self.registers.irq_reload = false;
+        } else {
+            self.registers.irq_counter -= 1;
+        }
+
+        if self.registers.irq_counter == 0 && self.registers.irq_enabled {
+            self.registers.irq_pending = true;
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.registers.irq_pending
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        postcard::to_allocvec(&self.registers).unwrap_or_default()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        if let Ok(registers) = postcard::from_bytes(state) {
+            self.registers = registers
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latch_8_fires_once_in_16_scanlines() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.flags.mapper = 4;
+
+        let mut mapper = Mmc3::new(&rom);
+
+        mapper.cpu_write(0xC000, 8, 0);
+        mapper.cpu_write(0xC001, 0, 0);
+        mapper.cpu_write(0xE001, 0, 0);
+
+        let mut fired = vec![];
+
+        for scanline in 1 ..= 16 {
+            mapper.on_scanline();
+
+            if mapper.irq_pending() {
+                fired.push(scanline);
+
+                // Acknowledge like an IRQ handler would, keeping the IRQ enabled.
+                mapper.cpu_write(0xE000, 0, 0);
+                mapper.cpu_write(0xE001, 0, 0);
+            }
+        }
+
+        // Reloaded on the first scanline, then counted down eight times.
+        assert_eq!(fired, vec![9]);
+    }
+}
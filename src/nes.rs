@@ -0,0 +1,224 @@
+use crate::controller::{Controller, ControllerFlags, GenericController};
+use crate::cpu::Cpu;
+use crate::interpreter::CpuError;
+use crate::renderer::{RenderAction, RenderedFrame, Renderer, ScanPosition};
+use crate::rom::Rom;
+use crate::software::{NES_FRAME_CYCLES, SoftwareRenderer};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Port {
+    One,
+    Two
+}
+
+// A CPU and a renderer kept in step, one call per frame.
+pub struct Nes<'a, C1: Controller, C2: Controller> {
+    pub cpu: Cpu<'a, C1, C2>,
+    pub renderer: SoftwareRenderer,
+    frame: Box<RenderedFrame>
+}
+
+impl<'a, C1: Controller, C2: Controller> Nes<'a, C1, C2> {
+    pub fn new(rom: &'a Rom, controllers: (C1, C2)) -> Nes<'a, C1, C2> {
+        Nes {
+            cpu: Cpu::new(rom, None, controllers),
+            renderer: SoftwareRenderer::new(),
+            frame: Box::default()
+        }
+    }
+
+    // Runs until the renderer completes a frame, delivering the NMI if the game asked for one.
+    // The renderer only hands over frames while NMIs are enabled, so with them off
+    // this stops after a frame's worth of cycles and returns the previous frame.
+    pub fn step_frame(&mut self) -> Result<&RenderedFrame, CpuError> {
+        let deadline = self.cpu.memory.cycles + NES_FRAME_CYCLES;
+
+        while self.cpu.memory.cycles < deadline {
+            self.cpu.step()?;
+
+            match self.renderer.render(&mut self.cpu.memory.ppu, self.cpu.memory.cycles) {
+                RenderAction::None => { },
+                RenderAction::SendFrame(frame) => {
+                    self.frame = frame;
+
+                    self.cpu.interrupt(self.cpu.vectors.nmi)?;
+
+                    return Ok(&self.frame)
+                }
+                RenderAction::SuppressedFrame(frame) => {
+                    self.frame = frame;
+
+                    return Ok(&self.frame)
+                }
+            }
+        }
+
+        Ok(&self.frame)
+    }
+
+    // Most recently completed frame, blank before the first step_frame.
+    pub fn frame(&self) -> &RenderedFrame {
+        &self.frame
+    }
+
+    // Like pressing the reset button, see Cpu::reset. Drawing restarts at the top of a frame.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+
+        self.renderer.resume(ScanPosition { cycle: self.cpu.memory.cycles, ..ScanPosition::default() });
+    }
+}
+
+impl<'a> Nes<'a, GenericController, GenericController> {
+    pub fn set_controller(&mut self, port: Port, flags: ControllerFlags) {
+        let controller = match port {
+            Port::One => &mut self.cpu.memory.controllers.0,
+            Port::Two => &mut self.cpu.memory.controllers.1,
+        };
+
+        controller.press(flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use super::*;
+
+    // Reads $2002 to clear the power on v-blank flag, enables NMI and spins at $8008.
+    // The NMI handler at $800B counts NMIs in $10.
+    const COUNTER_PROGRAM: [u8; 14] = [
+        0x2C, 0x02, 0x20,       // BIT $2002
+        0xA9, 0x80,             // LDA #$80
+        0x8D, 0x00, 0x20,       // STA $2000
+        0x4C, 0x08, 0x80,       // JMP $8008
+        0xE6, 0x10,             // nmi: INC $10
+        0x40,                   // RTI
+    ];
+
+    fn counter_rom() -> Rom {
+        let mut rom = Rom::from_prg(&COUNTER_PROGRAM, 0x8000);
+
+        rom.prg_rom[0x7FFA .. 0x7FFC].copy_from_slice(&0x800Bu16.to_le_bytes());
+
+        rom
+    }
+
+    #[test]
+    fn suppressed_nmi_is_not_serviced() {
+        let rom = counter_rom();
+        let mut nes = Nes::new(&rom, (NoController, NoController));
+
+        nes.cpu.memory.ppu.warmup = false;
+        nes.cpu.memory.ppu.suppress_nmi = true;
+
+        // Two frames of single steps, across two v-blanks.
+        let end = nes.cpu.memory.cycles + NES_FRAME_CYCLES * 2;
+
+        while nes.cpu.memory.cycles < end {
+            nes.cpu.step().unwrap();
+
+            let action = nes.renderer.render(&mut nes.cpu.memory.ppu, nes.cpu.memory.cycles);
+
+            assert!(!matches!(action, RenderAction::SendFrame(_)));
+            assert!(nes.cpu.registers.pc < 0x800B, "vectored to the NMI handler");
+        }
+
+        assert_eq!(nes.cpu.memory.ram[0x10], 0);
+
+        nes.cpu.memory.ppu.suppress_nmi = false;
+
+        nes.step_frame().unwrap();
+        nes.step_frame().unwrap();
+
+        assert_eq!(nes.cpu.memory.ram[0x10], 1);
+    }
+
+    #[test]
+    fn enabling_nmi_in_v_blank_fires() {
+        let program = [
+            0xA9, 0x80,             // LDA #$80
+            0x8D, 0x00, 0x20,       // STA $2000
+            0x4C, 0x05, 0x80,       // JMP $8005
+            0xE6, 0x10,             // nmi: INC $10
+            0x40,                   // RTI
+        ];
+
+        let mut rom = Rom::from_prg(&program, 0x8000);
+
+        rom.prg_rom[0x7FFA .. 0x7FFC].copy_from_slice(&0x8008u16.to_le_bytes());
+
+        for nmi_on_enable in [true, false] {
+            let mut nes = Nes::new(&rom, (NoController, NoController));
+
+            nes.cpu.memory.ppu.warmup = false;
+            nes.cpu.memory.ppu.nmi_on_enable = nmi_on_enable;
+            nes.cpu.memory.ppu.registers.status.v_blank_hit = true;
+
+            nes.cpu.step().unwrap();
+            nes.cpu.step().unwrap();
+
+            // Serviced right after the write, without waiting for the next v-blank edge.
+            let expected_pc = if nmi_on_enable { 0x8008 } else { 0x8005 };
+
+            assert_eq!(nes.cpu.registers.pc, expected_pc);
+        }
+    }
+
+    #[test]
+    fn reset_restarts_the_frame() {
+        let rom = counter_rom();
+        let mut nes = Nes::new(&rom, (NoController, NoController));
+
+        nes.cpu.memory.ppu.warmup = false;
+
+        nes.step_frame().unwrap();
+
+        for _ in 0 .. 1000 {
+            nes.cpu.step().unwrap();
+            nes.renderer.render(&mut nes.cpu.memory.ppu, nes.cpu.memory.cycles);
+        }
+
+        assert_ne!(nes.renderer.scan_position().y, 0);
+
+        nes.reset();
+
+        let scan = nes.renderer.scan_position();
+
+        assert_eq!((scan.x, scan.y), (0, 0));
+
+        // A whole frame from the top, so v-blank lands a full frame after the reset.
+        let cycles = nes.cpu.memory.cycles;
+        let frames = nes.cpu.memory.ram[0x10];
+
+        nes.step_frame().unwrap();
+
+        assert_eq!(nes.cpu.memory.ram[0x10], frames);
+        assert!(nes.cpu.memory.cycles - cycles >= NES_FRAME_CYCLES * 241 / 262);
+    }
+
+    #[test]
+    fn ten_frames_smoke_test() {
+        let rom = counter_rom();
+        let mut nes = Nes::new(&rom, (GenericController::latched(), GenericController::latched()));
+
+        nes.cpu.memory.ppu.warmup = false;
+        nes.set_controller(Port::One, ControllerFlags::A | ControllerFlags::START);
+
+        let start = nes.cpu.memory.cycles;
+
+        for _ in 0 .. 10 {
+            nes.step_frame().unwrap();
+        }
+
+        // Each NMI is delivered as a frame completes, its handler runs in the next step_frame.
+        assert_eq!(nes.cpu.memory.ram[0x10], 9);
+        assert_eq!(nes.cpu.registers.pc, 0x800B);
+
+        let elapsed = nes.cpu.memory.cycles - start;
+
+        assert!(elapsed > NES_FRAME_CYCLES * 9 && elapsed <= NES_FRAME_CYCLES * 10, "{elapsed} cycles");
+        assert_eq!(nes.cpu.memory.controllers.0.flags().bits(), (ControllerFlags::A | ControllerFlags::START).bits());
+        assert!(nes.cpu.memory.controllers.1.flags().is_empty());
+    }
+}
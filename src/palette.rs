@@ -0,0 +1,102 @@
+pub type Color = [u8; 4];
+
+pub const NES_PALETTE: [Color; 0x40] = [
+    [98, 98, 98, 255],
+    [0, 31, 177, 255],
+    [35, 3, 199, 255],
+    [81, 0, 177, 255],
+    [115, 0, 117, 255],
+    [127, 0, 35, 255],
+    [115, 10, 0, 255],
+    [81, 39, 0, 255],
+    [35, 67, 0, 255],
+    [0, 86, 0, 255],
+    [0, 92, 0, 255],
+    [0, 82, 35, 255],
+    [0, 60, 117, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+    [170, 170, 170, 255],
+    [13, 86, 255, 255],
+    [74, 47, 255, 255],
+    [138, 18, 255, 255],
+    [188, 8, 213, 255],
+    [210, 17, 104, 255],
+    [199, 45, 0, 255],
+    [157, 84, 0, 255],
+    [96, 123, 0, 255],
+    [32, 151, 0, 255],
+    [0, 162, 0, 255],
+    [0, 152, 66, 255],
+    [0, 124, 180, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+    [255, 255, 255, 255],
+    [82, 174, 255, 255],
+    [143, 133, 255, 255],
+    [210, 101, 255, 255],
+    [255, 86, 255, 255],
+    [255, 93, 206, 255],
+    [255, 119, 86, 255],
+    [249, 158, 0, 255],
+    [188, 199, 0, 255],
+    [121, 231, 0, 255],
+    [66, 246, 17, 255],
+    [38, 239, 125, 255],
+    [44, 213, 245, 255],
+    [77, 77, 77, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+    [255, 255, 255, 255],
+    [182, 225, 255, 255],
+    [205, 208, 255, 255],
+    [232, 195, 255, 255],
+    [255, 187, 255, 255],
+    [255, 188, 243, 255],
+    [255, 198, 195, 255],
+    [255, 213, 153, 255],
+    [232, 230, 129, 255],
+    [205, 243, 129, 255],
+    [182, 250, 153, 255],
+    [168, 249, 195, 255],
+    [168, 240, 243, 255],
+    [183, 183, 183, 255],
+    [0, 0, 0, 255],
+    [0, 0, 0, 255],
+];
+
+// Every color the PPU can output, with its palette index.
+pub fn entries() -> impl Iterator<Item = (u8, Color)> {
+    NES_PALETTE.iter().enumerate().map(|(index, color)| (index as u8, *color))
+}
+
+// Reads a .pal file, 64 RGB triplets (any extra emphasis entries are ignored).
+pub fn parse_palette(data: &[u8]) -> Option<[Color; 0x40]> {
+    if data.len() < 0x40 * 3 {
+        return None
+    }
+
+    let mut palette = [[0, 0, 0, 255]; 0x40];
+
+    for (color, rgb) in palette.iter_mut().zip(data.chunks_exact(3)) {
+        *color = [rgb[0], rgb[1], rgb[2], 255];
+    }
+
+    Some(palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_cover_the_palette() {
+        let entries: Vec<(u8, Color)> = entries().collect();
+
+        assert_eq!(entries.len(), 64);
+        assert_eq!(entries[0], (0x00, [98, 98, 98, 255]));
+        assert_eq!(entries[0x3F], (0x3F, [0, 0, 0, 255]));
+    }
+}
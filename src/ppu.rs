@@ -1,9 +1,14 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
-use crate::rom::Rom;
+use crate::chr_cache::{ChrCache, TilePixels};
+use crate::mapper::{create_mapper, Mapper};
+use crate::rom::{Mirroring, Rom};
 
 const SPRITE_COUNT: usize = 64;
 
+// CPU cycles after power on before $2000, $2001, $2005 and $2006 accept writes.
+pub const PPU_WARMUP_CYCLES: u64 = 29658;
+
 #[derive(Clone, Debug)]
 pub enum PpuMemoryError {
     UnmappedRead(u16),
@@ -22,6 +27,8 @@ pub struct PaletteMemory {
     pub background_solid: u8,
     pub background: [Palette; 4],
     pub sprite: [Palette; 4],
+    // $3F04, $3F08 and $3F0C, never drawn but still readable.
+    pub unused: [u8; 3],
 }
 
 #[derive(Default)]
@@ -47,10 +54,18 @@ pub struct MaskRegister {
 }
 
 pub struct StatusRegister {
+    pub sprite_overflow: bool,
     pub sprite_hit: bool,
     pub v_blank_hit: bool,
 }
 
+// Horizontal scroll the PPU copies from t at the end of each scanline, see RenderRegister::line_scroll.
+#[derive(Clone, Copy, Default)]
+pub struct LineScroll {
+    pub x_scroll: u8,
+    pub name_table_x: bool,
+}
+
 #[derive(Default)]
 pub struct RenderRegister {
     pub t: u16,
@@ -76,6 +91,9 @@ pub struct PpuRegisters {
 
     pub oam_address: u8,
 
+    // CPU cycle of the last $2002 read, for the v-blank race.
+    pub status_read_cycle: Option<u64>,
+
     // pub write_low_address: bool,
     // pub address: u16,
     pub read_buffer: u8,
@@ -86,12 +104,27 @@ pub struct PpuMemory<'a> {
 
     pub oam: [Sprite; SPRITE_COUNT],
     pub names: [NameTable; 4],
-    pub palette: PaletteMemory
+    pub palette: PaletteMemory,
+    // None keeps all four name tables independent.
+    pub mirroring: Option<Mirroring>,
+    pub chr_cache: ChrCache,
+    // Also serves the CPU side of the cartridge, see Memory::pass_get.
+    pub mapper: Box<dyn Mapper + 'a>,
+    // Writable pattern tables for ROMs without CHR, used instead of the mapper.
+    pub chr_ram: Option<Box<[u8; 0x2000]>>,
 }
 
 pub struct Ppu<'a> {
     pub registers: PpuRegisters,
-    pub memory: PpuMemory<'a>
+    pub memory: PpuMemory<'a>,
+    // Lets a debugger keep v-blank from requesting an NMI.
+    pub suppress_nmi: bool,
+    // Enabling NMI in $2000 while v-blank is set requests an NMI right away.
+    pub nmi_on_enable: bool,
+    // Serviced by Cpu::step after the current instruction.
+    pub pending_nmi: bool,
+    // Set at power on, see PPU_WARMUP_CYCLES.
+    pub warmup: bool,
 }
 
 impl Default for Sprite {
@@ -127,9 +160,11 @@ impl Sprite {
     }
 }
 
+// V-blank usually reads as set right after power on.
 impl Default for StatusRegister {
     fn default() -> StatusRegister {
         StatusRegister {
+            sprite_overflow: false,
             sprite_hit: false,
             v_blank_hit: true,
         }
@@ -147,6 +182,19 @@ impl ControlRegister {
             gen_nmi: value & 0b10000000 != 0,
         }
     }
+
+    // Name table select bits (0-1) live in RenderRegister::t.
+    pub fn bits(&self) -> u8 {
+        let increment_32 = if self.increment_32 { 0b00000100 } else { 0 };
+        let base_sprite_pattern_table = if self.base_sprite_pattern_table { 0b00001000 } else { 0 };
+        let base_background_pattern_table = if self.base_background_pattern_table { 0b00010000 } else { 0 };
+        let sprite_size = if self.sprite_size { 0b00100000 } else { 0 };
+        let ppu_color_ext = if self.ppu_color_ext { 0b01000000 } else { 0 };
+        let gen_nmi = if self.gen_nmi { 0b10000000 } else { 0 };
+
+        increment_32 | base_sprite_pattern_table | base_background_pattern_table
+            | sprite_size | ppu_color_ext | gen_nmi
+    }
 }
 
 impl MaskRegister {
@@ -162,14 +210,29 @@ impl MaskRegister {
             emphasize_blue: value & 0b10000000 != 0,
         }
     }
+
+    pub fn bits(&self) -> u8 {
+        let greyscale = if self.greyscale { 0b00000001 } else { 0 };
+        let show_background_leftmost = if self.show_background_leftmost { 0b00000010 } else { 0 };
+        let show_sprites_leftmost = if self.show_sprites_leftmost { 0b00000100 } else { 0 };
+        let show_background = if self.show_background { 0b00001000 } else { 0 };
+        let show_sprites = if self.show_sprites { 0b00010000 } else { 0 };
+        let emphasize_red = if self.emphasize_red { 0b00100000 } else { 0 };
+        let emphasize_green = if self.emphasize_green { 0b01000000 } else { 0 };
+        let emphasize_blue = if self.emphasize_blue { 0b10000000 } else { 0 };
+
+        greyscale | show_background_leftmost | show_sprites_leftmost | show_background
+            | show_sprites | emphasize_red | emphasize_green | emphasize_blue
+    }
 }
 
 impl StatusRegister {
     pub fn bits(&self) -> u8 {
+        let sprite_overflow = if self.sprite_overflow { 0b00100000 } else { 0 };
         let sprite_hit = if self.sprite_hit { 0b01000000 } else { 0 };
         let v_blank_hit = if self.v_blank_hit { 0b10000000 } else { 0 };
 
-        sprite_hit | v_blank_hit
+        sprite_overflow | sprite_hit | v_blank_hit
     }
 }
 
@@ -190,6 +253,13 @@ impl RenderRegister {
         self.t & 0b0000100000000000 != 0
     }
 
+    pub fn line_scroll(&self) -> LineScroll {
+        LineScroll {
+            x_scroll: self.x_scroll(),
+            name_table_x: self.name_table_x(),
+        }
+    }
+
     pub fn write_control(&mut self, value: u8) {
         self.t = (self.t & 0b1111001111111111) | (((value & 0b11) as u16) << 10)
     }
@@ -242,22 +312,33 @@ impl Display for PpuMemoryError {
 
 impl Error for PpuMemoryError { }
 
+// $3F10, $3F14, $3F18 and $3F1C mirror $3F00, $3F04, $3F08 and $3F0C.
+fn mirror_palette_address(address: u16) -> u16 {
+    let address = address % 0x20;
+
+    if address >= 0x10 && address & 0b11 == 0 {
+        address - 0x10
+    } else {
+        address
+    }
+}
+
 impl PaletteMemory {
     pub fn get(&self, address: u16) -> Result<u8, PpuMemoryError> {
-        let address = address % 0x20;
+        let address = mirror_palette_address(address);
 
         Ok(match address {
             0x00 => self.background_solid,
+            0x04 | 0x08 | 0x0C => self.unused[(address / 4 - 1) as usize],
             0x01..=0x0F => {
-                let base = (address - 0x3F01) as usize;
+                let base = (address - 0x01) as usize;
                 let page = base / 4;
                 let index = base % 4;
 
                 self.background[page][index]
             }
-            0x10 => self.background_solid,
             0x11..=0x1F => {
-                let base = (address - 0x3F11) as usize;
+                let base = (address - 0x11) as usize;
                 let page = base / 4;
                 let index = base % 4;
 
@@ -268,10 +349,11 @@ impl PaletteMemory {
     }
 
     pub fn set(&mut self, address: u16, value: u8) -> Result<(), PpuMemoryError> {
-        let address = address % 0x20;
+        let address = mirror_palette_address(address);
 
         match address {
             0x00 => self.background_solid = value,
+            0x04 | 0x08 | 0x0C => self.unused[(address / 4 - 1) as usize] = value,
             0x01..=0x0F => {
                 let base = (address - 0x01) as usize;
                 let page = base / 4;
@@ -281,7 +363,6 @@ impl PaletteMemory {
                     self.background[page][index] = value
                 }
             }
-            0x10 => self.background_solid = value,
             0x11..=0x1F => {
                 let base = (address - 0x11) as usize;
                 let page = base / 4;
@@ -298,23 +379,42 @@ impl PaletteMemory {
     }
 }
 
+// Power on palette contents observed on hardware (unspecified, but commonly these).
 impl Default for PaletteMemory {
     fn default() -> PaletteMemory {
         PaletteMemory {
-            background_solid: 0,
-            background: std::array::from_fn(|_| Palette::default()),
-            sprite: std::array::from_fn(|_| Palette::default()),
+            background_solid: 0x09,
+            background: [[0x01, 0x00, 0x01], [0x02, 0x02, 0x0D], [0x10, 0x08, 0x24], [0x00, 0x04, 0x2C]],
+            sprite: [[0x01, 0x34, 0x03], [0x04, 0x00, 0x14], [0x3A, 0x00, 0x02], [0x20, 0x2C, 0x08]],
+            unused: [0x00, 0x08, 0x00],
         }
     }
 }
 
 impl<'a> PpuMemory<'a> {
+    // Maps a logical name table (0-3) to the table that backs it.
+    pub fn name_table_page(&self, table: usize) -> usize {
+        let table = table % 4;
+
+        match self.mirroring {
+            None => table,
+            Some(Mirroring::Horizontal) => table / 2,
+            Some(Mirroring::Vertical) => table % 2,
+            Some(Mirroring::SingleLower) => 0,
+            Some(Mirroring::SingleUpper) => 1,
+        }
+    }
+
     pub fn read(&mut self, address: u16) -> Result<u8, PpuMemoryError> {
         Ok(match address {
-            0x0000..=0x1FFF => self.rom.chr_rom[address as usize],
+            0x0000..=0x1FFF => match &self.chr_ram {
+                Some(chr_ram) => chr_ram[address as usize],
+                None => self.mapper.ppu_read(address)
+                    .ok_or(PpuMemoryError::UnmappedRead(address))?,
+            },
             0x2000..=0x3EFF => {
                 let base = (address - 0x2000) as usize;
-                let page = (base / 0x400) % 4;
+                let page = self.name_table_page(base / 0x400);
                 let index = base % 0x400;
 
                 self.names[page].contents[index]
@@ -326,9 +426,19 @@ impl<'a> PpuMemory<'a> {
 
     pub fn write(&mut self, address: u16, value: u8) -> Result<(), PpuMemoryError> {
         match address {
+            0x0000..=0x1FFF if self.chr_ram.is_some() => {
+                if let Some(chr_ram) = &mut self.chr_ram {
+                    chr_ram[address as usize] = value;
+                }
+
+                self.chr_cache.invalidate(address as usize)
+            }
+            0x0000..=0x1FFF if self.mapper.ppu_write(address, value) => {
+                self.chr_cache.invalidate(address as usize)
+            }
             0x2000..=0x3EFF => {
                 let base = (address - 0x2000) as usize;
-                let page = (base / 0x400) % 4;
+                let page = self.name_table_page(base / 0x400);
                 let index = base % 0x400;
 
                 self.names[page].contents[index] = value
@@ -340,21 +450,113 @@ impl<'a> PpuMemory<'a> {
         Ok(())
     }
 
-    pub fn new(rom: &Rom) -> PpuMemory {
+    // Decoded pattern table tile (0-511), read through the mapper.
+    pub fn tile(&mut self, tile: usize) -> &TilePixels {
+        let mapper = &mut self.mapper;
+        let chr_ram = &self.chr_ram;
+
+        let read = |address: usize| match chr_ram {
+            Some(chr_ram) => chr_ram[address],
+            None => mapper.ppu_read(address as u16).unwrap_or(0),
+        };
+
+        self.chr_cache.tile(read, tile)
+    }
+
+    // CPU write to cartridge space, false if the mapper has nothing there.
+    // Pattern tiles are decoded again afterwards, the write may have switched CHR banks.
+    pub fn write_mapper(&mut self, address: u16, value: u8, cycle: u64) -> bool {
+        let handled = self.mapper.cpu_write(address, value, cycle);
+
+        if handled {
+            self.chr_cache.clear();
+
+            if let Some(mirroring) = self.mapper.mirroring() {
+                self.mirroring = Some(mirroring)
+            }
+        }
+
+        handled
+    }
+
+    pub fn new(rom: &Rom) -> PpuMemory<'_> {
+        let mapper = create_mapper(rom);
+
+        // Four screen carts bring their own VRAM for the other two tables.
+        let mirroring = mapper.mirroring()
+            .or((!rom.flags.four_screen).then_some(rom.flags.mirroring));
+
         PpuMemory {
             rom,
 
             oam: std::array::from_fn(|_| Sprite::default()),
             names: std::array::from_fn(|_| NameTable { contents: [0; 0x400] }),
-            palette: PaletteMemory::default()
+            palette: PaletteMemory::default(),
+            mirroring,
+            chr_cache: ChrCache::default(),
+            mapper,
+            chr_ram: rom.chr_rom.is_empty().then(|| Box::new([0; 0x2000])),
         }
     }
 }
 
 impl<'a> Ppu<'a> {
+    // Resolves a screen pixel to (name table page, x, y) within that page, using the scroll in t.
+    pub fn scrolled_position(&self, x: usize, y: usize) -> (usize, usize, usize) {
+        self.scrolled_position_at(self.registers.render.line_scroll(), x, y)
+    }
+
+    // Same as scrolled_position, with the horizontal scroll latched for this scanline.
+    pub fn scrolled_position_at(&self, line: LineScroll, x: usize, y: usize) -> (usize, usize, usize) {
+        let render = &self.registers.render;
+
+        let mut offset_x = x + (line.x_scroll as usize);
+        let mut offset_y = y + (render.y_scroll() as usize);
+
+        // Logical name table, bit 0 selects the right column and bit 1 the bottom row.
+        let mut table = (line.name_table_x as usize) | ((render.name_table_y() as usize) << 1);
+
+        if offset_x >= 256 {
+            offset_x -= 256;
+
+            table ^= 0b01;
+        }
+
+        // Scrolling into the attribute rows (y 240-255) wraps back to the same table.
+        if render.y_scroll() < 240 {
+            if offset_y >= 240 {
+                offset_y -= 240;
+
+                table ^= 0b10;
+            }
+        } else if offset_y >= 256 {
+            offset_y -= 256;
+        }
+
+        (self.memory.name_table_page(table), offset_x, offset_y)
+    }
+
+    // Tile index displayed at a screen pixel, after scrolling and mirroring.
+    pub fn nametable_byte_at_screen(&self, x: usize, y: usize) -> u8 {
+        let (name_table, offset_x, offset_y) = self.scrolled_position(x, y);
+
+        self.memory.names[name_table].contents[offset_x / 8 + (offset_y / 8) * 32]
+    }
+
+    pub fn warming_up(&self, cycle: u64) -> bool {
+        self.warmup && cycle < PPU_WARMUP_CYCLES
+    }
+
     pub fn write_ctrl(&mut self, value: u8) {
+        let was_enabled = self.registers.control.gen_nmi;
+
         self.registers.control = ControlRegister::from_bits(value);
 
+        if self.nmi_on_enable && !self.suppress_nmi && !was_enabled
+            && self.registers.control.gen_nmi && self.registers.status.v_blank_hit {
+            self.pending_nmi = true;
+        }
+
         self.registers.render.write_control(value)
     }
 
@@ -362,10 +564,15 @@ impl<'a> Ppu<'a> {
         self.registers.mask = MaskRegister::from_bits(value);
     }
 
-    pub fn read_status(&mut self) -> u8 {
+    pub fn read_status(&mut self, cycle: u64) -> u8 {
         self.registers.render.read_status();
 
-        self.registers.status.bits()
+        let result = self.registers.status.bits();
+
+        self.registers.status.v_blank_hit = false;
+        self.registers.status_read_cycle = Some(cycle);
+
+        result
     }
 
     pub fn write_oam_address(&mut self, value: u8) {
@@ -407,9 +614,20 @@ impl<'a> Ppu<'a> {
     }
 
     pub fn read_data(&mut self) -> Result<u8, PpuMemoryError> {
-        let result = self.registers.read_buffer;
+        let address = self.registers.render.v & 0x3FFF;
+
+        let result = if address >= 0x3F00 {
+            // Palette reads skip the buffer, which gets the name table byte underneath instead.
+            self.registers.read_buffer = self.memory.read(address - 0x1000)?;
+
+            self.memory.read(address)?
+        } else {
+            let result = self.registers.read_buffer;
+
+            self.registers.read_buffer = self.memory.read(address)?;
 
-        self.registers.read_buffer = self.memory.read(self.registers.render.v)?;
+            result
+        };
 
         if self.registers.control.increment_32 {
             self.registers.render.v += 32;
@@ -420,6 +638,11 @@ impl<'a> Ppu<'a> {
         Ok(result)
     }
 
+    // Called by mappers that switch mirroring at runtime.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.memory.mirroring = Some(mirroring)
+    }
+
     pub fn replace_oam(&mut self, data: [u8; 256]) {
         self.memory.oam = std::array::from_fn(|i| {
             Sprite {
@@ -431,10 +654,275 @@ impl<'a> Ppu<'a> {
         });
     }
 
-    pub fn new(rom: &Rom) -> Ppu {
+    pub fn oam_bytes(&self) -> [u8; 256] {
+        std::array::from_fn(|i| self.memory.oam[i / 4].read((i % 4) as u8))
+    }
+
+    pub fn new(rom: &Rom) -> Ppu<'_> {
         Ppu {
             registers: PpuRegisters::default(),
-            memory: PpuMemory::new(rom)
+            memory: PpuMemory::new(rom),
+            suppress_nmi: false,
+            nmi_on_enable: true,
+            pending_nmi: false,
+            warmup: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four logical tables, so the page returned is the logical table itself.
+    fn scrolled_ppu(rom: &Rom, x: u8, y: u8, table: u8) -> Ppu<'_> {
+        let mut ppu = Ppu::new(rom);
+
+        ppu.memory.mirroring = None;
+
+        ppu.registers.render.write_control(table);
+        ppu.registers.render.write_scroll(x);
+        ppu.registers.render.write_scroll(y);
+
+        ppu
+    }
+
+    #[test]
+    fn scroll_wraps_x_at_256() {
+        let rom = Rom::from_prg(&[], 0x8000);
+
+        let ppu = scrolled_ppu(&rom, 200, 0, 0);
+
+        assert_eq!(ppu.scrolled_position(55, 0), (0, 255, 0));
+        assert_eq!(ppu.scrolled_position(56, 0), (1, 0, 0));
+        assert_eq!(ppu.scrolled_position(100, 0), (1, 44, 0));
+
+        let ppu = scrolled_ppu(&rom, 200, 0, 1);
+
+        assert_eq!(ppu.scrolled_position(100, 0), (0, 44, 0));
+
+        // The latched line scroll wins over t.
+        let line = LineScroll { x_scroll: 16, name_table_x: true };
+
+        assert_eq!(ppu.scrolled_position_at(line, 100, 0), (1, 116, 0));
+        assert_eq!(ppu.scrolled_position_at(line, 240, 0), (0, 0, 0));
+    }
+
+    #[test]
+    fn scroll_wraps_y_at_240() {
+        let rom = Rom::from_prg(&[], 0x8000);
+
+        let ppu = scrolled_ppu(&rom, 0, 200, 0);
+
+        assert_eq!(ppu.scrolled_position(0, 39), (0, 0, 239));
+        assert_eq!(ppu.scrolled_position(0, 40), (2, 0, 0));
+        assert_eq!(ppu.scrolled_position(0, 50), (2, 0, 10));
+
+        let ppu = scrolled_ppu(&rom, 0, 200, 2);
+
+        assert_eq!(ppu.scrolled_position(0, 50), (0, 0, 10));
+
+        // Scrolled into the attribute rows, the wrap is at 256 and stays in the same table.
+        let ppu = scrolled_ppu(&rom, 0, 248, 0);
+
+        assert_eq!(ppu.scrolled_position(0, 0), (0, 0, 248));
+        assert_eq!(ppu.scrolled_position(0, 10), (0, 0, 2));
+    }
+
+    #[test]
+    fn scroll_wraps_both_axes() {
+        let rom = Rom::from_prg(&[], 0x8000);
+
+        let mut ppu = scrolled_ppu(&rom, 128, 128, 0);
+
+        assert_eq!(ppu.scrolled_position(200, 200), (3, 72, 88));
+
+        ppu.memory.mirroring = Some(Mirroring::Vertical);
+
+        assert_eq!(ppu.scrolled_position(200, 200), (1, 72, 88));
+
+        ppu.memory.mirroring = Some(Mirroring::Horizontal);
+
+        assert_eq!(ppu.scrolled_position(200, 200), (1, 72, 88));
+        assert_eq!(ppu.scrolled_position(100, 200), (1, 228, 88));
+        assert_eq!(ppu.scrolled_position(100, 100), (0, 228, 228));
+    }
+
+    #[test]
+    fn nametable_byte_under_scroll() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut ppu = Ppu::new(&rom);
+
+        ppu.set_mirroring(Mirroring::Vertical);
+
+        // Table 0 column 5 row 4 (x 40..48, y 32..40), table 1 column 2 row 4.
+        ppu.memory.write(0x2000 + 4 * 32 + 5, 0x33).unwrap();
+        ppu.memory.write(0x2400 + 4 * 32 + 2, 0x44).unwrap();
+
+        ppu.registers.render.write_scroll(20);
+        ppu.registers.render.write_scroll(13);
+
+        assert_eq!(ppu.nametable_byte_at_screen(20, 19), 0x33);
+        assert_eq!(ppu.nametable_byte_at_screen(27, 26), 0x33);
+        assert_eq!(ppu.nametable_byte_at_screen(19, 19), 0x00);
+        assert_eq!(ppu.nametable_byte_at_screen(28, 19), 0x00);
+
+        // Past the right edge of table 0, into table 1.
+        assert_eq!(ppu.nametable_byte_at_screen(253, 20), 0x44);
+    }
+
+    #[test]
+    fn chr_ram_write_reads_back() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.chr_rom.clear();
+
+        let mut memory = PpuMemory::new(&rom);
+
+        memory.write(0x0000, 0xA5).unwrap();
+        memory.write(0x1FFF, 0x5A).unwrap();
+
+        assert_eq!(memory.read(0x0000).unwrap(), 0xA5);
+        assert_eq!(memory.read(0x1FFF).unwrap(), 0x5A);
+
+        // CHR ROM stays read only.
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut memory = PpuMemory::new(&rom);
+
+        assert!(matches!(memory.write(0x0000, 0xA5), Err(PpuMemoryError::UnmappedWrite(0x0000))));
+        assert_eq!(memory.read(0x0000).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn power_on_registers() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let ppu = Ppu::new(&rom);
+        let registers = &ppu.registers;
+
+        assert_eq!((registers.control.bits(), registers.mask.bits()), (0, 0));
+        assert_eq!(registers.status.bits() & 0b11100000, 0b10000000);
+        assert_eq!((registers.oam_address, registers.read_buffer), (0, 0));
+
+        let render = &registers.render;
+
+        assert_eq!((render.t, render.v, render.x, render.w), (0, 0, 0, false));
+
+        assert!(ppu.warming_up(0));
+        assert!(!ppu.warming_up(PPU_WARMUP_CYCLES));
+    }
+
+    #[test]
+    fn name_table_mirrors_of_2000() {
+        let rom = Rom::from_prg(&[], 0x8000);
+
+        // Which of $2000, $2400, $2800 and $2C00 show a write to $2000.
+        let modes = [
+            (Some(Mirroring::Horizontal), [true, true, false, false]),
+            (Some(Mirroring::Vertical), [true, false, true, false]),
+            (Some(Mirroring::SingleLower), [true, true, true, true]),
+            (Some(Mirroring::SingleUpper), [true, true, true, true]),
+            (None, [true, false, false, false]),
+        ];
+
+        for (mirroring, expected) in modes {
+            let mut memory = PpuMemory::new(&rom);
+
+            memory.mirroring = mirroring;
+            memory.write(0x2000, 0x77).unwrap();
+
+            let mirrored = [0x2000, 0x2400, 0x2800, 0x2C00].map(|address| memory.read(address).unwrap() == 0x77);
+
+            assert_eq!(mirrored, expected, "{mirroring:?}");
+        }
+    }
+
+    #[test]
+    fn set_mirroring_switches_name_tables() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut ppu = Ppu::new(&rom);
+
+        assert_eq!(ppu.memory.mirroring, Some(Mirroring::Horizontal));
+
+        ppu.memory.write(0x2400, 0x11).unwrap();
+
+        assert_eq!(ppu.memory.read(0x2000).unwrap(), 0x11);
+        assert_eq!(ppu.memory.read(0x2C00).unwrap(), 0x00);
+
+        ppu.set_mirroring(Mirroring::Vertical);
+
+        // $2400 is now backed by the second table, shared with $2C00.
+        assert_eq!(ppu.memory.read(0x2400).unwrap(), 0x00);
+
+        ppu.memory.write(0x2400, 0x22).unwrap();
+
+        assert_eq!(ppu.memory.read(0x2C00).unwrap(), 0x22);
+        assert_eq!(ppu.memory.read(0x2000).unwrap(), 0x11);
+        assert_eq!(ppu.memory.read(0x2800).unwrap(), 0x11);
+    }
+
+    #[test]
+    fn sprite_backdrop_entries_mirror_background() {
+        let mut palette = PaletteMemory::default();
+
+        for (sprite, background) in [(0x10, 0x00), (0x14, 0x04), (0x18, 0x08), (0x1C, 0x0C)] {
+            palette.set(sprite, 0x21 + sprite as u8).unwrap();
+
+            assert_eq!(palette.get(background).unwrap(), 0x21 + sprite as u8);
+
+            palette.set(background, 0x05 + background as u8).unwrap();
+
+            assert_eq!(palette.get(sprite).unwrap(), 0x05 + background as u8);
+        }
+
+        // Through the PPU bus, including the $3F20-$3FFF mirrors.
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut memory = PpuMemory::new(&rom);
+
+        memory.write(0x3F10, 0x2A).unwrap();
+
+        assert_eq!(memory.read(0x3F00).unwrap(), 0x2A);
+        assert_eq!(memory.read(0x3F30).unwrap(), 0x2A);
+
+        memory.write(0x3F00, 0x0F).unwrap();
+
+        assert_eq!(memory.read(0x3F10).unwrap(), 0x0F);
+    }
+
+    #[test]
+    fn every_palette_slot_round_trips() {
+        let mut palette = PaletteMemory::default();
+
+        for address in 0x00 .. 0x20 {
+            palette.set(address, address as u8).unwrap();
+
+            assert_eq!(palette.get(address).unwrap(), address as u8);
+        }
+
+        // Entries $x0, $x4, $x8 and $xC are shared, so the later $1x write wins.
+        for address in 0x00 .. 0x20 {
+            let expected = if address % 4 == 0 { address | 0x10 } else { address };
+
+            assert_eq!(palette.get(address).unwrap(), expected as u8, "palette slot {address:02X}");
+        }
+    }
+
+    #[test]
+    fn palette_reads_skip_the_buffer() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let mut ppu = Ppu::new(&rom);
+
+        ppu.memory.write(0x3F00, 0x2A).unwrap();
+        ppu.memory.write(0x2F00, 0x77).unwrap();
+
+        for _ in 0 .. 2 {
+            ppu.registers.render.write_address(0x3F);
+            ppu.registers.render.write_address(0x00);
+
+            // The current palette entry on the first read, no stale buffer byte first.
+            assert_eq!(ppu.read_data().unwrap(), 0x2A);
+
+            // The buffer holds the name table byte under the palette.
+            assert_eq!(ppu.registers.read_buffer, 0x77);
         }
     }
 }
@@ -0,0 +1,86 @@
+use std::io::{Result, Write};
+use crate::controller::ControllerFlags;
+use crate::renderer::RenderedFrame;
+
+// FM2 button order, one character per button.
+const BUTTONS: [(ControllerFlags, char); 8] = [
+    (ControllerFlags::RIGHT, 'R'),
+    (ControllerFlags::LEFT, 'L'),
+    (ControllerFlags::DOWN, 'D'),
+    (ControllerFlags::UP, 'U'),
+    (ControllerFlags::START, 'T'),
+    (ControllerFlags::SELECT, 'S'),
+    (ControllerFlags::B, 'B'),
+    (ControllerFlags::A, 'A'),
+];
+
+// Writes raw RGBA frames (NES_WIDTH x NES_HEIGHT) to video and
+// one input line per frame to inputs, so both always share the same cadence.
+pub struct Recorder<V: Write, I: Write> {
+    video: V,
+    inputs: I,
+    frames: usize
+}
+
+pub fn format_input(input: ControllerFlags) -> String {
+    BUTTONS.iter()
+        .map(|(flag, name)| if input.contains(*flag) { *name } else { '.' })
+        .collect()
+}
+
+impl<V: Write, I: Write> Recorder<V, I> {
+    pub fn record(&mut self, frame: &RenderedFrame, input: ControllerFlags) -> Result<()> {
+        self.video.write_all(&frame.frame)?;
+
+        writeln!(self.inputs, "|0|{}|||", format_input(input))?;
+
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn finish(mut self) -> Result<usize> {
+        self.video.flush()?;
+        self.inputs.flush()?;
+
+        Ok(self.frames)
+    }
+
+    pub fn new(video: V, inputs: I) -> Recorder<V, I> {
+        Recorder {
+            video,
+            inputs,
+            frames: 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::NES_FRAME_SIZE;
+    use super::*;
+
+    #[test]
+    fn one_input_line_per_frame() {
+        let mut video = vec![];
+        let mut inputs = vec![];
+
+        let mut recorder = Recorder::new(&mut video, &mut inputs);
+        let frame = RenderedFrame::default();
+
+        for input in [ControllerFlags::empty(), ControllerFlags::A | ControllerFlags::RIGHT, ControllerFlags::START] {
+            recorder.record(&frame, input).unwrap();
+        }
+
+        assert_eq!(recorder.finish().unwrap(), 3);
+
+        let inputs = String::from_utf8(inputs).unwrap();
+
+        assert_eq!(inputs.lines().collect::<Vec<_>>(), ["|0|........|||", "|0|R......A|||", "|0|....T...|||"]);
+        assert_eq!(video.len(), 3 * NES_FRAME_SIZE);
+    }
+}
@@ -1,3 +1,4 @@
+use serde_derive::{Deserialize, Serialize};
 use crate::ppu::Ppu;
 
 
@@ -10,12 +11,98 @@ pub struct RenderedFrame {
     pub frame: [u8; NES_FRAME_SIZE]
 }
 
+impl RenderedFrame {
+    // RGBA bytes of a rectangle, row by row, clipped to the frame.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+        crop_pixels(&self.frame, 4, x, y, width, height)
+    }
+
+    // Absolute brightness change of each pixel since previous, one byte per pixel.
+    pub fn motion(&self, previous: &RenderedFrame) -> Vec<u8> {
+        let luma = |pixel: &[u8]| {
+            (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000
+        };
+
+        self.frame.chunks_exact(4)
+            .zip(previous.frame.chunks_exact(4))
+            .map(|(current, previous)| luma(current).abs_diff(luma(previous)) as u8)
+            .collect()
+    }
+}
+
+// Rectangle of a NES_WIDTH x NES_HEIGHT image with pixel_size bytes per pixel, clipped to the image.
+pub fn crop_pixels(data: &[u8], pixel_size: usize, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+    let right = x.saturating_add(width).min(NES_WIDTH);
+    let bottom = y.saturating_add(height).min(NES_HEIGHT);
+
+    let left = x.min(right);
+
+    (y.min(bottom) .. bottom)
+        .flat_map(|row| &data[(row * NES_WIDTH + left) * pixel_size .. (row * NES_WIDTH + right) * pixel_size])
+        .copied()
+        .collect()
+}
+
+// Dot the renderer draws next, (0, 0) being the top left of a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanPosition {
+    pub x: usize,
+    pub y: usize,
+    // Last CPU cycle rendered, behind Memory::cycles until the next render call (e.g. after an NMI).
+    pub cycle: u64
+}
+
 pub enum RenderAction {
     None,
     // Equivalent ot Send NMI
-    SendFrame(Box<RenderedFrame>)
+    SendFrame(Box<RenderedFrame>),
+    // Frame completed, but the NMI was suppressed (see Ppu::suppress_nmi).
+    SuppressedFrame(Box<RenderedFrame>)
 }
 
 pub trait Renderer {
     fn render(&mut self, ppu: &mut Ppu, cycle: u64) -> RenderAction;
+
+    // Returns to the top of the frame at cycle zero.
+    fn reset(&mut self);
+
+    fn scan_position(&self) -> ScanPosition;
+
+    // Continues drawing from position, used after restoring a CpuState, see CpuState::with_renderer.
+    fn resume(&mut self, position: ScanPosition);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each pixel holds its own coordinates.
+    fn coordinate_frame() -> RenderedFrame {
+        let mut frame = RenderedFrame::default();
+
+        for (index, pixel) in frame.frame.chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&[(index % NES_WIDTH) as u8, (index / NES_WIDTH) as u8, 0, 255]);
+        }
+
+        frame
+    }
+
+    #[test]
+    fn crop_copies_the_region() {
+        let frame = coordinate_frame();
+
+        let expected: Vec<u8> = [(10, 20), (11, 20), (12, 20), (10, 21), (11, 21), (12, 21)]
+            .into_iter()
+            .flat_map(|(x, y)| [x, y, 0, 255])
+            .collect();
+
+        assert_eq!(frame.crop(10, 20, 3, 2), expected);
+
+        // Clipped to the bottom right corner, 6 x 5 pixels.
+        let corner = frame.crop(250, 235, 10, 10);
+
+        assert_eq!(corner.len(), 6 * 5 * 4);
+        assert_eq!(corner[.. 4], [250, 235, 0, 255]);
+        assert_eq!(corner[corner.len() - 4 ..], [255, 239, 0, 255]);
+    }
 }
@@ -1,15 +1,23 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
 use nom::bytes::complete::{tag, take as take_bytes};
 use nom::IResult;
 use nom::number::complete::{u8 as take_u8};
 use nom::bits::complete::{bool, take as take_bits};
+use serde_derive::{Deserialize, Serialize};
+use crate::mapper::is_supported_mapper;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Mirroring {
     Horizontal,
-    Vertical
+    Vertical,
+    // Every name table shows the first (or second) table, only set by mappers.
+    SingleLower,
+    SingleUpper,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Flags {
     pub mirroring: Mirroring,
     pub battery_ram: bool,
@@ -17,13 +25,35 @@ pub struct Flags {
     pub four_screen: bool,
     pub uni_system: bool,
     pub play_choice: bool,
+    // 2 marks a NES 2.0 header, which extends mapper and adds submapper.
     pub nes2_test: u8,
-    pub mapper: u8,
+    pub mapper: u16,
+    pub submapper: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub prg_size: usize,
+    pub chr_size: usize,
+    pub flags: Flags,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(std::io::Error),
+    // The file doesn't start with NES<EOF>.
+    BadMagic,
+    Truncated { expected: usize, found: usize },
+    UnsupportedMapper(u16),
+    // Arcade boards (VS System, PlayChoice-10) with hardware this emulator lacks.
+    Unsupported(&'static str),
 }
 
 #[derive(Clone, Debug)]
 pub struct Rom {
     pub flags: Flags,
+    // Loaded into $7000-$71FF on power up, see Memory::new.
+    pub trainer: Option<[u8; TRAINER_SIZE]>,
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>
 }
@@ -44,7 +74,7 @@ pub fn parse_flags(bytes: &[u8]) -> IResult<(&[u8], usize), Flags> {
     let (bits, play_choice) = bool(bits)?;
     let (bits, uni_system) = bool(bits)?;
 
-    let mapper = (upper_mapper << 4) | lower_mapper;
+    let mapper = ((upper_mapper << 4) | lower_mapper) as u16;
 
     Ok((bits, Flags {
         mirroring,
@@ -55,10 +85,74 @@ pub fn parse_flags(bytes: &[u8]) -> IResult<(&[u8], usize), Flags> {
         play_choice,
         nes2_test,
         mapper,
+        submapper: 0,
     }))
 }
 
-pub fn parse_rom(bytes: &[u8]) -> IResult<&[u8], Rom> {
+impl Flags {
+    // Inverse of parse_flags, bytes 6 and 7 of the header.
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let mapper = self.mapper as u8;
+
+        let flags_6 = (mapper & 0x0F) << 4
+            | (self.four_screen as u8) << 3
+            | (self.has_trainer as u8) << 2
+            | (self.battery_ram as u8) << 1
+            | (self.mirroring == Mirroring::Vertical) as u8;
+
+        let flags_7 = (mapper & 0xF0)
+            | (self.nes2_test & 0b11) << 2
+            | (self.play_choice as u8) << 1
+            | self.uni_system as u8;
+
+        [flags_6, flags_7]
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.nes2_test == 2
+    }
+}
+
+// 64-bit FNV-1a, stable across builds and Rust releases, unlike DefaultHasher.
+pub fn fnv1a<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u64 {
+    bytes.into_iter().fold(0xCBF29CE484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001B3)
+    })
+}
+
+// Inverse of nes2_size, (LSB, MSB nibble, padded size in bytes) for size bytes of ROM.
+// Past $FFF banks the size is padded up to a power of two and written in exponent notation.
+fn nes2_size_fields(size: usize, unit: usize) -> (u8, u8, usize) {
+    let banks = size.div_ceil(unit);
+
+    if banks <= 0xFFF {
+        (banks as u8, (banks >> 8) as u8, banks * unit)
+    } else {
+        let exponent = size.next_power_of_two().trailing_zeros();
+
+        ((exponent << 2) as u8, 0xF, 1 << exponent)
+    }
+}
+
+// NES 2.0 ROM size from its LSB byte and MSB nibble, in bytes.
+// An MSB nibble of $F switches to exponent notation, 2^E * (MM * 2 + 1) with LSB = EEEEEEMM.
+fn nes2_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0xF {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+
+        2usize.checked_pow(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+
+pub fn parse_header(bytes: &[u8]) -> IResult<&[u8], Header> {
     let (bytes, _) = tag([b'N', b'E', b'S', 0x1A])(bytes)?;
 
     let (bytes, prg_size) = take_u8(bytes)?;
@@ -67,17 +161,410 @@ pub fn parse_rom(bytes: &[u8]) -> IResult<&[u8], Rom> {
     let ((bytes, _), flags) = parse_flags(bytes)
         .map_err(|e| e.map_input(|(bytes, _)| bytes))?;
 
-    let (bytes, _) = take_bytes(8usize)(bytes)?;
+    let (bytes, extended) = take_bytes(8usize)(bytes)?;
 
-    let prg_size = 16384 * (prg_size as usize);
-    let chr_size = 8192 * (chr_size as usize);
+    if !flags.is_nes2() {
+        return Ok((bytes, Header {
+            prg_size: 16384 * (prg_size as usize),
+            chr_size: 8192 * (chr_size as usize),
+            flags,
+        }))
+    }
 
-    let (bytes, prg_rom) = take_bytes(prg_size)(bytes)?;
-    let (bytes, chr_rom) = take_bytes(chr_size)(bytes)?;
+    let mut flags = flags;
 
-    Ok((bytes, Rom {
+    flags.mapper |= ((extended[0] & 0x0F) as u16) << 8;
+    flags.submapper = extended[0] >> 4;
+
+    Ok((bytes, Header {
+        prg_size: nes2_size(prg_size, extended[1] & 0x0F, 16384),
+        chr_size: nes2_size(chr_size, extended[1] >> 4, 8192),
         flags,
-        prg_rom: prg_rom.to_vec(),
-        chr_rom: chr_rom.to_vec(),
     }))
 }
+
+// Parses a whole iNES file held in memory, see Rom::from_reader for streams.
+pub fn parse_rom(bytes: &[u8]) -> Result<Rom, RomError> {
+    let (data, header) = read_header(bytes)?;
+
+    header.into_rom(data)
+}
+
+fn read_header(bytes: &[u8]) -> Result<(&[u8], Header), RomError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(RomError::Truncated { expected: HEADER_SIZE, found: bytes.len() })
+    }
+
+    // With 16 bytes present, only the magic can fail to parse.
+    parse_header(bytes).map_err(|_| RomError::BadMagic)
+}
+
+impl From<std::io::Error> for RomError {
+    fn from(value: std::io::Error) -> Self {
+        RomError::Io(value)
+    }
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::Io(error) => Display::fmt(error, f),
+            RomError::BadMagic => write!(f, "Not an iNES file, the header does not start with NES<EOF>"),
+            RomError::Truncated { expected, found } =>
+                write!(f, "ROM is truncated, expected {expected} bytes but only {found} are present"),
+            RomError::UnsupportedMapper(mapper) => write!(f, "Mapper {mapper} is not supported"),
+            RomError::Unsupported(system) => write!(f, "{system} ROMs are not supported"),
+        }
+    }
+}
+
+impl Error for RomError { }
+
+impl Header {
+    pub fn trainer_size(&self) -> usize {
+        if self.flags.has_trainer { TRAINER_SIZE } else { 0 }
+    }
+
+    // Everything after the header: trainer, PRG then CHR.
+    pub fn data_size(&self) -> usize {
+        self.prg_size.saturating_add(self.chr_size).saturating_add(self.trainer_size())
+    }
+
+    fn check_supported(&self) -> Result<(), RomError> {
+        if self.flags.uni_system {
+            return Err(RomError::Unsupported("VS System"))
+        }
+
+        if self.flags.play_choice {
+            return Err(RomError::Unsupported("PlayChoice-10"))
+        }
+
+        if !is_supported_mapper(self.flags.mapper) {
+            return Err(RomError::UnsupportedMapper(self.flags.mapper))
+        }
+
+        Ok(())
+    }
+
+    fn into_rom(self, data: &[u8]) -> Result<Rom, RomError> {
+        self.check_supported()?;
+
+        if data.len() < self.data_size() {
+            return Err(RomError::Truncated { expected: self.data_size(), found: data.len() })
+        }
+
+        let (trainer, data) = data.split_at(self.trainer_size());
+        let (prg_rom, data) = data.split_at(self.prg_size);
+        let chr_rom = &data[.. self.chr_size];
+
+        Ok(Rom {
+            flags: self.flags,
+            trainer: trainer.try_into().ok(),
+            prg_rom: prg_rom.to_vec(),
+            chr_rom: chr_rom.to_vec(),
+        })
+    }
+}
+
+impl Rom {
+    pub fn load(bytes: &[u8]) -> Result<Rom, RomError> {
+        parse_rom(bytes)
+    }
+
+    // FNV-1a over the trainer, PRG and CHR.
+    pub fn hash(&self) -> u64 {
+        let trainer = self.trainer.as_ref().map(|trainer| trainer.as_slice()).unwrap_or_default();
+
+        fnv1a(trainer.iter().chain(&self.prg_rom).chain(&self.chr_rom))
+    }
+
+    // Wraps a raw 6502 blob as 32KB NROM, loaded at $8000 and reset to entry.
+    // NMI and IRQ vectors point at an RTI in $FFF9, overwriting the blob if it is that long.
+    pub fn from_prg(bytes: &[u8], entry: u16) -> Rom {
+        let mut prg_rom = vec![0; 0x8000];
+
+        let length = bytes.len().min(prg_rom.len());
+        prg_rom[.. length].copy_from_slice(&bytes[.. length]);
+
+        const RTI: u8 = 0x40;
+        const RTI_ADDRESS: u16 = 0xFFF9;
+
+        prg_rom[0x7FF9] = RTI;
+
+        for (offset, vector) in [RTI_ADDRESS, entry, RTI_ADDRESS].into_iter().enumerate() {
+            prg_rom[0x7FFA + offset * 2 .. 0x7FFC + offset * 2].copy_from_slice(&vector.to_le_bytes());
+        }
+
+        Rom {
+            flags: Flags {
+                mirroring: Mirroring::Horizontal,
+                battery_ram: false,
+                has_trainer: false,
+                four_screen: false,
+                uni_system: false,
+                play_choice: false,
+                nes2_test: 0,
+                mapper: 0,
+                submapper: 0,
+            },
+            trainer: None,
+            prg_rom,
+            chr_rom: vec![0; 0x2000],
+        }
+    }
+
+    // iNES image of this ROM, PRG and CHR are padded up to whole banks.
+    // NES 2.0 ROMs keep their extended mapper and submapper, other NES 2.0 fields are written as zero.
+    // Sizes or mappers that don't fit in iNES switch the header to NES 2.0.
+    pub fn to_ines_bytes(&self) -> Vec<u8> {
+        let (prg_lsb, prg_msb, prg_size) = nes2_size_fields(self.prg_rom.len(), 16384);
+        let (chr_lsb, chr_msb, chr_size) = nes2_size_fields(self.chr_rom.len(), 8192);
+
+        let nes2 = self.flags.is_nes2() || prg_msb != 0 || chr_msb != 0 || self.flags.mapper > 0xFF;
+
+        let flags = Flags {
+            has_trainer: self.trainer.is_some(),
+            nes2_test: if nes2 { 2 } else { self.flags.nes2_test },
+            ..self.flags.clone()
+        }.to_bytes();
+
+        let mut bytes = vec![b'N', b'E', b'S', 0x1A, prg_lsb, chr_lsb, flags[0], flags[1]];
+
+        if nes2 {
+            bytes.push(self.flags.submapper << 4 | (self.flags.mapper >> 8) as u8 & 0x0F);
+            bytes.push(chr_msb << 4 | prg_msb);
+        }
+
+        bytes.resize(HEADER_SIZE, 0);
+
+        if let Some(trainer) = &self.trainer {
+            bytes.extend_from_slice(trainer);
+        }
+
+        let start = bytes.len();
+
+        bytes.extend_from_slice(&self.prg_rom);
+        bytes.resize(start + prg_size, 0);
+
+        bytes.extend_from_slice(&self.chr_rom);
+        bytes.resize(start + prg_size + chr_size, 0);
+
+        bytes
+    }
+
+    // Reads the header, then exactly the PRG and CHR sizes it declares.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Rom, RomError> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+
+        reader.by_ref().take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+
+        let (_, header) = read_header(&header)?;
+
+        header.check_supported()?;
+
+        // NES 2.0 exponent sizes can be huge, let the data itself decide the allocation.
+        let mut data = Vec::new();
+
+        reader.take(header.data_size() as u64).read_to_end(&mut data)?;
+
+        header.into_rom(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::cpu::Cpu;
+    use super::*;
+
+    fn assert_same_rom(a: &Rom, b: &Rom) {
+        assert_eq!(a.flags, b.flags);
+        assert_eq!(a.trainer, b.trainer);
+        assert_eq!(a.prg_rom, b.prg_rom);
+        assert_eq!(a.chr_rom, b.chr_rom);
+    }
+
+    #[test]
+    fn ines_bytes_round_trip() {
+        let mut rom = Rom::from_prg(&[0xA9, 0x01, 0x4C, 0x00, 0x80], 0x8000);
+
+        rom.flags.mirroring = Mirroring::Vertical;
+        rom.flags.battery_ram = true;
+        rom.chr_rom[0x1234] = 0x56;
+
+        assert_same_rom(&parse_rom(&rom.to_ines_bytes()).unwrap(), &rom);
+    }
+
+    #[test]
+    fn wide_roms_switch_to_nes2() {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.prg_rom = (0 .. 300 * 16384).map(|i| (i / 16384) as u8).collect();
+
+        let bytes = rom.to_ines_bytes();
+
+        assert_eq!(bytes[4], (300 & 0xFF) as u8);
+        assert_eq!(bytes[9] & 0x0F, 1);
+
+        let parsed = parse_rom(&bytes).unwrap();
+
+        assert!(parsed.flags.is_nes2());
+        assert_eq!(parsed.prg_rom, rom.prg_rom);
+        assert_eq!(parsed.chr_rom, rom.chr_rom);
+    }
+
+    #[test]
+    fn from_reader_stops_after_declared_data() {
+        let mut rom = Rom::from_prg(&[0xA9, 0x01], 0x8000);
+
+        rom.chr_rom[0x1FFF] = 0x42;
+
+        let mut bytes = rom.to_ines_bytes();
+        let length = bytes.len();
+
+        bytes.extend_from_slice(b"trailing junk");
+
+        let mut cursor = std::io::Cursor::new(bytes);
+
+        assert_same_rom(&Rom::from_reader(&mut cursor).unwrap(), &rom);
+        assert_eq!(cursor.position(), length as u64);
+    }
+
+    #[test]
+    fn truncated_prg_is_reported() {
+        let rom = Rom::from_prg(&[], 0x8000);
+        let bytes = rom.to_ines_bytes();
+
+        // PRG and CHR declared, but only 20000 bytes of data follow the header.
+        let truncated = &bytes[.. HEADER_SIZE + 20000];
+
+        assert!(matches!(
+            Rom::load(truncated),
+            Err(RomError::Truncated { expected: 0xA000, found: 20000 })
+        ));
+
+        assert!(matches!(
+            Rom::load(&bytes[.. 10]),
+            Err(RomError::Truncated { expected: HEADER_SIZE, found: 10 })
+        ));
+    }
+
+    #[test]
+    fn from_prg_starts_at_entry() {
+        let program = [
+            0xA9, 0x01,             // LDA #$01
+            0x85, 0x00,             // STA $00
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xA9, 0x42,             // entry: LDA #$42
+            0x85, 0x00,             // STA $00
+        ];
+
+        let rom = Rom::from_prg(&program, 0x8010);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        assert_eq!(cpu.registers.pc, 0x8010);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.memory.ram[0], 0x42);
+        assert_eq!(cpu.registers.pc, 0x8014);
+    }
+
+    #[test]
+    fn play_choice_is_detected() {
+        let mut bytes = Rom::from_prg(&[], 0x8000).to_ines_bytes();
+
+        // Flags 7, bit 1.
+        bytes[7] |= 0b10;
+
+        assert!(matches!(Rom::load(&bytes), Err(RomError::Unsupported("PlayChoice-10"))));
+
+        bytes[7] = (bytes[7] & !0b10) | 0b01;
+
+        assert!(matches!(Rom::load(&bytes), Err(RomError::Unsupported("VS System"))));
+    }
+
+    fn header(bytes: [u8; 12]) -> Header {
+        let mut data = b"NES\x1A".to_vec();
+
+        data.extend_from_slice(&bytes);
+
+        parse_header(&data).unwrap().1
+    }
+
+    #[test]
+    fn ines_and_nes2_header_sizes() {
+        // iNES, 2 x 16KB PRG, 1 x 8KB CHR, mapper 1.
+        let ines = header([2, 1, 0x10, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert!(!ines.flags.is_nes2());
+        assert_eq!((ines.prg_size, ines.chr_size, ines.flags.mapper), (0x8000, 0x2000, 1));
+
+        // NES 2.0, mapper $104 submapper 3, size MSB nibbles 1 (PRG) and 2 (CHR).
+        let nes2 = header([2, 1, 0x40, 0x08, 0x31, 0x21, 0, 0, 0, 0, 0, 0]);
+
+        assert!(nes2.flags.is_nes2());
+        assert_eq!((nes2.flags.mapper, nes2.flags.submapper), (0x104, 3));
+        assert_eq!((nes2.prg_size, nes2.chr_size), (0x102 * 16384, 0x201 * 8192));
+
+        // Exponent notation, 2^10 * 3 bytes of PRG and 2^7 * 1 of CHR.
+        let exponent = header([10 << 2 | 1, 7 << 2, 0x00, 0x08, 0x00, 0xFF, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!((exponent.prg_size, exponent.chr_size), (3072, 128));
+    }
+
+    #[test]
+    fn trainer_is_skipped_and_loaded() {
+        // 1 x 16KB PRG, 1 x 8KB CHR, trainer flag set.
+        let mut bytes = b"NES\x1A\x01\x01\x04\x00".to_vec();
+
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend((0 .. TRAINER_SIZE).map(|i| i as u8));
+        bytes.extend([0xA9; 0x4000]);
+        bytes.extend([0x33; 0x2000]);
+
+        let rom = Rom::load(&bytes).unwrap();
+        let trainer = rom.trainer.unwrap();
+
+        assert_eq!(trainer[.. 4], [0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(trainer[TRAINER_SIZE - 1], 0xFF);
+
+        assert!(rom.prg_rom.iter().all(|byte| *byte == 0xA9));
+        assert!(rom.chr_rom.iter().all(|byte| *byte == 0x33));
+
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        assert_eq!(cpu.memory.get(0x7000).unwrap(), 0x00);
+        assert_eq!(cpu.memory.get(0x7042).unwrap(), 0x42);
+        assert_eq!(cpu.memory.get(0x71FF).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let bytes = Rom::from_prg(&[], 0x8000).to_ines_bytes();
+
+        let short = parse_rom(&bytes[.. 4]);
+
+        assert!(matches!(short, Err(RomError::Truncated { expected: HEADER_SIZE, found: 4 })));
+
+        let mut wrong_magic = bytes.clone();
+
+        wrong_magic[.. 4].copy_from_slice(b"PK\x03\x04");
+
+        let error = parse_rom(&wrong_magic).unwrap_err();
+
+        assert!(matches!(error, RomError::BadMagic));
+        assert_eq!(error.to_string(), "Not an iNES file, the header does not start with NES<EOF>");
+
+        // Mapper 5 (MMC5), low nibble in flags 6 and high nibble in flags 7.
+        let mut mapper = bytes;
+
+        mapper[6] |= 0x50;
+
+        let error = parse_rom(&mapper).unwrap_err();
+
+        assert!(matches!(error, RomError::UnsupportedMapper(5)));
+        assert_eq!(error.to_string(), "Mapper 5 is not supported");
+    }
+}
@@ -1,92 +1,70 @@
-use crate::ppu::{Palette, Ppu};
-use crate::renderer::{Renderer, RenderAction, NES_WIDTH, RenderedFrame, NES_FRAME_SIZE};
+use crate::controller::Controller;
+use crate::cpu::Cpu;
+use crate::interpreter::CpuError;
+use crate::palette::{Color, NES_PALETTE};
+use crate::ppu::{LineScroll, MaskRegister, Palette, Ppu};
+use crate::renderer::{Renderer, RenderAction, NES_WIDTH, RenderedFrame, NES_FRAME_SIZE, ScanPosition};
 
 pub const NES_SCANLINE_WIDTH: usize = 341;
 pub const NES_SCANLINE_COUNT: usize = 262;
 
-type Color = [u8; 4];
-
-const NES_PALETTE: [Color; 0x40] = [
-    [98, 98, 98, 255],
-    [0, 31, 177, 255],
-    [35, 3, 199, 255],
-    [81, 0, 177, 255],
-    [115, 0, 117, 255],
-    [127, 0, 35, 255],
-    [115, 10, 0, 255],
-    [81, 39, 0, 255],
-    [35, 67, 0, 255],
-    [0, 86, 0, 255],
-    [0, 92, 0, 255],
-    [0, 82, 35, 255],
-    [0, 60, 117, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-    [170, 170, 170, 255],
-    [13, 86, 255, 255],
-    [74, 47, 255, 255],
-    [138, 18, 255, 255],
-    [188, 8, 213, 255],
-    [210, 17, 104, 255],
-    [199, 45, 0, 255],
-    [157, 84, 0, 255],
-    [96, 123, 0, 255],
-    [32, 151, 0, 255],
-    [0, 162, 0, 255],
-    [0, 152, 66, 255],
-    [0, 124, 180, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-    [255, 255, 255, 255],
-    [82, 174, 255, 255],
-    [143, 133, 255, 255],
-    [210, 101, 255, 255],
-    [255, 86, 255, 255],
-    [255, 93, 206, 255],
-    [255, 119, 86, 255],
-    [249, 158, 0, 255],
-    [188, 199, 0, 255],
-    [121, 231, 0, 255],
-    [66, 246, 17, 255],
-    [38, 239, 125, 255],
-    [44, 213, 245, 255],
-    [77, 77, 77, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-    [255, 255, 255, 255],
-    [182, 225, 255, 255],
-    [205, 208, 255, 255],
-    [232, 195, 255, 255],
-    [255, 187, 255, 255],
-    [255, 188, 243, 255],
-    [255, 198, 195, 255],
-    [255, 213, 153, 255],
-    [232, 230, 129, 255],
-    [205, 243, 129, 255],
-    [182, 250, 153, 255],
-    [168, 249, 195, 255],
-    [168, 240, 243, 255],
-    [183, 183, 183, 255],
-    [0, 0, 0, 255],
-    [0, 0, 0, 255],
-];
+// CPU cycles per frame, rounded up. Three PPU dots per CPU cycle.
+pub const NES_FRAME_CYCLES: u64 = ((NES_SCANLINE_WIDTH * NES_SCANLINE_COUNT).div_ceil(3)) as u64;
 
 struct PreRenderedScanline {
     background: [Option<Color>; NES_WIDTH],
-    foreground: [Option<Color>; NES_WIDTH]
+    foreground: [Option<Color>; NES_WIDTH],
+    // Opaque pixels of sprite 0, for the sprite 0 hit test in render_pixel.
+    sprite_zero: [bool; NES_WIDTH],
+}
+
+// Debugging override on top of PPUMASK. Hidden layers are still evaluated
+// (sprite 0 hits happen as usual), they just aren't drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layers {
+    pub background: bool,
+    pub sprites: bool,
+}
+
+impl Default for Layers {
+    fn default() -> Layers {
+        Layers { background: true, sprites: true }
+    }
 }
 
-#[derive(Default)]
 pub struct SoftwareRenderer {
     pub scan_x: usize,
     pub scan_y: usize,
+    pub palette: [Color; 0x40],
+    pub layers: Layers,
+    // Draws every sprite on a scanline instead of the first eight, removing flicker.
+    pub unlimited_sprites: bool,
     last_cycle: u64,
+    // Horizontal scroll for the current scanline, so writes mid-line apply from the next one.
+    line_scroll: LineScroll,
+    // The rest of the current row was already filled with the backdrop.
+    blank_row: bool,
     pre_rendered_sprites: Option<PreRenderedScanline>,
     frame: Box<RenderedFrame>,
 }
 
+impl Default for SoftwareRenderer {
+    fn default() -> SoftwareRenderer {
+        SoftwareRenderer {
+            scan_x: 0,
+            scan_y: 0,
+            palette: NES_PALETTE,
+            layers: Layers::default(),
+            unlimited_sprites: false,
+            last_cycle: 0,
+            line_scroll: LineScroll::default(),
+            blank_row: false,
+            pre_rendered_sprites: None,
+            frame: Box::default(),
+        }
+    }
+}
+
 impl Default for RenderedFrame {
     fn default() -> RenderedFrame {
         RenderedFrame { frame: [255; NES_FRAME_SIZE] }
@@ -98,29 +76,68 @@ impl Default for PreRenderedScanline {
         PreRenderedScanline {
             background: [None; NES_WIDTH],
             foreground: [None; NES_WIDTH],
+            sprite_zero: [false; NES_WIDTH],
         }
     }
 }
 
+// Background palette index picked by the attribute table for a tile in a name table.
+fn attribute_palette(ppu: &Ppu, table: usize, col: usize, row: usize) -> u8 {
+    let attribute_column = col / 4;
+    let attribute_row = row / 4;
+
+    let attribute_address = 0x3C0 + attribute_column + attribute_row * 8;
+
+    let attribute_byte = ppu.memory.names[table].contents[attribute_address];
+    let attribute_right = (col / 2) % 2;
+    let attribute_bottom = (row / 2) % 2;
+
+    let attribute_shift = attribute_right * 2 + attribute_bottom * 4;
+
+    (attribute_byte >> attribute_shift) & 0b11
+}
+
+// Background palette index under a screen pixel, after scrolling.
+pub fn background_palette_at(ppu: &Ppu, x: usize, y: usize) -> u8 {
+    let (name_table, offset_x, offset_y) = ppu.scrolled_position(x, y);
+
+    attribute_palette(ppu, name_table, offset_x / 8, offset_y / 8)
+}
+
 impl SoftwareRenderer {
-    fn render_sprite(&mut self, ppu: &mut Ppu, sprite: usize, x: usize, y: usize, palette: Palette) -> Option<Color> {
-        let address = sprite * 8 * 2 + y;
-        let plane_0 = ppu.memory.rom.chr_rom[address];
-        let plane_1 = ppu.memory.rom.chr_rom[address + 8];
+    // Palette entry as PPUMASK shows it. Greyscale keeps only the grey column,
+    // each emphasis bit darkens the other two channels.
+    fn color(&self, mask: &MaskRegister, index: u8) -> Color {
+        let index = if mask.greyscale { index & 0x30 } else { index };
+
+        let mut color = self.palette[index as usize & 0x3F];
+
+        let emphasis = [mask.emphasize_red, mask.emphasize_green, mask.emphasize_blue];
+
+        for (i, channel) in color.iter_mut().take(3).enumerate() {
+            let darkened = emphasis.iter().enumerate()
+                .filter(|&(j, &emphasized)| emphasized && i != j)
+                .count();
+
+            for _ in 0 .. darkened {
+                *channel = (*channel as u16 * 3 / 4) as u8;
+            }
+        }
 
-        let mask = 1 << (7 - x);
+        color
+    }
 
-        let has_bit_0 = plane_0 & mask != 0;
-        let has_bit_1 = plane_1 & mask != 0;
+    fn render_sprite(&mut self, ppu: &mut Ppu, sprite: usize, x: usize, y: usize, palette: Palette) -> Option<Color> {
+        let pixels = ppu.memory.tile(sprite);
 
-        let index = if has_bit_0 { 1 } else { 0 } | if has_bit_1 { 2 } else { 0 };
+        let index = pixels[y][x] as usize;
 
         if index == 0 {
             None
         } else {
             let color_index = palette[index - 1];
 
-            Some(NES_PALETTE[color_index as usize])
+            Some(self.color(&ppu.registers.mask, color_index))
         }
     }
 
@@ -133,17 +150,7 @@ impl SoftwareRenderer {
 
         let sprite = ppu.memory.names[table].contents[col + row * 32];
 
-        let attribute_column = col / 4;
-        let attribute_row = row / 4;
-
-        let attribute_address = 0x3C0 + attribute_column + attribute_row * 8;
-
-        let attribute_byte = ppu.memory.names[table].contents[attribute_address];
-        let attribute_right = (col / 2) % 2;
-        let attribute_bottom = (row / 2) % 2;
-
-        let attribute_shift = attribute_right * 2 + attribute_bottom * 4;
-        let palette_index = (attribute_byte >> attribute_shift) & 0b11;
+        let palette_index = attribute_palette(ppu, table, col, row);
 
         let palette = ppu.memory.palette.background[palette_index as usize];
 
@@ -153,26 +160,42 @@ impl SoftwareRenderer {
     fn pre_render_sprites(&mut self, ppu: &mut Ppu, y: usize) -> PreRenderedScanline {
         let mut result = PreRenderedScanline::default();
 
+        let tall = ppu.registers.control.sprite_size;
+
         let sprite_width = 8;
-        let sprite_height = 8;
+        let sprite_height = if tall { 16 } else { 8 };
+
+        // Sprites on this scanline in OAM order, with the row of the sprite that lands here.
+        let in_range: Vec<(usize, usize)> = ppu.memory.oam.iter().enumerate()
+            .filter_map(|(i, sprite)| {
+                // Y values of $EF and above place the sprite entirely off-screen.
+                if sprite.y >= 0xEF {
+                    return None
+                }
 
-        for i in (0 .. 64).rev() {
-            let sprite = ppu.memory.oam[i];
+                // Sprites are delayed by one scanline.
+                let offset_y = y.checked_sub(sprite.y as usize + 1)?;
 
-            // Sprites are delayed by one scanline.
-            let sprite_y = sprite.y as usize + 1;
+                (offset_y < sprite_height).then_some((i, offset_y))
+            })
+            .collect();
 
-            if !(sprite_y <= y && y < sprite_y + sprite_height) {
-                continue
-            }
+        // The PPU only has room for eight sprites per scanline.
+        if in_range.len() > 8 {
+            ppu.registers.status.sprite_overflow = true;
+        }
+
+        let limit = if self.unlimited_sprites { in_range.len() } else { 8 };
+
+        // Back to front, lower OAM indices are drawn on top.
+        for &(i, offset_y) in in_range.iter().take(limit).rev() {
+            let sprite = ppu.memory.oam[i];
 
             let behind_background = sprite.mask & 0b00100000 != 0;
 
             let flip_x = sprite.mask & 0b01000000 != 0;
             let flip_y = sprite.mask & 0b10000000 != 0;
 
-            let offset_y = y - sprite_y;
-
             let palette_index = sprite.mask & 0b11;
             let palette = ppu.memory.palette.sprite[palette_index as usize];
 
@@ -183,16 +206,30 @@ impl SoftwareRenderer {
                     break
                 }
 
+                // Hidden in the left column, the PPU doesn't draw or hit test there.
+                if write_x < 8 && !ppu.registers.mask.show_sprites_leftmost {
+                    continue
+                }
+
                 let sprite_offset_x = if flip_x { sprite_width - 1 - offset_x } else { offset_x };
                 let sprite_offset_y = if flip_y { sprite_height - 1 - offset_y } else { offset_y };
 
+                // 8x16 sprites pick their pattern table with bit 0, the bottom half is the next tile.
+                let tile = if tall {
+                    let table = (sprite.number as usize & 1) * 256;
+
+                    table + (sprite.number as usize & !1) + sprite_offset_y / 8
+                } else {
+                    sprite.number as usize
+                };
+
                 let color = self.render_sprite(
-                    ppu, sprite.number as usize, sprite_offset_x, sprite_offset_y, palette
+                    ppu, tile, sprite_offset_x, sprite_offset_y % 8, palette
                 );
 
                 if let Some(color) = color {
                     if i == 0 {
-                        ppu.registers.status.sprite_hit = true;
+                        result.sprite_zero[write_x] = true;
                     }
 
                     if behind_background {
@@ -208,63 +245,112 @@ impl SoftwareRenderer {
     }
 
     fn render_pixel(&mut self, ppu: &mut Ppu, x: usize, y: usize) -> Color {
-        let foreground_pixel = self.pre_rendered_sprites.as_ref()
-            .and_then(|pixels| pixels.foreground[x]);
+        let (foreground, behind, sprite_zero) = self.pre_rendered_sprites.as_ref()
+            .map(|pixels| (pixels.foreground[x], pixels.background[x], pixels.sprite_zero[x]))
+            .unwrap_or_default();
 
-        if let Some(color) = foreground_pixel {
+        let (foreground, behind) = if self.layers.sprites { (foreground, behind) } else { (None, None) };
+
+        // The background is still needed under a sprite 0 pixel to test for a hit.
+        if let (Some(color), false) = (foreground, sprite_zero) {
             return color
         }
 
-        let mut offset_x = x + (ppu.registers.render.x_scroll() as usize);
-        let mut offset_y = y + (ppu.registers.render.y_scroll() as usize);
+        let mask = &ppu.registers.mask;
+
+        let show_background = mask.show_background && (x >= 8 || mask.show_background_leftmost);
 
-        let mut name_table = ppu.registers.render.name_table_x() != ppu.registers.render.name_table_y();
+        let background = if show_background && (self.layers.background || sprite_zero) {
+            let (name_table, offset_x, offset_y) = ppu.scrolled_position_at(self.line_scroll, x, y);
 
-        if offset_x >= 256 {
-            offset_x -= 256;
+            self.render_background(ppu, name_table, offset_x, offset_y)
+        } else {
+            None
+        };
 
-            name_table = !name_table;
+        // Opaque sprite 0 over opaque background, the PPU never reports it in the last column.
+        if sprite_zero && background.is_some() && x != NES_WIDTH - 1 {
+            ppu.registers.status.sprite_hit = true;
         }
 
-        if offset_y >= 240 {
-            offset_y -= 240;
+        let background = background.filter(|_| self.layers.background);
 
-            name_table = !name_table;
+        foreground
+            .or(background)
+            .or(behind)
+            .unwrap_or_else(|| self.color(&ppu.registers.mask, ppu.memory.palette.background_solid))
+    }
+
+    // Nothing but the backdrop while rendering is off, one fill covers the rest of the row.
+    fn render_blank(&mut self, ppu: &Ppu) {
+        if !(1 ..= 256).contains(&self.scan_x) || self.blank_row {
+            return
         }
 
-        let name_table = if name_table { 1 } else { 0 };
+        let color = self.color(&ppu.registers.mask, ppu.memory.palette.background_solid);
 
-        let background = if ppu.registers.mask.show_background {
-            self.render_background(ppu, name_table, offset_x, offset_y)
-        } else {
-            None
-        };
+        let x = self.scan_x - 1;
+        let y = self.scan_y;
 
-        background
-            .or_else(|| {
-                self.pre_rendered_sprites.as_ref()
-                    .and_then(|pixels| pixels.background[x])
-            })
-            .unwrap_or_else(|| NES_PALETTE[ppu.memory.palette.background_solid as usize])
+        let row = &mut self.frame.frame[(x + y * NES_WIDTH) * 4 .. (y + 1) * NES_WIDTH * 4];
+
+        for pixel in row.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+
+        self.blank_row = true;
+    }
+
+    // Steps the CPU until the renderer moves onto the next scanline.
+    // Any frame completed on the way is returned, the caller still raises the NMI.
+    pub fn step_scanline<C1: Controller, C2: Controller>(&mut self, cpu: &mut Cpu<C1, C2>) -> Result<RenderAction, CpuError> {
+        let scan_y = self.scan_y;
+        let mut result = RenderAction::None;
+
+        while self.scan_y == scan_y {
+            cpu.step()?;
+
+            let action = self.render(&mut cpu.memory.ppu, cpu.memory.cycles);
+
+            if !matches!(action, RenderAction::None) {
+                result = action;
+            }
+        }
+
+        Ok(result)
     }
 
     pub fn new() -> SoftwareRenderer {
         SoftwareRenderer::default()
     }
+
+    pub fn with_palette(palette: [Color; 0x40]) -> SoftwareRenderer {
+        SoftwareRenderer { palette, ..SoftwareRenderer::default() }
+    }
 }
 
 impl Renderer for SoftwareRenderer {
     fn render(&mut self, ppu: &mut Ppu, cycle: u64) -> RenderAction {
-        let diff = (cycle - self.last_cycle) * 3;
+        let start = self.last_cycle * 3;
         self.last_cycle = cycle;
 
         let mut has_v_blank = false;
 
-        for _ in 0..diff {
+        for dot in start .. cycle * 3 {
+            let rendering = ppu.registers.mask.show_background || ppu.registers.mask.show_sprites;
+
             match self.scan_y {
+                0 ..= 239 if !rendering => self.render_blank(ppu),
                 0 ..= 239 => {
-                    if self.scan_x == 0 && ppu.registers.mask.show_sprites {
-                        self.pre_rendered_sprites = Some(self.pre_render_sprites(ppu, self.scan_y));
+                    if self.scan_x == 0 {
+                        self.line_scroll = ppu.registers.render.line_scroll();
+
+                        // Dropped with sprites off, so the last line's sprites don't linger.
+                        self.pre_rendered_sprites = if ppu.registers.mask.show_sprites {
+                            Some(self.pre_render_sprites(ppu, self.scan_y))
+                        } else {
+                            None
+                        };
                     }
 
                     if (1 ..= 256).contains(&self.scan_x) {
@@ -275,26 +361,39 @@ impl Renderer for SoftwareRenderer {
                         let address = (x + self.scan_y * NES_WIDTH) * 4;
 
                         self.frame.frame[address .. address + 4].copy_from_slice(&pixel);
+
+                        self.blank_row = false;
                     }
                 }
-                241 => {
-                    if self.scan_x == 1 {
+                241 if self.scan_x == 1 => {
+                    // Reading $2002 on the same CPU cycle the flag is raised
+                    // reads it as clear and suppresses the NMI for this frame.
+                    let cpu_cycle = dot / 3 + 1;
+
+                    if ppu.registers.status_read_cycle != Some(cpu_cycle) {
+                        ppu.registers.status.v_blank_hit = true;
+
                         has_v_blank = true;
                     }
                 }
-                261 => {
-                    if self.scan_x == 1 {
-                        ppu.registers.status.sprite_hit = false;
-                    }
+                261 if self.scan_x == 1 => {
+                    ppu.registers.status.sprite_overflow = false;
+                    ppu.registers.status.sprite_hit = false;
+                    ppu.registers.status.v_blank_hit = false;
                 }
                 _ => { /* idle */ }
             }
 
+            if self.scan_x == 260 && (self.scan_y < 240 || self.scan_y == 261) && rendering {
+                ppu.memory.mapper.on_scanline();
+            }
+
             self.scan_x += 1;
 
             if self.scan_x >= NES_SCANLINE_WIDTH {
                 self.scan_x = 0;
                 self.scan_y += 1;
+                self.blank_row = false;
 
                 if self.scan_y >= NES_SCANLINE_COUNT {
                     self.scan_y = 0;
@@ -303,9 +402,562 @@ impl Renderer for SoftwareRenderer {
         }
 
         if has_v_blank && ppu.registers.control.gen_nmi {
-            RenderAction::SendFrame(std::mem::take(&mut self.frame))
+            let frame = std::mem::take(&mut self.frame);
+
+            if ppu.suppress_nmi {
+                RenderAction::SuppressedFrame(frame)
+            } else {
+                RenderAction::SendFrame(frame)
+            }
         } else {
             RenderAction::None
         }
     }
+
+    fn reset(&mut self) {
+        self.scan_x = 0;
+        self.scan_y = 0;
+        self.last_cycle = 0;
+        self.line_scroll = LineScroll::default();
+        self.blank_row = false;
+        self.pre_rendered_sprites = None;
+        *self.frame = RenderedFrame::default();
+    }
+
+    fn scan_position(&self) -> ScanPosition {
+        ScanPosition { x: self.scan_x, y: self.scan_y, cycle: self.last_cycle }
+    }
+
+    // The partially drawn frame is lost, the rows above position stay blank until the next frame.
+    fn resume(&mut self, position: ScanPosition) {
+        self.reset();
+
+        self.scan_x = position.x.min(NES_SCANLINE_WIDTH - 1);
+        self.scan_y = position.y.min(NES_SCANLINE_COUNT - 1);
+        self.last_cycle = position.cycle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::nes::Nes;
+    use crate::renderer::NES_HEIGHT;
+    use crate::rom::{Mirroring, Rom};
+    use crate::state::CpuState;
+    use super::*;
+
+    // NROM with tile 1 opaque (color 1) in both pattern tables.
+    fn solid_tile_rom() -> Rom {
+        let mut rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+
+        for table in [0, 0x1000] {
+            rom.chr_rom[table + 16 .. table + 24].fill(0xFF);
+        }
+
+        rom
+    }
+
+    // OAM with sprite 0 set to bytes and every other sprite hidden below the screen.
+    fn oam_with_sprite_zero(bytes: [u8; 4]) -> [u8; 256] {
+        let mut oam = [0xFF; 256];
+
+        oam[.. 4].copy_from_slice(&bytes);
+
+        oam
+    }
+
+    // Renders one CPU cycle (three dots) at a time until done returns true.
+    fn render_until(renderer: &mut SoftwareRenderer, ppu: &mut Ppu, cycle: &mut u64, done: impl Fn(&SoftwareRenderer, &Ppu) -> bool) {
+        let end = *cycle + NES_FRAME_CYCLES * 2;
+
+        while !done(renderer, ppu) {
+            assert!(*cycle < end, "condition not reached within two frames");
+
+            *cycle += 1;
+
+            renderer.render(ppu, *cycle);
+        }
+    }
+
+    // Background tile 1 covers x 40..48, y 32..40. Sprite 0 (tile 1) covers x 44..52 from line 30.
+    fn sprite_zero_scene(rom: &Rom) -> Ppu<'_> {
+        let mut ppu = Ppu::new(rom);
+
+        ppu.memory.names[0].contents[4 * 32 + 5] = 1;
+        ppu.replace_oam(oam_with_sprite_zero([29, 1, 0, 44]));
+
+        ppu.registers.mask.show_background = true;
+        ppu.registers.mask.show_sprites = true;
+
+        ppu
+    }
+
+    // Scanlines where pre_render_sprites draws something at x.
+    fn sprite_rows(renderer: &mut SoftwareRenderer, ppu: &mut Ppu, x: usize) -> Vec<usize> {
+        (0 .. NES_HEIGHT)
+            .filter(|&y| renderer.pre_render_sprites(ppu, y).foreground[x].is_some())
+            .collect()
+    }
+
+    #[test]
+    fn sprites_at_the_bottom_edge() {
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        ppu.replace_oam(oam_with_sprite_zero([255, 1, 0, 44]));
+
+        assert!(sprite_rows(&mut renderer, &mut ppu, 44).is_empty());
+
+        // Delayed by one line, only the top row of the sprite reaches line 239.
+        ppu.replace_oam(oam_with_sprite_zero([238, 1, 0, 44]));
+
+        assert_eq!(sprite_rows(&mut renderer, &mut ppu, 44), vec![239]);
+    }
+
+    #[test]
+    fn reset_renders_a_clean_frame() {
+        let rom = solid_tile_rom();
+        let mut ppu = sprite_zero_scene(&rom);
+        let mut renderer = SoftwareRenderer::new();
+        let mut cycle = 0;
+
+        // Partway through a frame, past sprite 0.
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 100);
+
+        renderer.reset();
+
+        assert_eq!(renderer.scan_position(), ScanPosition::default());
+
+        let mut fresh = SoftwareRenderer::new();
+
+        for (renderer, mut cycle) in [(&mut renderer, 0), (&mut fresh, 0)] {
+            render_until(renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 241);
+        }
+
+        assert!(renderer.frame.frame == fresh.frame.frame, "frame after reset differs");
+    }
+
+    #[test]
+    fn step_scanline_covers_a_frame() {
+        let rom = solid_tile_rom();
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+        let mut renderer = SoftwareRenderer::new();
+
+        for line in 1 ..= NES_SCANLINE_COUNT {
+            renderer.step_scanline(&mut cpu).unwrap();
+
+            assert_eq!(renderer.scan_y, line % NES_SCANLINE_COUNT);
+        }
+
+        // Each call overshoots by less than one instruction (a 3 cycle JMP).
+        let frame_cycles = (NES_SCANLINE_WIDTH * NES_SCANLINE_COUNT / 3) as u64;
+
+        assert!((frame_cycles .. frame_cycles + 3).contains(&cpu.memory.cycles), "{} cycles", cpu.memory.cycles);
+    }
+
+    #[test]
+    fn blank_fast_path_matches_pixels() {
+        let rom = solid_tile_rom();
+        let mut ppu = sprite_zero_scene(&rom);
+        let mut renderer = SoftwareRenderer::new();
+        let mut cycle = 0;
+
+        ppu.registers.mask.show_background = false;
+        ppu.registers.mask.show_sprites = false;
+        ppu.memory.palette.set(0x00, 0x21).unwrap();
+        ppu.registers.status.v_blank_hit = false;
+
+        // Timing still runs, v-blank arrives on time.
+        render_until(&mut renderer, &mut ppu, &mut cycle, |_, ppu| ppu.registers.status.v_blank_hit);
+
+        assert_eq!(cycle, V_BLANK_CYCLE);
+
+        let backdrop = renderer.palette[0x21];
+
+        let frame = renderer.frame.frame.to_vec();
+
+        for (index, pixel) in frame.chunks_exact(4).enumerate() {
+            let (x, y) = (index % NES_WIDTH, index / NES_WIDTH);
+
+            assert_eq!(pixel, backdrop);
+            assert_eq!(renderer.render_pixel(&mut ppu, x, y), backdrop, "slow path at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn sprite_zero_hit_position() {
+        let rom = solid_tile_rom();
+        let mut ppu = sprite_zero_scene(&rom);
+        let mut renderer = SoftwareRenderer::new();
+        let mut cycle = 0;
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |_, ppu| ppu.registers.status.sprite_hit);
+
+        // Pixel 44 is drawn at dot 45, and the cycle that drew it may run up to two dots further.
+        assert_eq!(renderer.scan_y, 32);
+        assert!((46 ..= 48).contains(&renderer.scan_x), "hit reported at dot {}", renderer.scan_x);
+    }
+
+    #[test]
+    fn sprites_disabled_mid_frame() {
+        let rom = solid_tile_rom();
+        let mut ppu = sprite_zero_scene(&rom);
+        let mut renderer = SoftwareRenderer::new();
+        let mut cycle = 0;
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 31);
+
+        ppu.registers.mask.show_sprites = false;
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 40);
+
+        assert!(!ppu.registers.status.sprite_hit);
+
+        // Background tile only, no sprite pixels past x 48.
+        let backdrop = &renderer.frame.frame[(36 * NES_WIDTH + 50) * 4 ..][.. 4];
+        let tile = &renderer.frame.frame[(36 * NES_WIDTH + 44) * 4 ..][.. 4];
+
+        assert_ne!(backdrop, tile);
+        assert_eq!(backdrop, &renderer.frame.frame[(36 * NES_WIDTH + 100) * 4 ..][.. 4]);
+    }
+
+    // CPU cycle whose dots include the one that raises v-blank (line 241, dot 1).
+    const V_BLANK_CYCLE: u64 = (241 * NES_SCANLINE_WIDTH as u64 + 1) / 3 + 1;
+
+    // Reads $2002 at read_cycle, just around the v-blank flag being raised, then renders past it.
+    fn read_status_near_v_blank(read_cycle: u64) -> (u8, RenderAction, bool) {
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        ppu.registers.status.v_blank_hit = false;
+        ppu.registers.control.gen_nmi = true;
+
+        assert!(matches!(renderer.render(&mut ppu, read_cycle - 1), RenderAction::None));
+
+        let status = ppu.read_status(read_cycle);
+        let action = renderer.render(&mut ppu, V_BLANK_CYCLE + 1);
+
+        (status, action, ppu.registers.status.v_blank_hit)
+    }
+
+    #[test]
+    fn status_read_races_v_blank() {
+        // A cycle early, the flag is still clear but is raised right after and the NMI fires.
+        let (status, action, v_blank) = read_status_near_v_blank(V_BLANK_CYCLE - 1);
+
+        assert_eq!(status & 0x80, 0);
+        assert!(matches!(action, RenderAction::SendFrame(_)));
+        assert!(v_blank);
+
+        // On the same cycle, the flag reads clear and stays clear, and no NMI is sent.
+        let (status, action, v_blank) = read_status_near_v_blank(V_BLANK_CYCLE);
+
+        assert_eq!(status & 0x80, 0);
+        assert!(matches!(action, RenderAction::None));
+        assert!(!v_blank);
+
+        // A cycle late, the read sees the flag and clears it, the NMI was already sent.
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        ppu.registers.status.v_blank_hit = false;
+        ppu.registers.control.gen_nmi = true;
+
+        assert!(matches!(renderer.render(&mut ppu, V_BLANK_CYCLE), RenderAction::SendFrame(_)));
+        assert_eq!(ppu.read_status(V_BLANK_CYCLE + 1) & 0x80, 0x80);
+        assert!(!ppu.registers.status.v_blank_hit);
+    }
+
+    // Sets two sprite colors and turns on 8x16 sprites, then moves sprite 0 (tiles 2 and 3) each vblank with OAM DMA.
+    const TALL_SPRITE_PROGRAM: [u8; 62] = [
+        0xA9, 0x3F,             // LDA #$3F
+        0x8D, 0x06, 0x20,       // STA $2006
+        0xA9, 0x11,             // LDA #$11
+        0x8D, 0x06, 0x20,       // STA $2006
+        0xA9, 0x16,             // LDA #$16
+        0x8D, 0x07, 0x20,       // STA $2007
+        0xA9, 0x2A,             // LDA #$2A
+        0x8D, 0x07, 0x20,       // STA $2007
+        0xA9, 0x00,             // LDA #$00
+        0x8D, 0x06, 0x20,       // STA $2006
+        0x8D, 0x06, 0x20,       // STA $2006
+        0xA9, 0x03,             // LDA #$03
+        0x8D, 0x01, 0x02,       // STA $0201
+        0xA9, 0xA0,             // LDA #$A0
+        0x8D, 0x00, 0x20,       // STA $2000
+        0xA9, 0x1E,             // LDA #$1E
+        0x8D, 0x01, 0x20,       // STA $2001
+        0x2C, 0x02, 0x20,       // loop: BIT $2002
+        0x10, 0xFB,             // BPL loop
+        0xEE, 0x00, 0x02,       // INC $0200
+        0xEE, 0x03, 0x02,       // INC $0203
+        0xA9, 0x02,             // LDA #$02
+        0x8D, 0x14, 0x40,       // STA $4014
+        0x4C, 0x2B, 0x80,       // JMP loop
+    ];
+
+    // Tile 2 is color 1 and tile 3 is color 2, both in the pattern table at $1000.
+    fn tall_sprite_rom() -> Rom {
+        let mut rom = Rom::from_prg(&TALL_SPRITE_PROGRAM, 0x8000);
+
+        rom.chr_rom[0x1020 .. 0x1028].fill(0xFF);
+        rom.chr_rom[0x1038 .. 0x1040].fill(0xFF);
+
+        rom
+    }
+
+    #[test]
+    fn tall_sprites_restore_mid_frame() {
+        let rom = tall_sprite_rom();
+        let mut nes = Nes::new(&rom, (NoController, NoController));
+
+        // The program sets up the PPU straight away.
+        nes.cpu.memory.ppu.warmup = false;
+
+        for _ in 0 .. 5 {
+            nes.step_frame().unwrap();
+        }
+
+        // Near the top of the next frame, above the sprite.
+        while nes.renderer.scan_y != 3 {
+            nes.cpu.step().unwrap();
+
+            let action = nes.renderer.render(&mut nes.cpu.memory.ppu, nes.cpu.memory.cycles);
+
+            assert!(matches!(action, RenderAction::None));
+        }
+
+        let state = CpuState::from(&nes.cpu).with_renderer(&nes.renderer);
+
+        let expected: Vec<Vec<u8>> = (0 .. 5)
+            .map(|_| nes.step_frame().unwrap().frame.to_vec())
+            .collect();
+
+        // Both halves of the sprite made it into every frame.
+        for color in [0x16, 0x2A].map(|index| nes.renderer.palette[index]) {
+            assert!(expected.iter().all(|frame| frame.chunks_exact(4).any(|pixel| pixel == &color[..])));
+        }
+
+        let scan = state.scan;
+
+        let mut restored = Nes::new(&rom, (NoController, NoController));
+
+        restored.cpu = state.restore(&rom, (NoController, NoController)).unwrap();
+        restored.renderer.resume(scan);
+
+        for (index, expected) in expected.iter().enumerate() {
+            let frame = restored.step_frame().unwrap();
+
+            // Rows drawn before the save are lost on restore.
+            let start = if index == 0 { (scan.y + 1) * NES_WIDTH * 4 } else { 0 };
+
+            assert!(frame.frame[start ..] == expected[start ..], "frame {index} differs after restoring");
+        }
+    }
+
+    // OAM with count sprites of tile 1 on lines 51..59, 16 pixels apart from x 16.
+    fn sprites_on_one_line(count: usize) -> [u8; 256] {
+        let mut oam = [0xFF; 256];
+
+        for (i, sprite) in oam.chunks_exact_mut(4).take(count).enumerate() {
+            sprite.copy_from_slice(&[50, 1, 0, 16 + i as u8 * 16]);
+        }
+
+        oam
+    }
+
+    #[test]
+    fn nine_sprites_overflow() {
+        let rom = solid_tile_rom();
+        let mut renderer = SoftwareRenderer::new();
+
+        let mut ppu = Ppu::new(&rom);
+
+        ppu.replace_oam(sprites_on_one_line(8));
+        renderer.pre_render_sprites(&mut ppu, 54);
+
+        assert!(!ppu.registers.status.sprite_overflow);
+
+        let mut ppu = Ppu::new(&rom);
+        let mut cycle = 0;
+
+        ppu.replace_oam(sprites_on_one_line(9));
+        ppu.registers.mask.show_sprites = true;
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 50);
+
+        assert!(!ppu.registers.status.sprite_overflow);
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 52);
+
+        assert!(ppu.registers.status.sprite_overflow);
+        assert_eq!(ppu.registers.status.bits() & 0b00100000, 0b00100000);
+
+        // Set until the pre-render line.
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 260);
+
+        assert!(ppu.registers.status.sprite_overflow);
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 261 && renderer.scan_x > 1);
+
+        assert!(!ppu.registers.status.sprite_overflow);
+    }
+
+    #[test]
+    fn ninth_sprite_is_not_drawn() {
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        ppu.replace_oam(sprites_on_one_line(9));
+
+        // The ninth sprite covers x 144..152.
+        let drawn = |renderer: &mut SoftwareRenderer, ppu: &mut Ppu, x: usize| {
+            renderer.pre_render_sprites(ppu, 54).foreground[x].is_some()
+        };
+
+        assert!(drawn(&mut renderer, &mut ppu, 16 + 7 * 16));
+        assert!(!drawn(&mut renderer, &mut ppu, 16 + 8 * 16));
+
+        renderer.unlimited_sprites = true;
+
+        assert!(drawn(&mut renderer, &mut ppu, 16 + 8 * 16));
+    }
+
+    #[test]
+    fn greyscale_desaturates() {
+        let rom = solid_tile_rom();
+        let mut ppu = sprite_zero_scene(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        // Red background tile, left of the sprite.
+        ppu.memory.write(0x3F01, 0x16).unwrap();
+
+        let color = renderer.render_pixel(&mut ppu, 41, 36);
+
+        assert_eq!(color, NES_PALETTE[0x16]);
+        assert!(color[0] > color[1] && color[0] > color[2]);
+
+        ppu.registers.mask.greyscale = true;
+
+        let [r, g, b, _] = renderer.render_pixel(&mut ppu, 41, 36);
+
+        assert_eq!([r, g, b], [170, 170, 170]);
+    }
+
+    #[test]
+    fn left_column_clipping() {
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        // Background tile 1 at x 0..8, y 32..40 and a sprite at x 0..8, y 50..58.
+        ppu.memory.names[0].contents[4 * 32] = 1;
+        ppu.replace_oam(oam_with_sprite_zero([49, 1, 0, 0]));
+
+        ppu.memory.write(0x3F00, 0x0F).unwrap();
+        ppu.memory.write(0x3F01, 0x16).unwrap();
+        ppu.memory.write(0x3F11, 0x30).unwrap();
+
+        ppu.registers.mask.show_background = true;
+        ppu.registers.mask.show_sprites = true;
+
+        let mut column_0 = |ppu: &mut Ppu, background: bool, sprites: bool| {
+            ppu.registers.mask.show_background_leftmost = background;
+            ppu.registers.mask.show_sprites_leftmost = sprites;
+
+            [36, 54].map(|y| {
+                renderer.pre_rendered_sprites = Some(renderer.pre_render_sprites(ppu, y));
+
+                renderer.render_pixel(ppu, 0, y)
+            })
+        };
+
+        let [black, red, white] = [0x0F, 0x16, 0x30].map(|index| NES_PALETTE[index]);
+
+        assert_eq!(column_0(&mut ppu, false, false), [black, black]);
+        assert_eq!(column_0(&mut ppu, true, false), [red, black]);
+        assert_eq!(column_0(&mut ppu, false, true), [black, white]);
+        assert_eq!(column_0(&mut ppu, true, true), [red, white]);
+    }
+
+    #[test]
+    fn scroll_shifts_the_background() {
+        let rom = solid_tile_rom();
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+
+        // Tile 1 at x 40..48, y 32..40 in the top table.
+        ppu.memory.names[0].contents[4 * 32 + 5] = 1;
+        ppu.memory.write(0x3F00, 0x0F).unwrap();
+        ppu.memory.write(0x3F01, 0x16).unwrap();
+        ppu.registers.mask.show_background = true;
+
+        let [black, red] = [0x0F, 0x16].map(|index| NES_PALETTE[index]);
+
+        let mut sample = |ppu: &mut Ppu, pixels: [(usize, usize); 4]| {
+            renderer.line_scroll = ppu.registers.render.line_scroll();
+
+            pixels.map(|(x, y)| renderer.render_pixel(ppu, x, y))
+        };
+
+        let edges = [(40, 32), (39, 32), (47, 39), (48, 39)];
+
+        assert_eq!(sample(&mut ppu, edges), [red, black, red, black]);
+
+        // X 3 (all fine) and Y 8, the tile moves to x 37..45, y 24..32.
+        ppu.registers.render.write_scroll(3);
+        ppu.registers.render.write_scroll(8);
+
+        assert_eq!(sample(&mut ppu, edges), [black; 4]);
+        assert_eq!(sample(&mut ppu, [(37, 24), (36, 24), (44, 31), (45, 31)]), [red, black, red, black]);
+
+        // $0800 through PPUADDR selects the bottom table, the second one with horizontal mirroring.
+        // Coarse scroll is cleared but PPUADDR leaves fine X at 3.
+        ppu.memory.names[1].contents[4 * 32 + 5] = 1;
+        ppu.memory.names[0].contents[4 * 32 + 5] = 0;
+
+        ppu.registers.render.write_address(0x08);
+        ppu.registers.render.write_address(0x00);
+
+        assert_eq!(sample(&mut ppu, [(37, 32), (36, 32), (44, 39), (45, 39)]), [red, black, red, black]);
+    }
+
+    #[test]
+    fn scroll_split_at_line_32() {
+        let mut rom = solid_tile_rom();
+
+        rom.flags.mirroring = Mirroring::Vertical;
+
+        let mut ppu = Ppu::new(&rom);
+        let mut renderer = SoftwareRenderer::new();
+        let mut cycle = 0;
+
+        // Left table empty, right table filled with tile 1.
+        ppu.memory.names[1].contents[.. 960].fill(1);
+        ppu.memory.write(0x3F00, 0x0F).unwrap();
+        ppu.memory.write(0x3F01, 0x16).unwrap();
+        ppu.registers.mask.show_background = true;
+        ppu.registers.mask.show_background_leftmost = true;
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 31 && renderer.scan_x > 300);
+
+        // Switch to the right table between lines 31 and 32, like a status bar split.
+        ppu.registers.render.write_control(1);
+
+        render_until(&mut renderer, &mut ppu, &mut cycle, |renderer, _| renderer.scan_y == 240);
+
+        let row = |y: usize| renderer.frame.frame[y * NES_WIDTH * 4 .. (y + 1) * NES_WIDTH * 4].to_vec();
+
+        let [black, red] = [0x0F, 0x16].map(|index| NES_PALETTE[index]);
+
+        assert_eq!(row(0), black.repeat(NES_WIDTH));
+        assert_eq!(row(31), black.repeat(NES_WIDTH));
+        assert_eq!(row(32), red.repeat(NES_WIDTH));
+        assert_eq!(row(239), red.repeat(NES_WIDTH));
+    }
 }
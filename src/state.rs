@@ -1,9 +1,141 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::ops::Range;
 use serde_derive::{Deserialize, Serialize};
+use crate::apu::{Apu, Dmc, FrameCounter, Noise, Pulse, Triangle};
 use crate::controller::Controller;
 use crate::cpu::{Cpu, Registers, StatusRegister, Vectors};
-use crate::memory::Memory;
+use crate::genie::GameGenieCode;
+use crate::chr_cache::ChrCache;
+use crate::mapper::create_mapper;
+use crate::memory::{Memory, UnmappedWrites};
 use crate::ppu::{ControlRegister, MaskRegister, StatusRegister as PpuStatusRegister, NameTable, Palette, PaletteMemory, Ppu, PpuMemory, PpuRegisters, Sprite, RenderRegister};
-use crate::rom::Rom;
+use crate::renderer::{Renderer, ScanPosition};
+use crate::rom::{Mirroring, Rom};
+
+// Byte buffers are written as hex strings for human readable formats (JSON),
+// binary formats like postcard are unaffected.
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let text: String = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+
+            serializer.serialize_str(&text)
+        } else {
+            bytes.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Vec::<u8>::deserialize(deserializer)
+        }
+
+        let text = String::deserialize(deserializer)?;
+
+        if text.len() % 2 != 0 || !text.is_ascii() {
+            return Err(D::Error::custom("hex string must be ascii with an even length"))
+        }
+
+        (0 .. text.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&text[i .. i + 2], 16).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+pub const STATE_MAGIC: [u8; 4] = *b"EMST";
+// Bump whenever CpuState or anything inside it changes shape.
+pub const STATE_VERSION: u16 = 4;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(std::io::Error),
+    Decode(postcard::Error),
+    // Not a state at all, or one from before states had a header.
+    BadMagic,
+    Version { expected: u16, found: u16 },
+    // Saved while running a different ROM.
+    RomMismatch { expected: u64, found: u64 },
+    // Decoded, but buffer sizes don't fit the hardware (e.g. RAM that isn't 0x800 bytes).
+    Invalid,
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::Io(error) => Display::fmt(error, f),
+            StateError::Decode(error) => Display::fmt(error, f),
+            StateError::BadMagic => write!(f, "Not a save state, the header does not start with EMST"),
+            StateError::Version { expected, found } =>
+                write!(f, "Save state is version {found}, but this build reads version {expected}"),
+            StateError::RomMismatch { expected, found } =>
+                write!(f, "Save state belongs to ROM {found:016X}, but ROM {expected:016X} is loaded"),
+            StateError::Invalid => write!(f, "Save state has buffers of the wrong size"),
+        }
+    }
+}
+
+impl Error for StateError { }
+
+impl From<std::io::Error> for StateError {
+    fn from(value: std::io::Error) -> StateError {
+        StateError::Io(value)
+    }
+}
+
+impl From<postcard::Error> for StateError {
+    fn from(value: postcard::Error) -> StateError {
+        StateError::Decode(value)
+    }
+}
+
+// First field of CpuState, so postcard writes it ahead of everything else.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StateHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    // See Rom::hash.
+    pub rom_hash: u64,
+}
+
+impl StateHeader {
+    pub fn new(rom: &Rom) -> StateHeader {
+        StateHeader {
+            magic: STATE_MAGIC,
+            version: STATE_VERSION,
+            rom_hash: rom.hash(),
+        }
+    }
+
+    // Magic and version only, the ROM is checked in CpuState::restore.
+    fn check_format(&self) -> Result<(), StateError> {
+        if self.magic != STATE_MAGIC {
+            return Err(StateError::BadMagic)
+        }
+
+        if self.version != STATE_VERSION {
+            return Err(StateError::Version { expected: STATE_VERSION, found: self.version })
+        }
+
+        Ok(())
+    }
+
+    pub fn check(&self, rom: &Rom) -> Result<(), StateError> {
+        self.check_format()?;
+
+        let expected = rom.hash();
+
+        if self.rom_hash != expected {
+            return Err(StateError::RomMismatch { expected, found: self.rom_hash })
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CpuRegisters {
@@ -47,6 +179,7 @@ pub struct PpuStateMaskRegister {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PpuStateStatusRegister {
+    pub sprite_overflow: bool,
     pub sprite_hit: bool,
     pub v_blank_hit: bool,
 }
@@ -71,6 +204,7 @@ pub struct PpuStateRegisters {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PpuStateNameTable {
+    #[serde(with = "hex_bytes")]
     pub contents: Vec<u8>
 }
 
@@ -79,6 +213,7 @@ pub struct PpuStatePaletteMemory {
     pub background_solid: u8,
     pub background: [Palette; 4],
     pub sprite: [Palette; 4],
+    pub unused: [u8; 3],
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -86,20 +221,50 @@ pub struct PpuStateMemory {
     pub oam: Vec<PpuStateSprite>, // size: 256
     pub names: Vec<PpuStateNameTable>,
     pub palette: PpuStatePaletteMemory, // size: 20
+    pub mirroring: Option<Mirroring>,
+    // Empty unless the ROM has no CHR, size: 0x2000
+    #[serde(with = "hex_bytes")]
+    pub chr_ram: Vec<u8>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PpuState {
     pub registers: PpuStateRegisters,
     pub memory: PpuStateMemory,
+    pub suppress_nmi: bool,
+    pub nmi_on_enable: bool,
+    // Raised by a PPUCTRL write during v-blank, not yet serviced by Cpu::step.
+    pub pending_nmi: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApuState {
+    pub pulse: [Pulse; 2],
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    pub frame_counter: FrameCounter,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CpuState {
+    pub header: StateHeader,
+    // Memory::cycles, which the renderer and APU keep time against.
+    pub cycles: u64,
+    // Top of the frame unless captured with with_renderer.
+    pub scan: ScanPosition,
+    #[serde(with = "hex_bytes")]
     pub ram: Vec<u8>, // size: 0x800
+    #[serde(with = "hex_bytes")]
+    pub saved: Vec<u8>, // size: 0x2000, PRG RAM at 0x6000
     pub controller_cycles: (u64, u64),
     pub registers: CpuRegisters,
-    pub ppu: PpuState
+    pub ppu: PpuState,
+    pub apu: ApuState,
+    // Opaque, from Mapper::save_state.
+    #[serde(with = "hex_bytes")]
+    pub mapper: Vec<u8>,
+    pub game_genie: Vec<GameGenieCode>,
 }
 
 impl From<&Registers> for CpuRegisters {
@@ -187,6 +352,7 @@ impl From<&MaskRegister> for PpuStateMaskRegister {
 impl From<&PpuStatusRegister> for PpuStateStatusRegister {
     fn from(value: &PpuStatusRegister) -> Self {
         PpuStateStatusRegister {
+            sprite_overflow: value.sprite_overflow,
             sprite_hit: value.sprite_hit,
             v_blank_hit: value.v_blank_hit,
         }
@@ -197,6 +363,7 @@ impl From<&PpuStatusRegister> for PpuStateStatusRegister {
 impl From<&PpuStateStatusRegister> for PpuStatusRegister {
     fn from(value: &PpuStateStatusRegister) -> Self {
         PpuStatusRegister {
+            sprite_overflow: value.sprite_overflow,
             sprite_hit: value.sprite_hit,
             v_blank_hit: value.v_blank_hit,
         }
@@ -246,6 +413,7 @@ impl From<&PpuStateRegisters> for PpuRegisters {
             status: (&value.status).into(),
             render: (&value.render).into(),
             oam_address: value.oam_address,
+            status_read_cycle: None,
             read_buffer: value.read_buffer,
         }
     }
@@ -279,6 +447,7 @@ impl From<&PpuStatePaletteMemory> for PaletteMemory {
             background_solid: value.background_solid,
             background: value.background,
             sprite: value.sprite,
+            unused: value.unused,
         }
     }
 }
@@ -289,12 +458,13 @@ impl From<&PaletteMemory> for PpuStatePaletteMemory {
             background_solid: value.background_solid,
             background: value.background,
             sprite: value.sprite,
+            unused: value.unused,
         }
     }
 }
 
 impl PpuStateMemory {
-    pub fn restore(self, rom: &Rom) -> Option<PpuMemory> {
+    pub fn restore(self, rom: &Rom) -> Option<PpuMemory<'_>> {
         Some(PpuMemory {
             rom,
             oam: self.oam.iter().map(Sprite::from)
@@ -303,6 +473,14 @@ impl PpuStateMemory {
                 x.contents.try_into().ok().map(|contents| NameTable { contents })
             }).collect::<Option<Vec<NameTable>>>()?.try_into().ok()?,
             palette: (&self.palette).into(),
+            mirroring: self.mirroring,
+            chr_cache: ChrCache::default(),
+            mapper: create_mapper(rom),
+            chr_ram: if rom.chr_rom.is_empty() {
+                Some(Box::new(self.chr_ram.try_into().ok()?))
+            } else {
+                None
+            },
         })
     }
 }
@@ -317,6 +495,8 @@ impl<'a> From<&PpuMemory<'a>> for PpuStateMemory {
                 .map(|x| PpuStateNameTable { contents: x.contents.to_vec() })
                 .collect(),
             palette: (&value.palette).into(),
+            mirroring: value.mirroring,
+            chr_ram: value.chr_ram.as_ref().map(|chr_ram| chr_ram.to_vec()).unwrap_or_default(),
         }
     }
 }
@@ -324,36 +504,548 @@ impl<'a> From<&PpuMemory<'a>> for PpuStateMemory {
 impl<'a, C1: Controller, C2: Controller> From<&Cpu<'a, C1, C2>> for CpuState {
     fn from(value: &Cpu<C1, C2>) -> CpuState {
         CpuState {
+            header: StateHeader::new(value.memory.rom),
+            cycles: value.memory.cycles,
+            scan: ScanPosition { cycle: value.memory.cycles, ..ScanPosition::default() },
             ram: value.memory.ram.to_vec(),
+            saved: value.memory.saved.to_vec(),
             controller_cycles: value.memory.controller_cycles,
             registers: (&value.registers).into(),
             ppu: PpuState {
                 registers: (&value.memory.ppu.registers).into(),
                 memory: (&value.memory.ppu.memory).into(),
+                suppress_nmi: value.memory.ppu.suppress_nmi,
+                nmi_on_enable: value.memory.ppu.nmi_on_enable,
+                pending_nmi: value.memory.ppu.pending_nmi,
             },
+            apu: (&value.memory.apu).into(),
+            mapper: value.memory.ppu.memory.mapper.save_state(),
+            game_genie: value.memory.game_genie.clone(),
+        }
+    }
+}
+
+impl From<&Apu> for ApuState {
+    fn from(value: &Apu) -> ApuState {
+        ApuState {
+            pulse: value.pulse.clone(),
+            triangle: value.triangle.clone(),
+            noise: value.noise.clone(),
+            dmc: value.dmc.clone(),
+            frame_counter: value.frame_counter.clone(),
         }
     }
 }
 
+impl ApuState {
+    // Sample collection settings and channel muting start over at their defaults.
+    pub fn restore(self) -> Apu {
+        let mut apu = Apu::default();
+
+        apu.pulse = self.pulse;
+        apu.triangle = self.triangle;
+        apu.noise = self.noise;
+        apu.dmc = self.dmc;
+        apu.frame_counter = self.frame_counter;
+
+        apu
+    }
+}
+
 impl CpuState {
-    pub fn restore<C1: Controller, C2: Controller>(self, rom: &Rom, controllers: (C1, C2)) -> Option<Cpu<C1, C2>> {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> serde_json::Result<CpuState> {
+        serde_json::from_str(text)
+    }
+
+    // Keeps the renderer's position, restore it with Renderer::resume(state.scan).
+    pub fn with_renderer<R: Renderer>(mut self, renderer: &R) -> CpuState {
+        self.scan = renderer.scan_position();
+
+        self
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, StateError> {
+        Ok(postcard::to_allocvec(self)?)
+    }
+
+    // Reads the header on its own first, so stale states get a clear error instead of a decode failure.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CpuState, StateError> {
+        let (header, _) = postcard::take_from_bytes::<StateHeader>(bytes)
+            .map_err(|_| StateError::BadMagic)?;
+
+        header.check_format()?;
+
+        Ok(postcard::from_bytes(bytes)?)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let bytes = self.to_bytes().map_err(std::io::Error::other)?;
+
+        writer.write_all(&bytes)?;
+
+        writer.flush()
+    }
+
+    // Reads to the end of reader, one state per stream.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<CpuState, StateError> {
+        let mut bytes = vec![];
+
+        reader.read_to_end(&mut bytes)?;
+
+        CpuState::from_bytes(&bytes)
+    }
+
+    pub fn restore<C1: Controller, C2: Controller>(self, rom: &Rom, controllers: (C1, C2)) -> Result<Cpu<'_, C1, C2>, StateError> {
+        self.header.check(rom)?;
+
+        let mut apu = self.apu.restore();
+
+        // Otherwise the APU would replay every cycle since power on.
+        apu.last_cycle = self.cycles;
+
         let mut memory = Memory {
             cycles: 0,
-            ram: self.ram.try_into().ok()?,
+            ram: self.ram.try_into().map_err(|_| StateError::Invalid)?,
             rom,
             ppu: Ppu {
                 registers: (&self.ppu.registers).into(),
-                memory: self.ppu.memory.restore(rom)?,
+                memory: self.ppu.memory.restore(rom).ok_or(StateError::Invalid)?,
+                suppress_nmi: self.ppu.suppress_nmi,
+                nmi_on_enable: self.ppu.nmi_on_enable,
+                pending_nmi: self.ppu.pending_nmi,
+                warmup: false,
             },
-            saved: [0; 0x2000],
+            apu,
+            saved: self.saved.try_into().map_err(|_| StateError::Invalid)?,
             controllers,
             controller_cycles: self.controller_cycles,
+            game_genie: self.game_genie,
+            unmapped_writes: UnmappedWrites::default(),
+            ignored_writes: vec![],
         };
 
-        Some(Cpu {
-            vectors: Vectors::new(&mut memory),
+        memory.ppu.memory.mapper.restore_state(&self.mapper);
+
+        let vectors = Vectors::new(&mut memory);
+
+        // Reading the vectors ticks the clock, so set it afterwards.
+        memory.cycles = self.cycles;
+
+        Ok(Cpu {
+            vectors,
             registers: (&self.registers).into(),
             memory,
+            histogram: None,
+            invalid_op_hook: None,
+            decimal_enabled: false,
         })
     }
 }
+
+// Many CpuStates packed into one buffer, for checkpoint heavy workloads.
+// Serialized as [count: u32][end offset: u32; count][postcard states].
+#[derive(Clone, Default)]
+pub struct StateBatch {
+    data: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+struct Append<'a>(&'a mut Vec<u8>);
+
+impl<'a> Extend<u8> for Append<'a> {
+    fn extend<T: IntoIterator<Item=u8>>(&mut self, iter: T) {
+        self.0.extend(iter)
+    }
+}
+
+impl StateBatch {
+    pub fn capture<C1: Controller, C2: Controller>(&mut self, cpu: &Cpu<C1, C2>) -> Option<usize> {
+        let start = self.data.len();
+        let state = CpuState::from(cpu);
+
+        if postcard::to_extend(&state, Append(&mut self.data)).is_err() {
+            self.data.truncate(start);
+
+            return None
+        }
+
+        self.ends.push(self.data.len());
+
+        Some(self.ends.len() - 1)
+    }
+
+    fn range(&self, index: usize) -> Option<Range<usize>> {
+        let end = *self.ends.get(index)?;
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+
+        Some(start .. end)
+    }
+
+    pub fn get(&self, index: usize) -> Option<CpuState> {
+        postcard::from_bytes(&self.data[self.range(index)?]).ok()
+    }
+
+    pub fn restore<'a, C1: Controller, C2: Controller>(
+        &self, index: usize, rom: &'a Rom, controllers: (C1, C2)
+    ) -> Option<Cpu<'a, C1, C2>> {
+        self.get(index)?.restore(rom, controllers).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(4 + self.ends.len() * 4 + self.data.len());
+
+        result.extend_from_slice(&(self.ends.len() as u32).to_le_bytes());
+
+        for end in &self.ends {
+            result.extend_from_slice(&(*end as u32).to_le_bytes());
+        }
+
+        result.extend_from_slice(&self.data);
+
+        result
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<StateBatch> {
+        let read_u32 = |index: usize| -> Option<usize> {
+            let value = bytes.get(index * 4 .. index * 4 + 4)?;
+
+            Some(u32::from_le_bytes(value.try_into().ok()?) as usize)
+        };
+
+        let count = read_u32(0)?;
+
+        let ends = (0 .. count)
+            .map(|i| read_u32(i + 1))
+            .collect::<Option<Vec<usize>>>()?;
+
+        let data = bytes.get((count + 1) * 4 ..)?.to_vec();
+
+        let ordered = ends.windows(2).all(|x| x[0] <= x[1]);
+
+        if !ordered || ends.last().is_some_and(|end| *end > data.len()) {
+            return None
+        }
+
+        Some(StateBatch { data, ends })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::NoController;
+    use crate::nes::Nes;
+    use super::*;
+
+    fn apu_bytes(apu: &Apu) -> Vec<u8> {
+        postcard::to_allocvec(&ApuState::from(apu)).unwrap()
+    }
+
+    #[test]
+    fn apu_round_trip() {
+        let rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        let writes = [
+            (0x4015, 0x0F),
+            (0x4000, 0b10100001), // duty 2, looping envelope, period 1
+            (0x4001, 0b10011010), // sweep down every other half frame
+            (0x4002, 0x80),
+            (0x4003, 0x09),
+            (0x4008, 0x81),
+            (0x400A, 0x40),
+            (0x400B, 0x10),
+            (0x400C, 0x23),
+            (0x400E, 0x84),
+            (0x400F, 0x18),
+        ];
+
+        for (address, value) in writes {
+            cpu.memory.set(address, value).unwrap();
+        }
+
+        // About 16 quarter frames, the envelopes and sweep are partway through.
+        for _ in 0 .. 40000 {
+            cpu.step().unwrap();
+        }
+
+        let decay = cpu.memory.apu.pulse[0].envelope.decay;
+
+        assert!((1 .. 15).contains(&decay), "decay {decay}");
+        assert_ne!(cpu.memory.apu.pulse[0].timer_period, 0x180);
+
+        let bytes = CpuState::from(&cpu).to_bytes().unwrap();
+
+        let mut restored = CpuState::from_bytes(&bytes).unwrap()
+            .restore(&rom, (NoController, NoController))
+            .unwrap();
+
+        assert_eq!(apu_bytes(&restored.memory.apu), apu_bytes(&cpu.memory.apu));
+
+        for _ in 0 .. 1000 {
+            cpu.step().unwrap();
+            restored.step().unwrap();
+
+            assert_eq!(restored.memory.apu.sample(), cpu.memory.apu.sample());
+        }
+    }
+
+    #[test]
+    fn stale_or_foreign_states_are_rejected() {
+        let rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+        let cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        // A state written by a build with the next version.
+        let mut state = CpuState::from(&cpu);
+
+        state.header.version = STATE_VERSION + 1;
+
+        let bytes = state.to_bytes().unwrap();
+
+        assert!(matches!(
+            CpuState::from_bytes(&bytes),
+            Err(StateError::Version { expected: STATE_VERSION, found }) if found == STATE_VERSION + 1
+        ));
+
+        let mut bytes = CpuState::from(&cpu).to_bytes().unwrap();
+
+        // Decodes, but belongs to a different game.
+        let other = Rom::from_prg(&[0x4C, 0x01, 0x80], 0x8000);
+
+        let state = CpuState::from_bytes(&bytes).unwrap();
+
+        assert!(matches!(
+            state.restore(&other, (NoController, NoController)),
+            Err(StateError::RomMismatch { expected, found }) if expected == other.hash() && found == rom.hash()
+        ));
+
+        bytes[0] ^= 0xFF;
+
+        assert!(matches!(CpuState::from_bytes(&bytes), Err(StateError::BadMagic)));
+    }
+
+    #[test]
+    fn status_round_trip() {
+        let rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+
+        for bits in [0xFF, 0x08, 0xCB, 0x24] {
+            let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+            cpu.registers.p = StatusRegister::from_bits_retain(bits);
+
+            let bytes = CpuState::from(&cpu).to_bytes().unwrap();
+
+            let restored = CpuState::from_bytes(&bytes).unwrap()
+                .restore(&rom, (NoController, NoController))
+                .unwrap();
+
+            assert_eq!(restored.registers.p.bits(), bits);
+        }
+    }
+
+    #[test]
+    fn batch_restores_middle_state() {
+        // INC $10, JMP $8000
+        let rom = Rom::from_prg(&[0xE6, 0x10, 0x4C, 0x00, 0x80], 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        let mut batch = StateBatch::default();
+        let mut expected = vec![];
+
+        for index in 0 .. 3 {
+            for _ in 0 .. 10 {
+                cpu.step().unwrap();
+            }
+
+            expected.push(postcard::to_allocvec(&CpuState::from(&cpu)).unwrap());
+
+            assert_eq!(batch.capture(&cpu), Some(index));
+        }
+
+        let batch = StateBatch::from_bytes(&batch.to_bytes()).unwrap();
+
+        assert_eq!(batch.len(), 3);
+
+        let middle = batch.restore(1, &rom, (NoController, NoController)).unwrap();
+
+        assert_eq!(middle.memory.ram[0x10], 10);
+        assert_eq!(postcard::to_allocvec(&CpuState::from(&middle)).unwrap(), expected[1]);
+        assert!(batch.get(3).is_none());
+    }
+
+    #[test]
+    fn chr_ram_round_trip() {
+        let mut rom = Rom::from_prg(&[0x4C, 0x00, 0x80], 0x8000);
+
+        rom.chr_rom.clear();
+
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        let pattern: Vec<u8> = (0 .. 0x2000).map(|i| (i * 7 % 251) as u8).collect();
+
+        for (address, value) in pattern.iter().enumerate() {
+            cpu.memory.ppu.memory.write(address as u16, *value).unwrap();
+        }
+
+        cpu.memory.set(0x6123, 0x5A).unwrap();
+
+        let bytes = CpuState::from(&cpu).to_bytes().unwrap();
+        let mut restored = CpuState::from_bytes(&bytes).unwrap()
+            .restore(&rom, (NoController, NoController)).unwrap();
+
+        let read: Vec<u8> = (0 .. 0x2000u16)
+            .map(|address| restored.memory.ppu.memory.read(address).unwrap())
+            .collect();
+
+        assert!(read == pattern, "pattern data lost");
+        assert_eq!(restored.memory.pass_get(0x6123).unwrap(), 0x5A);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        // LDA #$42, LDX #$17, LDY #$99, SEC, STA $0123
+        let program = [0xA9, 0x42, 0xA2, 0x17, 0xA0, 0x99, 0x38, 0x8D, 0x23, 0x01];
+
+        let rom = Rom::from_prg(&program, 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        for _ in 0 .. 5 {
+            cpu.step().unwrap();
+        }
+
+        let state = CpuState::from(&cpu);
+        let text = state.to_json().unwrap();
+
+        // RAM is hex encoded.
+        assert!(text.contains("\"ram\": \""));
+
+        let restored = CpuState::from_json(&text).unwrap();
+        let registers = &restored.registers;
+
+        assert_eq!((registers.pc, registers.a, registers.x, registers.y), (0x800A, 0x42, 0x17, 0x99));
+        assert_eq!((registers.p, registers.sp), (cpu.registers.p.bits(), 0xFD));
+        assert_eq!(restored.ram[0x123], 0x42);
+        assert_eq!(postcard::to_allocvec(&restored).unwrap(), postcard::to_allocvec(&state).unwrap());
+    }
+
+    #[test]
+    fn mid_frame_restore_keeps_cycle_accounting() {
+        let mut rom = Rom::from_prg(&[
+            0xA9, 0x80,             // LDA #$80
+            0x8D, 0x00, 0x20,       // STA $2000
+            0x4C, 0x05, 0x80,       // JMP $8005
+            0xE6, 0x10,             // nmi: INC $10
+            0x40,                   // RTI
+        ], 0x8000);
+
+        rom.prg_rom[0x7FFA .. 0x7FFC].copy_from_slice(&0x8008u16.to_le_bytes());
+
+        // Cycles, renderer position and NMI count after each of three frames.
+        let run = |nes: &mut Nes<NoController, NoController>| -> Vec<(u64, ScanPosition, u8)> {
+            (0 .. 3)
+                .map(|_| {
+                    nes.step_frame().unwrap();
+
+                    (nes.cpu.memory.cycles, nes.renderer.scan_position(), nes.cpu.memory.ram[0x10])
+                })
+                .collect()
+        };
+
+        // Part way down the picture, then early in v-blank with an NMI raised by PPUCTRL
+        // but not yet serviced.
+        for (steps, nmi_pending) in [(5000, false), (20, true)] {
+            let mut nes = Nes::new(&rom, (NoController, NoController));
+
+            nes.cpu.memory.ppu.warmup = false;
+
+            nes.step_frame().unwrap();
+
+            for _ in 0 .. steps {
+                nes.cpu.step().unwrap();
+                nes.renderer.render(&mut nes.cpu.memory.ppu, nes.cpu.memory.cycles);
+            }
+
+            if nmi_pending {
+                nes.cpu.memory.ppu.write_ctrl(0x00);
+                nes.cpu.memory.ppu.write_ctrl(0x80);
+            }
+
+            let state = CpuState::from(&nes.cpu).with_renderer(&nes.renderer);
+            let bytes = state.to_bytes().unwrap();
+
+            assert!(state.scan.y > 0 && state.scan.y < 261);
+            assert_eq!(state.ppu.pending_nmi, nmi_pending);
+
+            let expected = run(&mut nes);
+
+            let state = CpuState::from_bytes(&bytes).unwrap();
+            let scan = state.scan;
+
+            nes.cpu = state.restore(&rom, (NoController, NoController)).unwrap();
+            nes.renderer.resume(scan);
+
+            assert_eq!(nes.renderer.scan_position(), scan);
+            assert_eq!(nes.cpu.memory.ppu.pending_nmi, nmi_pending);
+
+            // Serviced on the next step, before the handler could run otherwise.
+            nes.cpu.step().unwrap();
+
+            assert_eq!(nes.cpu.registers.pc == 0x8008, nmi_pending);
+
+            // Back to the saved point for the frame by frame comparison.
+            let state = CpuState::from_bytes(&bytes).unwrap();
+
+            nes.cpu = state.restore(&rom, (NoController, NoController)).unwrap();
+            nes.renderer.resume(scan);
+
+            assert_eq!(run(&mut nes), expected);
+        }
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let rom = Rom::from_prg(&[0xE6, 0x10, 0x4C, 0x00, 0x80], 0x8000);
+        let mut cpu = Cpu::new(&rom, None, (NoController, NoController));
+
+        for _ in 0 .. 100 {
+            cpu.step().unwrap();
+        }
+
+        cpu.memory.add_game_genie(GameGenieCode::parse("SXIOPO").unwrap());
+
+        let state = CpuState::from(&cpu);
+        let mut cursor = std::io::Cursor::new(vec![]);
+
+        state.write_to(&mut cursor).unwrap();
+
+        assert_eq!(cursor.get_ref(), &state.to_bytes().unwrap());
+
+        cursor.set_position(0);
+
+        let restored = CpuState::read_from(&mut cursor).unwrap()
+            .restore(&rom, (NoController, NoController))
+            .unwrap();
+
+        assert_eq!(restored.memory.ram, cpu.memory.ram);
+        assert_eq!(restored.memory.cycles, cpu.memory.cycles);
+        assert_eq!(restored.registers.pc, cpu.registers.pc);
+        assert_eq!(restored.memory.game_genie, cpu.memory.game_genie);
+
+        // An empty stream is not a state.
+        assert!(CpuState::read_from(std::io::Cursor::new(vec![])).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::mapper::Mapper;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// Mapper 2, any write to $8000-$FFFF selects the 16KB bank at $8000. $C000 is fixed to the last bank.
+pub struct UxRom<'a> {
+    rom: &'a Rom,
+    bank: u8,
+    // Boards without a write enable on the ROM see the written value ANDed with the ROM byte there.
+    bus_conflicts: bool,
+}
+
+impl<'a> UxRom<'a> {
+    pub fn new(rom: &'a Rom, bus_conflicts: bool) -> UxRom<'a> {
+        UxRom { rom, bank: 0, bus_conflicts }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let banks = (self.rom.prg_rom.len() / PRG_BANK_SIZE).max(1);
+
+        let bank = if address >= 0xC000 { banks - 1 } else { self.bank as usize % banks };
+
+        bank * PRG_BANK_SIZE + (address as usize % PRG_BANK_SIZE)
+    }
+}
+
+impl<'a> Mapper for UxRom<'a> {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0xFFFF if !self.rom.prg_rom.is_empty() => {
+                let offset = self.prg_offset(address) % self.rom.prg_rom.len();
+
+                Some(self.rom.prg_rom[offset])
+            }
+            _ => None
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8, _: u64) -> bool {
+        if address < 0x8000 {
+            return false
+        }
+
+        self.bank = match self.cpu_read(address) {
+            Some(rom) if self.bus_conflicts => value & rom,
+            _ => value,
+        };
+
+        true
+    }
+
+    fn ppu_read(&mut self, address: u16) -> Option<u8> {
+        self.rom.chr_rom.get(address as usize).copied()
+    }
+
+    fn ppu_write(&mut self, _: u16, _: u8) -> bool {
+        false
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn restore_state(&mut self, state: &[u8]) {
+        if let Some(bank) = state.first() {
+            self.bank = *bank
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four 16KB banks, each filled with its own index.
+    fn banked_rom() -> Rom {
+        let mut rom = Rom::from_prg(&[], 0x8000);
+
+        rom.flags.mapper = 2;
+        rom.prg_rom = (0 .. 4 * PRG_BANK_SIZE).map(|i| (i / PRG_BANK_SIZE) as u8).collect();
+
+        rom
+    }
+
+    #[test]
+    fn switches_lower_bank() {
+        let rom = banked_rom();
+        let mut mapper = UxRom::new(&rom, false);
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(0));
+        assert_eq!(mapper.cpu_read(0xC000), Some(3));
+
+        assert!(mapper.cpu_write(0x8000, 2, 0));
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(2));
+        assert_eq!(mapper.cpu_read(0xC000), Some(3));
+    }
+
+    #[test]
+    fn bus_conflict_ands_with_rom() {
+        let mut rom = banked_rom();
+
+        // The ROM drives $06 at $8010 while the CPU writes $03, the latch sees $02.
+        rom.prg_rom[0x10] = 0x06;
+
+        let mut mapper = UxRom::new(&rom, true);
+
+        mapper.cpu_write(0x8010, 0x03, 0);
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(2));
+
+        let mut mapper = UxRom::new(&rom, false);
+
+        mapper.cpu_write(0x8010, 0x03, 0);
+
+        assert_eq!(mapper.cpu_read(0x8000), Some(3));
+    }
+}